@@ -0,0 +1,74 @@
+//! Latency of a small send and receive for growing buffer sizes.
+//!
+//! Run with `cargo bench --bench send_latency`. Each row shows the time of one
+//! send followed by a receive of a 64 byte message, next to the same round trip
+//! with the whole data buffer zero-filled before the send, as every send used to do.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use windows_shared_memory::{
+    BufferSizes, Client, HeapTransport, Server, SharedDataHeader, Transport,
+};
+
+const MESSAGE: [u8; 64] = [0x5A; 64];
+
+const BUFFER_SIZES: [usize; 5] = [
+    4 * 1024,
+    64 * 1024,
+    1024 * 1024,
+    16 * 1024 * 1024,
+    64 * 1024 * 1024,
+];
+
+/// Runs `f` repeatedly for about `budget` and returns the mean time per call.
+fn measure(budget: Duration, mut f: impl FnMut()) -> Duration {
+    // Warm up caches and page in the buffer
+    for _ in 0..10 {
+        f();
+    }
+
+    let start = Instant::now();
+    let mut iterations = 0u32;
+    while start.elapsed() < budget {
+        f();
+        iterations += 1;
+    }
+    start.elapsed() / iterations
+}
+
+fn main() {
+    let budget = Duration::from_millis(500);
+
+    println!(
+        "{:>12} {:>16} {:>24}",
+        "buffer", "send + recv", "zero-fill + send + recv"
+    );
+    for buffer_size in BUFFER_SIZES {
+        let transport = HeapTransport::new(SharedDataHeader::total_size(buffer_size));
+        let server = Server::with_transport(transport.clone(), buffer_size).unwrap();
+        let client = Client::with_transport(transport.clone()).unwrap();
+
+        // The server-to-client data buffer the sends below write to
+        let offset = SharedDataHeader::offset_s2c(BufferSizes::symmetric(buffer_size), 1, 0);
+        let data =
+            unsafe { std::slice::from_raw_parts_mut(transport.as_ptr().add(offset), buffer_size) };
+
+        let round_trip = measure(budget, || {
+            server.send(black_box(&MESSAGE)).unwrap();
+            black_box(client.try_recv_bytes().unwrap());
+        });
+
+        let zero_filled = measure(budget, || {
+            black_box(&mut *data).fill(0);
+            server.send(black_box(&MESSAGE)).unwrap();
+            black_box(client.try_recv_bytes().unwrap());
+        });
+
+        println!(
+            "{:>9} KB {:>16.2?} {:>24.2?}",
+            buffer_size / 1024,
+            round_trip,
+            zero_filled
+        );
+    }
+}
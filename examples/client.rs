@@ -58,6 +58,10 @@ fn main() {
             eprintln!("[Client {}] Message error occurred: {}", arch, e);
             std::process::exit(1);
         }
+        ReceiveMessage::PeerDied => {
+            eprintln!("[Client {}] The server process died", arch);
+            std::process::exit(1);
+        }
     }
 
     // Send response to server
@@ -73,6 +73,10 @@ fn main() {
             eprintln!("[Server {}] Message error occurred: {}", arch, e);
             std::process::exit(1);
         }
+        ReceiveMessage::PeerDied => {
+            eprintln!("[Server {}] The client process died", arch);
+            std::process::exit(1);
+        }
     }
 
     // Send close signal
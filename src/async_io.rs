@@ -0,0 +1,336 @@
+use crate::{Client, Error, Result, Server, Shared, ShmTransport, Transport};
+use futures_core::Stream;
+use std::future::poll_fn;
+use std::io;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+
+/// How long the waiter thread blocks at a time before checking for shutdown (ms)
+const WAIT_SLICE_MS: u32 = 50;
+
+/// Blocking operations the background threads run on a [`Server`] or [`Client`].
+trait Endpoint {
+    fn recv_bytes(&self, timeout_ms: Option<u32>) -> Result<Option<Vec<u8>>>;
+    fn send_cancellable(&self, data: &[u8], cancelled: &dyn Fn() -> bool) -> Result<()>;
+}
+
+impl<T: Transport> Endpoint for Server<T> {
+    fn recv_bytes(&self, timeout_ms: Option<u32>) -> Result<Option<Vec<u8>>> {
+        Server::recv_bytes(self, timeout_ms)
+    }
+
+    fn send_cancellable(&self, data: &[u8], cancelled: &dyn Fn() -> bool) -> Result<()> {
+        Server::send_cancellable(self, data, cancelled)
+    }
+}
+
+impl<T: Transport> Endpoint for Client<T> {
+    fn recv_bytes(&self, timeout_ms: Option<u32>) -> Result<Option<Vec<u8>>> {
+        Client::recv_bytes(self, timeout_ms)
+    }
+
+    fn send_cancellable(&self, data: &[u8], cancelled: &dyn Fn() -> bool) -> Result<()> {
+        Client::send_cancellable(self, data, cancelled)
+    }
+}
+
+/// State shared between async receivers and the waiter thread.
+#[derive(Default)]
+struct Inbox {
+    /// A receiver is waiting for the next message
+    wanted: bool,
+    /// Message read by the waiter thread that no receiver has taken yet
+    ready: Option<Result<Vec<u8>>>,
+    wakers: Vec<Waker>,
+    closed: bool,
+}
+
+/// Completion of one queued send.
+#[derive(Default)]
+struct Outcome {
+    result: Option<Result<()>>,
+    waker: Option<Waker>,
+}
+
+type SendJob = (Vec<u8>, Arc<Mutex<Outcome>>);
+
+/// Runs the blocking receive and send calls of one endpoint on background threads.
+struct Worker {
+    inbox: Arc<(Mutex<Inbox>, Condvar)>,
+    jobs: Option<mpsc::Sender<SendJob>>,
+    closed: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Worker {
+    fn spawn<E: Endpoint + Send + 'static>(endpoint: E) -> Self
+    where
+        Shared<E>: Sync,
+    {
+        // Only the waiter thread receives and only the sender thread sends
+        let endpoint = Arc::new(Shared(endpoint));
+        let inbox = Arc::new((Mutex::new(Inbox::default()), Condvar::new()));
+        let closed = Arc::new(AtomicBool::new(false));
+        let (jobs, queue) = mpsc::channel::<SendJob>();
+
+        let waiter = {
+            let endpoint = endpoint.clone();
+            let inbox = inbox.clone();
+            let closed = closed.clone();
+            thread::spawn(move || Self::wait_loop(&endpoint.0, &inbox, &closed))
+        };
+        let sender = {
+            let closed = closed.clone();
+            thread::spawn(move || {
+                for (data, outcome) in queue {
+                    let result = Self::send_blocking(&endpoint.0, &data, &closed);
+                    let mut outcome = outcome.lock().unwrap();
+                    outcome.result = Some(result);
+                    if let Some(waker) = outcome.waker.take() {
+                        waker.wake();
+                    }
+                }
+            })
+        };
+
+        Self {
+            inbox,
+            jobs: Some(jobs),
+            closed,
+            threads: vec![waiter, sender],
+        }
+    }
+
+    /// Reads one message each time a receiver asks for one.
+    fn wait_loop<E: Endpoint>(endpoint: &E, inbox: &(Mutex<Inbox>, Condvar), closed: &AtomicBool) {
+        let (state, wanted) = inbox;
+        loop {
+            {
+                let mut state = state.lock().unwrap();
+                while !state.wanted && !state.closed {
+                    state = wanted.wait(state).unwrap();
+                }
+                if state.closed {
+                    return;
+                }
+            }
+
+            // Wait in slices so the thread notices shutdown
+            let result = loop {
+                if closed.load(Ordering::Acquire) {
+                    return;
+                }
+                match endpoint.recv_bytes(Some(WAIT_SLICE_MS)) {
+                    Ok(None) => continue,
+                    Ok(Some(data)) => break Ok(data),
+                    Err(e) => break Err(e),
+                }
+            };
+
+            let mut state = state.lock().unwrap();
+            state.ready = Some(result);
+            state.wanted = false;
+            for waker in state.wakers.drain(..) {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Sends `data`, waiting until the peer has room for it or the worker is dropped.
+    fn send_blocking<E: Endpoint>(endpoint: &E, data: &[u8], closed: &AtomicBool) -> Result<()> {
+        // Every fragment of a message checks for shutdown while it waits, so dropping the
+        // worker never waits for a peer that stopped reading
+        endpoint.send_cancellable(data, &|| closed.load(Ordering::Acquire))
+    }
+
+    fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Result<Vec<u8>>> {
+        let (state, wanted) = &*self.inbox;
+        let mut state = state.lock().unwrap();
+        if let Some(result) = state.ready.take() {
+            return Poll::Ready(result);
+        }
+        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        state.wanted = true;
+        wanted.notify_one();
+        Poll::Pending
+    }
+
+    fn poll_next(&self, cx: &mut Context<'_>) -> Poll<Option<Result<Vec<u8>>>> {
+        match self.poll_recv(cx) {
+            Poll::Ready(Err(Error::PeerClosed)) => Poll::Ready(None),
+            Poll::Ready(result) => Poll::Ready(Some(result)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    async fn recv(&self) -> Result<Vec<u8>> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    async fn send(&self, data: &[u8]) -> Result<()> {
+        let outcome = Arc::new(Mutex::new(Outcome::default()));
+        let job = (data.to_vec(), outcome.clone());
+        if self.jobs.as_ref().is_none_or(|jobs| jobs.send(job).is_err()) {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "sender thread exited").into());
+        }
+
+        poll_fn(|cx| {
+            let mut outcome = outcome.lock().unwrap();
+            match outcome.result.take() {
+                Some(result) => Poll::Ready(result),
+                None => {
+                    outcome.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Release);
+        {
+            let (state, wanted) = &*self.inbox;
+            state.lock().unwrap().closed = true;
+            wanted.notify_all();
+        }
+        // Ends the sender thread once queued sends are done
+        self.jobs.take();
+
+        for thread in self.threads.drain(..) {
+            if thread.join().is_err() {
+                eprintln!("Failed to join shared memory worker thread");
+            }
+        }
+    }
+}
+
+/// Async wrapper around a [`Server`].
+///
+/// Receives and sends run on two background threads, so awaiting a message
+/// never blocks the executor. Works with any async runtime.
+///
+/// Like [`Server::send`] and [`Server::receive`], it talks to the client in the first slot.
+///
+/// # Example
+///
+/// ```no_run
+/// use windows_shared_memory::{AsyncServer, Server};
+///
+/// # async fn run() -> windows_shared_memory::Result<()> {
+/// let server = AsyncServer::new(Server::new(None)?);
+/// let request = server.recv().await?;
+/// server.send(&request).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncServer<T: Transport = ShmTransport> {
+    worker: Worker,
+    _transport: PhantomData<fn() -> T>,
+}
+
+impl<T: Transport + Send + Sync + 'static> AsyncServer<T> {
+    /// Moves the server onto background threads.
+    pub fn new(server: Server<T>) -> Self {
+        Self {
+            worker: Worker::spawn(server),
+            _transport: PhantomData,
+        }
+    }
+}
+
+impl<T: Transport> AsyncServer<T> {
+    /// Receives the next message from the client.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::PeerClosed`] once the client has sent a close signal.
+    pub async fn recv(&self) -> Result<Vec<u8>> {
+        self.worker.recv().await
+    }
+
+    /// Sends data, waiting until the client has consumed the previous message.
+    ///
+    /// The message is queued as soon as this is first polled and is still sent
+    /// if the future is dropped.
+    pub async fn send(&self, data: &[u8]) -> Result<()> {
+        self.worker.send(data).await
+    }
+}
+
+impl<T: Transport> Stream for AsyncServer<T> {
+    type Item = Result<Vec<u8>>;
+
+    /// Yields messages until the client sends a close signal.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.worker.poll_next(cx)
+    }
+}
+
+/// Async wrapper around a [`Client`].
+///
+/// Receives and sends run on two background threads, so awaiting a message
+/// never blocks the executor. Works with any async runtime.
+///
+/// # Example
+///
+/// ```no_run
+/// use windows_shared_memory::{AsyncClient, Client};
+///
+/// # async fn run() -> windows_shared_memory::Result<()> {
+/// let client = AsyncClient::new(Client::new(None)?);
+/// client.send(b"ping").await?;
+/// let reply = client.recv().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncClient<T: Transport = ShmTransport> {
+    worker: Worker,
+    _transport: PhantomData<fn() -> T>,
+}
+
+impl<T: Transport + Send + Sync + 'static> AsyncClient<T> {
+    /// Moves the client onto background threads.
+    pub fn new(client: Client<T>) -> Self {
+        Self {
+            worker: Worker::spawn(client),
+            _transport: PhantomData,
+        }
+    }
+}
+
+impl<T: Transport> AsyncClient<T> {
+    /// Receives the next message from the server.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::PeerClosed`] once the server has sent a close signal.
+    pub async fn recv(&self) -> Result<Vec<u8>> {
+        self.worker.recv().await
+    }
+
+    /// Sends data, waiting until the server has consumed the previous message.
+    ///
+    /// The message is queued as soon as this is first polled and is still sent
+    /// if the future is dropped.
+    pub async fn send(&self, data: &[u8]) -> Result<()> {
+        self.worker.send(data).await
+    }
+}
+
+impl<T: Transport> Stream for AsyncClient<T> {
+    type Item = Result<Vec<u8>>;
+
+    /// Yields messages until the server sends a close signal.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.worker.poll_next(cx)
+    }
+}
@@ -0,0 +1,413 @@
+use crate::server::layout_size;
+use crate::transport::CreateOptions;
+use crate::{
+    BufferSizes, ChannelMode, Client, CreationPolicy, EventNames, Result, Security, Server, ShmTransport,
+    DEFAULT_BUFFER_SIZE, DEFAULT_CLOSE_TIMEOUT_MS, DEFAULT_MAPPING_NAME, FEATURE_FRAGMENTATION,
+    FEATURE_SCRUB_AFTER_READ,
+};
+
+/// Kernel object namespace of the mapping and event names.
+///
+/// On Windows, `Local` names are visible within one session and `Global` names
+/// across sessions, which a server needs `SeCreateGlobalPrivilege` for. Linux
+/// has a single namespace and ignores the prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Namespace {
+    /// `Local\` prefix, visible within the current session
+    Local,
+    /// `Global\` prefix, visible across sessions
+    Global,
+}
+
+impl Namespace {
+    /// Returns `name` with its namespace prefix replaced by this one.
+    fn apply(self, name: &str) -> String {
+        let bare = name
+            .strip_prefix("Local\\")
+            .or_else(|| name.strip_prefix("Global\\"))
+            .unwrap_or(name);
+        match self {
+            Namespace::Local => format!("Local\\{}", bare),
+            Namespace::Global => format!("Global\\{}", bare),
+        }
+    }
+}
+
+/// Options shared by [`ServerBuilder`] and [`ClientBuilder`].
+#[derive(Debug, Clone)]
+struct Common {
+    mapping_name: String,
+    namespace: Option<Namespace>,
+    events: Option<EventNames>,
+    fragmentation: bool,
+    close_on_drop: bool,
+    close_timeout_ms: Option<u32>,
+    receive_timeout_ms: Option<u32>,
+    send_timeout_ms: Option<u32>,
+    heartbeat_ms: Option<u32>,
+}
+
+impl Common {
+    fn new() -> Self {
+        Self {
+            mapping_name: DEFAULT_MAPPING_NAME.to_string(),
+            namespace: None,
+            events: None,
+            fragmentation: false,
+            close_on_drop: false,
+            close_timeout_ms: Some(DEFAULT_CLOSE_TIMEOUT_MS),
+            receive_timeout_ms: None,
+            send_timeout_ms: None,
+            heartbeat_ms: None,
+        }
+    }
+
+    fn mapping_name(&self) -> String {
+        match self.namespace {
+            Some(namespace) => namespace.apply(&self.mapping_name),
+            None => self.mapping_name.clone(),
+        }
+    }
+
+    fn event_names(&self, mapping_name: &str) -> EventNames {
+        match &self.events {
+            Some(events) => events.clone(),
+            None => EventNames::for_mapping(mapping_name),
+        }
+    }
+}
+
+/// Builder for a [`Server`] on named shared memory.
+///
+/// Every option has a default, so only the ones that differ need to be set.
+///
+/// # Example
+///
+/// ```no_run
+/// use windows_shared_memory::{CreationPolicy, Namespace, Server};
+///
+/// let server = Server::builder()
+///     .name("MyShm")
+///     .namespace(Namespace::Local)
+///     .buffer_size(64 * 1024)
+///     .creation_policy(CreationPolicy::CreateNew)
+///     .fragmentation(true)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServerBuilder {
+    common: Common,
+    buffers: BufferSizes,
+    mode: ChannelMode,
+    max_clients: usize,
+    scrub_after_read: bool,
+    options: CreateOptions,
+}
+
+impl ServerBuilder {
+    /// Creates a builder for a mailbox server on [`DEFAULT_MAPPING_NAME`] with
+    /// the default buffer size and one client slot.
+    pub fn new() -> Self {
+        Self {
+            common: Common::new(),
+            buffers: BufferSizes::symmetric(DEFAULT_BUFFER_SIZE),
+            mode: ChannelMode::Mailbox,
+            max_clients: 1,
+            scrub_after_read: false,
+            options: CreateOptions::default(),
+        }
+    }
+
+    /// Sets the name of the shared memory mapping.
+    pub fn name(mut self, mapping_name: &str) -> Self {
+        self.common.mapping_name = mapping_name.to_string();
+        self
+    }
+
+    /// Puts the mapping and event names into `namespace`, replacing any prefix in the name.
+    pub fn namespace(mut self, namespace: Namespace) -> Self {
+        self.common.namespace = Some(namespace);
+        self
+    }
+
+    /// Sets the size of the data buffers in both directions in bytes.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffers = BufferSizes::symmetric(buffer_size);
+        self
+    }
+
+    /// Sets the size of the buffer the server sends through in bytes.
+    pub fn server_to_client_buffer(mut self, buffer_size: usize) -> Self {
+        self.buffers.server_to_client = buffer_size;
+        self
+    }
+
+    /// Sets the size of the buffer clients send through in bytes.
+    pub fn client_to_server_buffer(mut self, buffer_size: usize) -> Self {
+        self.buffers.client_to_server = buffer_size;
+        self
+    }
+
+    /// Sets how messages are stored in the data buffers.
+    pub fn mode(mut self, mode: ChannelMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the number of client slots.
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients;
+        self
+    }
+
+    /// Sets explicit event names instead of deriving them from the mapping name.
+    pub fn event_names(mut self, events: EventNames) -> Self {
+        self.common.events = Some(events);
+        self
+    }
+
+    /// Sets what happens if the mapping name is already in use.
+    ///
+    /// Defaults to [`CreationPolicy::OpenOrCreate`].
+    pub fn creation_policy(mut self, policy: CreationPolicy) -> Self {
+        self.options.policy = policy;
+        self
+    }
+
+    /// Sets the security descriptor of the mapping and events, in SDDL form.
+    ///
+    /// For example `"D:(A;;GA;;;WD)"` lets every user connect. Defaults to the
+    /// creator's default descriptor.
+    #[cfg(windows)]
+    pub fn security_descriptor(mut self, sddl: &str) -> Self {
+        self.options.security = Security {
+            descriptor: Some(sddl.to_string()),
+        };
+        self
+    }
+
+    /// Sets the permission bits of the shared memory objects.
+    ///
+    /// For example `0o666` lets every user connect. Defaults to `0o600`.
+    #[cfg(target_os = "linux")]
+    pub fn permissions(mut self, mode: u32) -> Self {
+        self.options.security = Security {
+            permissions: Some(mode),
+        };
+        self
+    }
+
+    /// Enables fragmentation, see [`Server::set_fragmentation`].
+    pub fn fragmentation(mut self, enabled: bool) -> Self {
+        self.common.fragmentation = enabled;
+        self
+    }
+
+    /// Makes both sides zero every message in the shared memory once it has been read.
+    ///
+    /// Senders only ever write the bytes of the message itself, so without this
+    /// a received message stays readable in the mapping until it is overwritten.
+    /// Clients pick the setting up from the header.
+    pub fn scrub_after_read(mut self, enabled: bool) -> Self {
+        self.scrub_after_read = enabled;
+        self
+    }
+
+    /// Closes the connection when the server is dropped, see [`Server::set_close_on_drop`].
+    pub fn close_on_drop(mut self, enabled: bool) -> Self {
+        self.common.close_on_drop = enabled;
+        self
+    }
+
+    /// Sets how long a close on drop waits, see [`Server::set_close_timeout`].
+    pub fn close_timeout(mut self, timeout_ms: Option<u32>) -> Self {
+        self.common.close_timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Sets the timeout of receives called with None, see [`Server::set_receive_timeout`].
+    pub fn receive_timeout(mut self, timeout_ms: Option<u32>) -> Self {
+        self.common.receive_timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Sets the timeout of waiting sends called with None, see [`Server::set_send_timeout`].
+    pub fn send_timeout(mut self, timeout_ms: Option<u32>) -> Self {
+        self.common.send_timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Sends heartbeats every `interval_ms`, see [`Server::start_heartbeat`].
+    pub fn heartbeat(mut self, interval_ms: u32) -> Self {
+        self.common.heartbeat_ms = Some(interval_ms);
+        self
+    }
+
+    /// Creates the shared memory and the server.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::AlreadyExists`](crate::Error::AlreadyExists) if the name
+    /// is in use and the creation policy does not allow reusing it.
+    pub fn build(self) -> Result<Server> {
+        let mapping_name = self.common.mapping_name();
+        let events = self.common.event_names(&mapping_name);
+        let total_size = layout_size(self.buffers, self.mode, self.max_clients)?;
+        let transport =
+            ShmTransport::create_with_options(&mapping_name, total_size, &events, self.max_clients, &self.options)?;
+
+        let mut features = FEATURE_FRAGMENTATION;
+        if self.scrub_after_read {
+            features |= FEATURE_SCRUB_AFTER_READ;
+        }
+        let mut server =
+            Server::with_transport_features(transport, self.buffers, self.mode, self.max_clients, features)?;
+        server.set_fragmentation(self.common.fragmentation);
+        server.set_close_on_drop(self.common.close_on_drop);
+        server.set_close_timeout(self.common.close_timeout_ms);
+        server.set_receive_timeout(self.common.receive_timeout_ms);
+        server.set_send_timeout(self.common.send_timeout_ms);
+        if let Some(interval_ms) = self.common.heartbeat_ms {
+            server.start_heartbeat(interval_ms);
+        }
+        Ok(server)
+    }
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builder for a [`Client`] of named shared memory.
+///
+/// Every option has a default, so only the ones that differ need to be set.
+///
+/// # Example
+///
+/// ```no_run
+/// use windows_shared_memory::{ChannelMode, Client};
+///
+/// let client = Client::builder()
+///     .name("Local\\MyShm")
+///     .mode(ChannelMode::Ring)
+///     .connect_timeout(Some(5000))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    common: Common,
+    mode: Option<ChannelMode>,
+    // None fails right away if there is no server
+    connect_timeout_ms: Option<Option<u32>>,
+}
+
+impl ClientBuilder {
+    /// Creates a builder for a client of [`DEFAULT_MAPPING_NAME`] that fails
+    /// right away if the server is not running.
+    pub fn new() -> Self {
+        Self {
+            common: Common::new(),
+            mode: None,
+            connect_timeout_ms: None,
+        }
+    }
+
+    /// Sets the name of the shared memory mapping.
+    pub fn name(mut self, mapping_name: &str) -> Self {
+        self.common.mapping_name = mapping_name.to_string();
+        self
+    }
+
+    /// Puts the mapping and event names into `namespace`, replacing any prefix in the name.
+    pub fn namespace(mut self, namespace: Namespace) -> Self {
+        self.common.namespace = Some(namespace);
+        self
+    }
+
+    /// Sets explicit event names instead of deriving them from the mapping name.
+    pub fn event_names(mut self, events: EventNames) -> Self {
+        self.common.events = Some(events);
+        self
+    }
+
+    /// Fails to connect unless the server uses `mode`.
+    pub fn mode(mut self, mode: ChannelMode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Waits for the server to appear, see [`Client::connect`].
+    ///
+    /// If None, waits indefinitely.
+    pub fn connect_timeout(mut self, timeout_ms: Option<u32>) -> Self {
+        self.connect_timeout_ms = Some(timeout_ms);
+        self
+    }
+
+    /// Enables fragmentation, see [`Client::set_fragmentation`].
+    pub fn fragmentation(mut self, enabled: bool) -> Self {
+        self.common.fragmentation = enabled;
+        self
+    }
+
+    /// Closes the connection when the client is dropped, see [`Client::set_close_on_drop`].
+    pub fn close_on_drop(mut self, enabled: bool) -> Self {
+        self.common.close_on_drop = enabled;
+        self
+    }
+
+    /// Sets how long a close on drop waits, see [`Client::set_close_timeout`].
+    pub fn close_timeout(mut self, timeout_ms: Option<u32>) -> Self {
+        self.common.close_timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Sets the timeout of receives called with None, see [`Client::set_receive_timeout`].
+    pub fn receive_timeout(mut self, timeout_ms: Option<u32>) -> Self {
+        self.common.receive_timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Sets the timeout of waiting sends called with None, see [`Client::set_send_timeout`].
+    pub fn send_timeout(mut self, timeout_ms: Option<u32>) -> Self {
+        self.common.send_timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Sends heartbeats every `interval_ms`, see [`Client::start_heartbeat`].
+    pub fn heartbeat(mut self, interval_ms: u32) -> Self {
+        self.common.heartbeat_ms = Some(interval_ms);
+        self
+    }
+
+    /// Connects to the server.
+    pub fn build(self) -> Result<Client> {
+        let mapping_name = self.common.mapping_name();
+        let events = self.common.event_names(&mapping_name);
+        // The mode is checked before a slot is claimed, so a mismatch leaves the slot free
+        let mut client = match self.connect_timeout_ms {
+            Some(timeout_ms) => Client::connect_with_event_names(&mapping_name, &events, self.mode, timeout_ms)?,
+            None => Client::open(&mapping_name, &events, self.mode)?,
+        };
+
+        client.set_fragmentation(self.common.fragmentation);
+        client.set_close_on_drop(self.common.close_on_drop);
+        client.set_close_timeout(self.common.close_timeout_ms);
+        client.set_receive_timeout(self.common.receive_timeout_ms);
+        client.set_send_timeout(self.common.send_timeout_ms);
+        if let Some(interval_ms) = self.common.heartbeat_ms {
+            client.start_heartbeat(interval_ms);
+        }
+        Ok(client)
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,22 +1,52 @@
 use crate::{
-    open_event, read_from_shared_memory, str_to_pcwstr, write_to_shared_memory, ReceiveBytes,
-    ReceiveMessage, SharedDataHeader,
+    max_message_len, read_bytes_from_shared_memory, read_from_shared_memory,
+    recv_bytes_from_shared_memory, recv_bytes_from_shared_memory_until, recv_from_shared_memory,
+    recv_from_shared_memory_until, try_write_to_shared_memory,
+    write_fragmented_to_shared_memory, write_to_shared_memory, write_to_shared_memory_timeout,
+    write_vectored_to_shared_memory, BufferSizes, ChannelMode, ClientBuilder, ClientId, ClientSlot, Direction, Error, EventNames,
+    ReadGuard, ReceiveBytes, ReceiveMessage, Result, SharedDataHeader, ShmTransport, Transport,
+    WriteGuard, DEFAULT_CLOSE_TIMEOUT_MS, DEFAULT_MAPPING_NAME,
 };
-use windows::core::Result;
-use windows::Win32::{
-    Foundation::{CloseHandle, HANDLE},
-    System::Memory::*,
+use crate::liveness::{self, Heartbeat};
+use crate::shared_memory::{
+    claim_slot, client_slot, deadline, gather, release_slot, remaining_ms, retry_until, ring,
 };
+use std::cell::Cell;
+use std::io::IoSlice;
+use std::marker::PhantomData;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often [`Client::connect`] checks whether the server is ready (ms)
+const CONNECT_POLL_MS: u32 = 10;
 
 /// Client instance for shared memory communication.
 ///
-/// The client connects to an existing shared memory created by the server.
-pub struct Client {
-    header_address: *mut SharedDataHeader,
-    buffer_size: usize,
-    h_map_file: HANDLE,
-    h_event_s2c: HANDLE,
-    h_event_c2s: HANDLE,
+/// The client connects to an existing shared memory created by the server
+/// and claims one of its client slots until it is dropped.
+///
+/// By default the client runs over named OS shared memory ([`ShmTransport`]),
+/// but any [`Transport`] can be used with [`Client::with_transport`].
+///
+/// A client can be moved to another thread, but not shared between threads;
+/// wrap it in a [`SyncClient`](crate::SyncClient) for that.
+pub struct Client<T: Transport = ShmTransport> {
+    transport: T,
+    buffers: BufferSizes,
+    mode: ChannelMode,
+    slot: usize,
+    // Generation of the server this client connected to
+    generation: u64,
+    fragmentation: bool,
+    close_on_drop: bool,
+    close_timeout_ms: Option<u32>,
+    // Used by calls that pass a timeout of None
+    receive_timeout_ms: Option<u32>,
+    send_timeout_ms: Option<u32>,
+    heartbeat: Option<Heartbeat>,
+    // Concurrent sends or receives on one instance would race on the buffers, see SyncClient
+    _not_sync: PhantomData<Cell<()>>,
 }
 
 impl Client {
@@ -38,80 +68,359 @@ impl Client {
     /// let client = Client::new(None).unwrap();
     /// ```
     pub fn new(mapping_path: Option<&str>) -> Result<Self> {
-        let mapping_name = mapping_path.unwrap_or("Local\\MySharedMemory");
-        let mapping_name_pcwstr = str_to_pcwstr(mapping_name);
-
-        // Open file mapping object
-        let h_map_file =
-            unsafe { OpenFileMappingW(FILE_MAP_ALL_ACCESS.0, false, &mapping_name_pcwstr)? };
-
-        // First, map only the header to read buffer size
-        let header_size = std::mem::size_of::<SharedDataHeader>();
-        let p_buf_header = unsafe {
-            MapViewOfFile(
-                h_map_file,
-                FILE_MAP_ALL_ACCESS,
-                0,
-                0,
-                header_size,
-            )
-        };
+        let mapping_name = mapping_path.unwrap_or(DEFAULT_MAPPING_NAME);
+        Self::with_event_names(Some(mapping_name), &EventNames::for_mapping(mapping_name))
+    }
+
+    /// Returns a builder for configuring every option of a client.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use windows_shared_memory::Client;
+    ///
+    /// let client = Client::builder().name("Local\\MyShm").connect_timeout(Some(5000)).build().unwrap();
+    /// ```
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
+    /// Connects to a server, waiting for it to be created if it is not running yet.
+    ///
+    /// Unlike [`Client::new`], a missing mapping or a server that is still
+    /// setting up is not an error; the connection is retried every few
+    /// milliseconds until the timeout elapses. Any other failure is returned right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `mapping_path` - Optional custom path for the shared memory mapping.
+    ///   If None, uses "Local\\MySharedMemory" as default.
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, waits indefinitely.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::Timeout`](crate::Error::Timeout) if no server was ready in time.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use windows_shared_memory::Client;
+    ///
+    /// // Started at the same time as the server
+    /// let client = Client::connect(None, Some(5000)).unwrap();
+    /// ```
+    pub fn connect(mapping_path: Option<&str>, timeout_ms: Option<u32>) -> Result<Self> {
+        let mapping_name = mapping_path.unwrap_or(DEFAULT_MAPPING_NAME);
+        Self::connect_with_event_names(mapping_name, &EventNames::for_mapping(mapping_name), None, timeout_ms)
+    }
+
+    /// Like [`Client::connect`], but with explicit event names and, if given, failing
+    /// without claiming a slot unless the server uses `mode`.
+    pub(crate) fn connect_with_event_names(
+        mapping_name: &str,
+        events: &EventNames,
+        mode: Option<ChannelMode>,
+        timeout_ms: Option<u32>,
+    ) -> Result<Self> {
+        let deadline = deadline(timeout_ms);
+        loop {
+            if ShmTransport::server_ready(mapping_name)? {
+                match Self::open(mapping_name, events, mode) {
+                    // The server went away again in between
+                    Err(_) if !ShmTransport::server_ready(mapping_name)? => {}
+                    result => return result,
+                }
+            }
+
+            let sleep_ms = match deadline.map(remaining_ms) {
+                Some(0) => return Err(Error::Timeout),
+                Some(remaining) => remaining.min(CONNECT_POLL_MS),
+                None => CONNECT_POLL_MS,
+            };
+            thread::sleep(Duration::from_millis(sleep_ms as u64));
+        }
+    }
+
+    /// Creates a new client instance that connects using explicit event names.
+    ///
+    /// The names must match the ones the server was created with.
+    ///
+    /// # Arguments
+    ///
+    /// * `mapping_path` - Optional custom path for the shared memory mapping.
+    ///   If None, uses "Local\\MySharedMemory" as default.
+    /// * `events` - Names of the server-to-client and client-to-server events.
+    pub fn with_event_names(mapping_path: Option<&str>, events: &EventNames) -> Result<Self> {
+        Self::open(mapping_path.unwrap_or(DEFAULT_MAPPING_NAME), events, None)
+    }
 
-        if p_buf_header.Value.is_null() {
-            unsafe { CloseHandle(h_map_file)? };
-            return Err(windows::core::Error::from_thread());
+    /// Opens the mapping and connects, failing without claiming a slot unless the
+    /// server uses `mode` (if given).
+    pub(crate) fn open(mapping_name: &str, events: &EventNames, mode: Option<ChannelMode>) -> Result<Self> {
+        Self::with_transport_in_mode(ShmTransport::open_with_events(mapping_name, events)?, mode)
+    }
+
+    /// Creates a new client instance for a server in ring buffer mode.
+    ///
+    /// [`Client::new`] also connects to ring buffer servers; this constructor
+    /// additionally fails if the server is not in ring buffer mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `mapping_path` - Optional custom path for the shared memory mapping.
+    ///   If None, uses "Local\\MySharedMemory" as default.
+    pub fn new_ring(mapping_path: Option<&str>) -> Result<Self> {
+        let mapping_name = mapping_path.unwrap_or(DEFAULT_MAPPING_NAME);
+        Self::open(mapping_name, &EventNames::for_mapping(mapping_name), Some(ChannelMode::Ring))
+    }
+}
+
+impl<T: Transport> Client<T> {
+    /// Creates a client over a transport whose header was initialized by a server.
+    pub fn with_transport(transport: T) -> Result<Self> {
+        Self::with_transport_in_mode(transport, None)
+    }
+
+    /// Like [`Client::with_transport`], but fails before claiming a slot unless the
+    /// server uses `expected` (if given).
+    pub(crate) fn with_transport_in_mode(transport: T, expected: Option<ChannelMode>) -> Result<Self> {
+        let too_small = || Error::HeaderMismatch("transport region is smaller than the shared memory layout".into());
+
+        if transport.size() < std::mem::size_of::<SharedDataHeader>() {
+            return Err(too_small());
         }
 
-        // Read buffer size from header
-        let buffer_size = unsafe { (*(p_buf_header.Value as *const SharedDataHeader)).buffer_size as usize };
+        // Validate the header before trusting any size in it
+        let header = transport.as_ptr() as *const SharedDataHeader;
+        unsafe { (*header).validate()? };
 
-        // Unmap header-only view
-        unsafe {
-            UnmapViewOfFile(MEMORY_MAPPED_VIEW_ADDRESS {
-                Value: p_buf_header.Value,
-            })?;
-        }
-
-        // Now map the full shared memory
-        let total_size = SharedDataHeader::total_size(buffer_size);
-        let p_buf = unsafe {
-            MapViewOfFile(
-                h_map_file,
-                FILE_MAP_ALL_ACCESS,
-                0,
-                0,
-                total_size,
-            )
-        };
+        // Read buffer sizes and mode from header
+        let buffers = unsafe { (*header).buffer_sizes() };
+        let mode = ChannelMode::from_u32(unsafe { (*header).mode })
+            .ok_or_else(|| Error::HeaderMismatch("unknown channel mode in header".into()))?;
 
-        if p_buf.Value.is_null() {
-            unsafe { CloseHandle(h_map_file)? };
-            return Err(windows::core::Error::from_thread());
+        let max_clients = unsafe { (*header).max_clients as usize };
+        if transport.size() < SharedDataHeader::total_size_with_buffers(buffers, max_clients) {
+            return Err(too_small());
+        }
+
+        if let Some(expected) = expected
+            && mode != expected
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("server is in {:?} mode, not {:?}", mode, expected),
+            )
+            .into());
         }
 
-        // Open event objects
-        let h_event_s2c = open_event("Local\\MyEventS2C")?;
-        let h_event_c2s = open_event("Local\\MyEventC2S")?;
+        let slot = unsafe { claim_slot(header)? };
+        let generation = unsafe { (*header).generation.load(Ordering::Acquire) };
 
         Ok(Self {
-            header_address: p_buf.Value as *mut SharedDataHeader,
-            buffer_size,
-            h_map_file,
-            h_event_s2c,
-            h_event_c2s,
+            transport,
+            buffers,
+            mode,
+            slot,
+            generation,
+            fragmentation: false,
+            close_on_drop: false,
+            close_timeout_ms: Some(DEFAULT_CLOSE_TIMEOUT_MS),
+            receive_timeout_ms: None,
+            send_timeout_ms: None,
+            heartbeat: None,
+            _not_sync: PhantomData,
         })
     }
 
-    /// Returns the buffer size for this client (set by the server).
+    /// Returns the size of the buffer this client sends through (client to server,
+    /// set by the server).
     pub fn buffer_size(&self) -> usize {
-        self.buffer_size
+        self.buffers.client_to_server
+    }
+
+    /// Returns the buffer sizes of both directions (set by the server).
+    pub fn buffer_sizes(&self) -> BufferSizes {
+        self.buffers
+    }
+
+    /// Returns the largest message that can be sent without fragmentation.
+    pub fn max_message_len(&self) -> usize {
+        max_message_len(self.mode, self.buffers.client_to_server)
+    }
+
+    /// Enables or disables fragmentation of messages larger than [`max_message_len`](Self::max_message_len).
+    ///
+    /// When enabled, `send` and `send_timeout` split large messages across
+    /// multiple buffer fills, waiting for the server to read each one, and
+    /// `receive_bytes` on the other side reassembles them transparently. When
+    /// disabled (the default), large messages fail with
+    /// [`Error::MessageTooLarge`](crate::Error::MessageTooLarge). `try_send` never fragments.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::Client;
+    /// # let mut client = Client::new(None).unwrap();
+    /// client.set_fragmentation(true);
+    /// client.send(&vec![0u8; 4 * 1024 * 1024]).unwrap();
+    /// ```
+    pub fn set_fragmentation(&mut self, enabled: bool) {
+        self.fragmentation = enabled;
+    }
+
+    /// Returns true if fragmentation of large messages is enabled.
+    pub fn fragmentation(&self) -> bool {
+        self.fragmentation
+    }
+
+    /// Returns the client slot this client claimed on the server.
+    pub fn id(&self) -> ClientId {
+        ClientId(self.slot)
+    }
+
+    /// Returns the channel mode for this client (set by the server).
+    pub fn mode(&self) -> ChannelMode {
+        self.mode
+    }
+
+    /// Returns `timeout_ms`, or the default receive timeout if it is None.
+    fn receive_timeout_or_default(&self, timeout_ms: Option<u32>) -> Option<u32> {
+        timeout_ms.or(self.receive_timeout_ms)
+    }
+
+    /// Returns `timeout_ms`, or the default send timeout if it is None.
+    fn send_timeout_or_default(&self, timeout_ms: Option<u32>) -> Option<u32> {
+        timeout_ms.or(self.send_timeout_ms)
+    }
+
+    fn client_slot(&self) -> &ClientSlot {
+        unsafe { client_slot(self.transport.as_ptr() as *const SharedDataHeader, self.slot) }
+    }
+
+    /// Sends a close signal to the server.
+    ///
+    /// The server reports [`ReceiveMessage::Exit`] for this client on its next receive
+    /// and acknowledges the close. Use [`close`](Self::close) to wait for that.
+    pub fn send_close(&self) -> Result<()> {
+        self.client_slot().flag_client.store(3, Ordering::Release);
+        self.transport.signal(Direction::ClientToServer, self.slot)
+    }
+
+    /// Closes the connection, waiting until the server has seen the close.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, waits indefinitely.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::Timeout`](crate::Error::Timeout) if the server has not
+    /// acknowledged the close in time.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::Client;
+    /// # let client = Client::new(None).unwrap();
+    /// client.send(b"bye").unwrap();
+    /// client.close(Some(1000)).unwrap();
+    /// ```
+    pub fn close(&self, timeout_ms: Option<u32>) -> Result<()> {
+        self.send_close()?;
+        let flag = &self.client_slot().flag_client;
+        retry_until(deadline(timeout_ms), || match flag.load(Ordering::Acquire) {
+            3 => Err(Error::WouldBlock),
+            _ => Ok(()),
+        })
+    }
+
+    /// Makes dropping the client [`close`](Self::close) the connection first.
+    ///
+    /// The drop waits up to the [close timeout](Self::set_close_timeout)
+    /// for the server to acknowledge. Disabled by default.
+    pub fn set_close_on_drop(&mut self, enabled: bool) {
+        self.close_on_drop = enabled;
+    }
+
+    /// Sets how long a close on drop waits for the server to acknowledge.
+    ///
+    /// If None, waits indefinitely. Defaults to [`DEFAULT_CLOSE_TIMEOUT_MS`](crate::DEFAULT_CLOSE_TIMEOUT_MS).
+    pub fn set_close_timeout(&mut self, timeout_ms: Option<u32>) {
+        self.close_timeout_ms = timeout_ms;
+    }
+
+    /// Sets the timeout of receives that are called with a timeout of None.
+    ///
+    /// If None, such receives wait indefinitely. Defaults to None.
+    pub fn set_receive_timeout(&mut self, timeout_ms: Option<u32>) {
+        self.receive_timeout_ms = timeout_ms;
+    }
+
+    /// Sets the timeout of [`send_timeout`](Self::send_timeout) and fragmented sends
+    /// that are called with a timeout of None.
+    ///
+    /// If None, such sends wait indefinitely. Defaults to None.
+    pub fn set_send_timeout(&mut self, timeout_ms: Option<u32>) {
+        self.send_timeout_ms = timeout_ms;
+    }
+
+    /// Starts a background thread that refreshes the client's heartbeat every `interval_ms`.
+    ///
+    /// The server treats the client as dead if it misses three heartbeats in a row,
+    /// which also catches a client that hangs without exiting. Without a heartbeat
+    /// the server only notices when the client process exits.
+    pub fn start_heartbeat(&mut self, interval_ms: u32) {
+        self.heartbeat = None;
+        let field = &self.client_slot().client_heartbeat as *const _;
+        self.heartbeat = Some(unsafe { Heartbeat::start(field, interval_ms) });
+    }
+
+    /// Stops the heartbeat started by [`start_heartbeat`](Self::start_heartbeat).
+    pub fn stop_heartbeat(&mut self) {
+        self.heartbeat = None;
+    }
+
+    /// Returns the generation of the server this client connected to.
+    ///
+    /// Every server picks a new generation, so a restarted server has a different one.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns false once the server this client connected to has been dropped,
+    /// replaced by a new server or has died.
+    ///
+    /// A disconnected client has to be recreated to talk to a new server, see
+    /// [`ReconnectingClient`](crate::ReconnectingClient).
+    pub fn is_connected(&self) -> bool {
+        let header = unsafe { &*(self.transport.as_ptr() as *const SharedDataHeader) };
+        header.generation.load(Ordering::Acquire) == self.generation && self.is_peer_alive()
+    }
+
+    /// Returns true once the server has sent a close signal.
+    pub(crate) fn close_requested(&self) -> bool {
+        matches!(self.client_slot().flag_server.load(Ordering::Acquire), 3 | 5)
+    }
+
+    /// Returns true if the server process is alive.
+    ///
+    /// The server counts as dead once its process has exited or, if it sends
+    /// heartbeats, once its heartbeat is stale.
+    pub fn is_peer_alive(&self) -> bool {
+        let header = unsafe { &*(self.transport.as_ptr() as *const SharedDataHeader) };
+        liveness::peer_alive(
+            header.server_pid.load(Ordering::Acquire),
+            header.server_heartbeat.load(Ordering::Acquire),
+        )
     }
 
     /// Sends data to the server.
     ///
     /// # Arguments
     ///
-    /// * `data` - Byte slice to send. Maximum size is the configured buffer size.
+    /// * `data` - Byte slice to send. Maximum size is [`max_message_len`](Self::max_message_len)
+    ///   unless fragmentation is enabled.
     ///
     /// # Example
     ///
@@ -121,13 +430,172 @@ impl Client {
     /// client.send(b"Hello, server!").unwrap();
     /// ```
     pub fn send(&self, data: &[u8]) -> Result<()> {
+        if self.fragmentation && data.len() > self.max_message_len() {
+            return self.send_timeout(data, None);
+        }
+
         unsafe {
             write_to_shared_memory(
-                self.header_address,
-                self.buffer_size,
+                &self.transport,
+                self.buffers.client_to_server,
+                data,
+                Direction::ClientToServer,
+                self.slot,
+            )
+        }
+    }
+
+    /// Sends one message made of `bufs` one after another.
+    ///
+    /// Each piece is copied straight into the shared buffer, so a header and a
+    /// payload do not have to be joined first. Otherwise behaves like
+    /// [`send`](Self::send), with the total length checked against
+    /// [`max_message_len`](Self::max_message_len). With fragmentation an
+    /// oversized message is gathered into one allocation and sent in fragments.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::Client;
+    /// use std::io::IoSlice;
+    ///
+    /// # let client = Client::new(None).unwrap();
+    /// let header = 7u32.to_le_bytes();
+    /// client.send_vectored(&[IoSlice::new(&header), IoSlice::new(b"payload")]).unwrap();
+    /// ```
+    pub fn send_vectored(&self, bufs: &[IoSlice<'_>]) -> Result<()> {
+        let len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        if self.fragmentation && len > self.max_message_len() {
+            return self.send_timeout(&gather(bufs), None);
+        }
+
+        unsafe {
+            write_vectored_to_shared_memory(
+                &self.transport,
+                self.buffers.client_to_server,
+                bufs,
+                Direction::ClientToServer,
+                self.slot,
+            )
+        }
+    }
+
+    /// Sends data only if the server has consumed the previous message.
+    ///
+    /// Returns [`Error::WouldBlock`](crate::Error::WouldBlock) instead of overwriting
+    /// an unread message (or, in ring buffer mode, if the ring is full).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::{Client, Error};
+    /// # let client = Client::new(None).unwrap();
+    /// match client.try_send(b"update") {
+    ///     Ok(()) => {}
+    ///     Err(Error::WouldBlock) => println!("previous message not read yet"),
+    ///     Err(e) => panic!("{}", e),
+    /// }
+    /// ```
+    pub fn try_send(&self, data: &[u8]) -> Result<()> {
+        unsafe {
+            try_write_to_shared_memory(
+                &self.transport,
+                self.buffers.client_to_server,
                 data,
-                false,
-                self.h_event_c2s,
+                Direction::ClientToServer,
+                self.slot,
+            )
+        }
+    }
+
+    /// Sends data, waiting until the server has consumed the previous message.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Byte slice to send. Maximum size is [`max_message_len`](Self::max_message_len)
+    ///   unless fragmentation is enabled.
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default send timeout](Self::set_send_timeout).
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::Timeout`](crate::Error::Timeout) if the previous message
+    /// is still unread when the timeout elapses. With fragmentation the timeout
+    /// applies to the whole message.
+    pub fn send_timeout(&self, data: &[u8], timeout_ms: Option<u32>) -> Result<()> {
+        if self.fragmentation {
+            return unsafe {
+                write_fragmented_to_shared_memory(
+                    &self.transport,
+                    self.buffers.client_to_server,
+                    data,
+                    Direction::ClientToServer,
+                    self.slot,
+                    self.send_timeout_or_default(timeout_ms),
+                )
+            };
+        }
+
+        unsafe {
+            write_to_shared_memory_timeout(
+                &self.transport,
+                self.buffers.client_to_server,
+                data,
+                Direction::ClientToServer,
+                self.slot,
+                self.send_timeout_or_default(timeout_ms),
+            )
+        }
+    }
+
+    /// Sends to the server like [`send_timeout`](Self::send_timeout) without a timeout, but
+    /// gives up with [`Error::Timeout`](crate::Error::Timeout) once `cancelled` returns true
+    /// while waiting.
+    #[cfg(feature = "async")]
+    pub(crate) fn send_cancellable(&self, data: &[u8], cancelled: &dyn Fn() -> bool) -> Result<()> {
+        use crate::shared_memory::{retry_until_cancelled, write_fragmented_until};
+
+        let (buffer_size, direction) = (self.buffers.client_to_server, Direction::ClientToServer);
+        unsafe {
+            if self.fragmentation {
+                write_fragmented_until(&self.transport, buffer_size, data, direction, self.slot, None, cancelled)
+            } else {
+                retry_until_cancelled(None, cancelled, || {
+                    try_write_to_shared_memory(&self.transport, buffer_size, data, direction, self.slot)
+                })
+            }
+        }
+    }
+
+    /// Reserves `len` bytes in the buffer to the server, to build a message in place.
+    ///
+    /// The message is sent when the returned guard is committed, and dropped
+    /// if the guard is dropped without committing.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::WouldBlock`](crate::Error::WouldBlock) if the server has not
+    /// consumed the previous message (or, in ring buffer mode, if the ring is full)
+    /// and [`Error::MessageTooLarge`](crate::Error::MessageTooLarge) if `len` exceeds
+    /// [`max_message_len`](Self::max_message_len).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::Client;
+    /// # let mut client = Client::new(None).unwrap();
+    /// let mut request = client.reserve(5).unwrap();
+    /// request.copy_from_slice(b"hello");
+    /// request.commit().unwrap();
+    /// ```
+    pub fn reserve(&mut self, len: usize) -> Result<WriteGuard<'_, T>> {
+        unsafe {
+            WriteGuard::new(
+                &self.transport,
+                self.buffers.client_to_server,
+                len,
+                Direction::ClientToServer,
+                self.slot,
             )
         }
     }
@@ -136,7 +604,8 @@ impl Client {
     ///
     /// # Arguments
     ///
-    /// * `timeout_ms` - Optional timeout in milliseconds. If None, waits indefinitely.
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default receive timeout](Self::set_receive_timeout).
     ///
     /// # Returns
     ///
@@ -144,11 +613,11 @@ impl Client {
     pub fn receive(&self, timeout_ms: Option<u32>) -> ReceiveMessage {
         unsafe {
             read_from_shared_memory(
-                self.header_address,
-                self.buffer_size,
-                false,
-                timeout_ms,
-                self.h_event_s2c,
+                &self.transport,
+                self.buffers.server_to_client,
+                Direction::ServerToClient,
+                self.slot,
+                self.receive_timeout_or_default(timeout_ms),
             )
         }
     }
@@ -157,44 +626,305 @@ impl Client {
     ///
     /// # Arguments
     ///
-    /// * `timeout_ms` - Optional timeout in milliseconds. If None, waits indefinitely.
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default receive timeout](Self::set_receive_timeout).
     ///
     /// # Returns
     ///
     /// Returns a ReceiveBytes enum containing the bytes or status.
     pub fn receive_bytes(&self, timeout_ms: Option<u32>) -> ReceiveBytes {
         unsafe {
-            crate::read_bytes_from_shared_memory(
-                self.header_address,
-                self.buffer_size,
-                false,
-                timeout_ms,
-                self.h_event_s2c,
+            read_bytes_from_shared_memory(
+                &self.transport,
+                self.buffers.server_to_client,
+                Direction::ServerToClient,
+                self.slot,
+                self.receive_timeout_or_default(timeout_ms),
+            )
+        }
+    }
+
+    /// Receives data from the server as a String.
+    ///
+    /// Like [`receive`](Self::receive), but reports failures as a typed [`Error`](crate::Error).
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default receive timeout](Self::set_receive_timeout).
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(None)` on timeout and [`Error::PeerClosed`](crate::Error::PeerClosed)
+    /// when the close signal is received.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::{Client, Error};
+    /// # let client = Client::new(None).unwrap();
+    /// match client.recv(Some(1000)) {
+    ///     Ok(Some(message)) => println!("{}", message),
+    ///     Ok(None) => println!("timed out"),
+    ///     Err(Error::PeerClosed) => println!("closed"),
+    ///     Err(Error::InvalidUtf8(bytes)) => println!("{} bytes of binary data", bytes.len()),
+    ///     Err(e) => eprintln!("{}", e),
+    /// }
+    /// ```
+    pub fn recv(&self, timeout_ms: Option<u32>) -> Result<Option<String>> {
+        unsafe {
+            recv_from_shared_memory(
+                &self.transport,
+                self.buffers.server_to_client,
+                Direction::ServerToClient,
+                self.slot,
+                self.receive_timeout_or_default(timeout_ms),
+            )
+        }
+    }
+
+    /// Receives raw bytes from the server.
+    ///
+    /// Like [`receive_bytes`](Self::receive_bytes), but reports failures as a typed [`Error`](crate::Error).
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default receive timeout](Self::set_receive_timeout).
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(None)` on timeout and [`Error::PeerClosed`](crate::Error::PeerClosed)
+    /// when the close signal is received.
+    pub fn recv_bytes(&self, timeout_ms: Option<u32>) -> Result<Option<Vec<u8>>> {
+        unsafe {
+            recv_bytes_from_shared_memory(
+                &self.transport,
+                self.buffers.server_to_client,
+                Direction::ServerToClient,
+                self.slot,
+                self.receive_timeout_or_default(timeout_ms),
+            )
+        }
+    }
+
+    /// Receives a message from the server without copying it out of shared memory.
+    ///
+    /// The server cannot reuse the buffer until the returned guard is dropped,
+    /// see [`ReadGuard`].
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default receive timeout](Self::set_receive_timeout).
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(None)` on timeout and [`Error::PeerClosed`](crate::Error::PeerClosed)
+    /// when the close signal is received.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::Client;
+    /// # let mut client = Client::new(None).unwrap();
+    /// if let Some(frame) = client.receive_borrowed(Some(1000)).unwrap() {
+    ///     println!("{} bytes", frame.len());
+    /// }
+    /// ```
+    pub fn receive_borrowed(&mut self, timeout_ms: Option<u32>) -> Result<Option<ReadGuard<'_>>> {
+        unsafe {
+            ReadGuard::receive(
+                &self.transport,
+                self.buffers.server_to_client,
+                Direction::ServerToClient,
+                self.slot,
+                deadline(self.receive_timeout_or_default(timeout_ms)),
+                usize::MAX,
+            )
+        }
+    }
+
+    /// Receives raw bytes from the server into `buffer`, reusing its allocation.
+    ///
+    /// The previous contents of `buffer` are replaced by the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - Vector the message is copied into.
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default receive timeout](Self::set_receive_timeout).
+    ///
+    /// # Returns
+    ///
+    /// Returns the length of the message, `Ok(None)` on timeout and
+    /// [`Error::PeerClosed`](crate::Error::PeerClosed) when the close signal is received.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::Client;
+    /// # let client = Client::new(None).unwrap();
+    /// let mut buffer = Vec::with_capacity(client.buffer_sizes().server_to_client);
+    /// while let Ok(Some(len)) = client.receive_into(&mut buffer, Some(1000)) {
+    ///     println!("{} bytes", len);
+    /// }
+    /// ```
+    pub fn receive_into(&self, buffer: &mut Vec<u8>, timeout_ms: Option<u32>) -> Result<Option<usize>> {
+        let Some(message) = self.receive_guard(timeout_ms, usize::MAX)? else {
+            return Ok(None);
+        };
+        buffer.clear();
+        buffer.extend_from_slice(&message);
+        Ok(Some(message.len()))
+    }
+
+    /// Receives raw bytes from the server into the start of `buffer`.
+    ///
+    /// Like [`receive_into`](Self::receive_into), but if the message is longer than
+    /// `buffer` it is left unread and [`Error::MessageTooLarge`](crate::Error::MessageTooLarge)
+    /// holds its length, so the call can be retried with a larger buffer.
+    pub fn receive_into_slice(&self, buffer: &mut [u8], timeout_ms: Option<u32>) -> Result<Option<usize>> {
+        let Some(message) = self.receive_guard(timeout_ms, buffer.len())? else {
+            return Ok(None);
+        };
+        buffer[..message.len()].copy_from_slice(&message);
+        Ok(Some(message.len()))
+    }
+
+    /// Waits for a message from the server, left in place until the returned guard is dropped.
+    fn receive_guard(&self, timeout_ms: Option<u32>, max_len: usize) -> Result<Option<ReadGuard<'_>>> {
+        // The guard does not outlive the calling method, and a client is not shared between threads
+        unsafe {
+            ReadGuard::receive(
+                &self.transport,
+                self.buffers.server_to_client,
+                Direction::ServerToClient,
+                self.slot,
+                deadline(self.receive_timeout_or_default(timeout_ms)),
+                max_len,
+            )
+        }
+    }
+
+    /// Receives a message from the server as a String if one has already arrived.
+    ///
+    /// Never waits; returns [`ReceiveMessage::Timeout`] if there is no message.
+    pub fn try_receive(&self) -> ReceiveMessage {
+        self.receive_until(Instant::now())
+    }
+
+    /// Receives data from the server as a String, waiting until `deadline`.
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - Point in time after which the wait gives up. A deadline in the past
+    ///   only checks for a message that has already arrived.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::{Client, ReceiveMessage};
+    /// # use std::time::{Duration, Instant};
+    /// # let client = Client::new(None).unwrap();
+    /// // Several receives sharing one overall deadline
+    /// let deadline = Instant::now() + Duration::from_secs(1);
+    /// while let ReceiveMessage::Message(msg) = client.receive_until(deadline) {
+    ///     println!("{}", msg);
+    /// }
+    /// ```
+    pub fn receive_until(&self, deadline: Instant) -> ReceiveMessage {
+        self.recv_until(deadline).into()
+    }
+
+    /// Receives raw bytes from the server if a message has already arrived.
+    ///
+    /// Never waits; returns [`ReceiveBytes::Timeout`] if there is no message.
+    pub fn try_receive_bytes(&self) -> ReceiveBytes {
+        self.receive_bytes_until(Instant::now())
+    }
+
+    /// Receives raw bytes from the server, waiting until `deadline`.
+    ///
+    /// See [`receive_until`](Self::receive_until).
+    pub fn receive_bytes_until(&self, deadline: Instant) -> ReceiveBytes {
+        self.recv_bytes_until(deadline).into()
+    }
+
+    /// Like [`try_receive`](Self::try_receive), but reports failures as a typed [`Error`](crate::Error).
+    ///
+    /// Returns `Ok(None)` if there is no message.
+    pub fn try_recv(&self) -> Result<Option<String>> {
+        self.recv_until(Instant::now())
+    }
+
+    /// Like [`receive_until`](Self::receive_until), but reports failures as a typed [`Error`](crate::Error).
+    ///
+    /// Returns `Ok(None)` if the deadline passes without a message.
+    pub fn recv_until(&self, deadline: Instant) -> Result<Option<String>> {
+        unsafe {
+            recv_from_shared_memory_until(
+                &self.transport,
+                self.buffers.server_to_client,
+                Direction::ServerToClient,
+                self.slot,
+                Some(deadline),
+            )
+        }
+    }
+
+    /// Like [`try_receive_bytes`](Self::try_receive_bytes), but reports failures as a typed [`Error`](crate::Error).
+    ///
+    /// Returns `Ok(None)` if there is no message.
+    pub fn try_recv_bytes(&self) -> Result<Option<Vec<u8>>> {
+        self.recv_bytes_until(Instant::now())
+    }
+
+    /// Like [`receive_bytes_until`](Self::receive_bytes_until), but reports failures as a typed [`Error`](crate::Error).
+    ///
+    /// Returns `Ok(None)` if the deadline passes without a message.
+    pub fn recv_bytes_until(&self, deadline: Instant) -> Result<Option<Vec<u8>>> {
+        unsafe {
+            recv_bytes_from_shared_memory_until(
+                &self.transport,
+                self.buffers.server_to_client,
+                Direction::ServerToClient,
+                self.slot,
+                Some(deadline),
             )
         }
     }
 }
 
-impl Drop for Client {
+impl<T: Transport> Drop for Client<T> {
     fn drop(&mut self) {
+        if self.close_on_drop
+            && let Err(e) = self.close(self.close_timeout_ms)
+        {
+            eprintln!("Failed to close connection: {}", e);
+        }
+        self.heartbeat.take();
+
         unsafe {
-            if let Err(e) = UnmapViewOfFile(MEMORY_MAPPED_VIEW_ADDRESS {
-                Value: self.header_address as *mut _,
-            }) {
-                eprintln!("Failed to unmap shared memory: {:?}", e);
+            let header = self.transport.as_ptr() as *mut SharedDataHeader;
+            // The server that handed out the slot is gone, a new server may have reused it
+            if (*header).generation.load(Ordering::Acquire) != self.generation {
+                return;
             }
-
-            if let Err(e) = CloseHandle(self.h_event_s2c) {
-                eprintln!("Failed to close event handle (s2c): {:?}", e);
+            let slot = client_slot(header, self.slot);
+            // Another client took the slot over because this one looked dead
+            if slot.client_pid.load(Ordering::Acquire) != std::process::id() {
+                return;
             }
 
-            if let Err(e) = CloseHandle(self.h_event_c2s) {
-                eprintln!("Failed to close event handle (c2s): {:?}", e);
+            // Drop unread messages so the server does not wait on them
+            slot.flag_server.store(0, Ordering::Release);
+            if self.mode == ChannelMode::Ring {
+                let ring = ring(header, self.buffers.server_to_client, Direction::ServerToClient, self.slot);
+                ring.clear();
             }
 
-            if let Err(e) = CloseHandle(self.h_map_file) {
-                eprintln!("Failed to close file mapping handle: {:?}", e);
-            }
+            release_slot(header, self.slot, std::process::id());
         }
     }
 }
@@ -0,0 +1,117 @@
+use crate::ClientId;
+use std::fmt;
+
+/// Result type used throughout this crate.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors returned by shared memory operations.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An operating system call failed
+    Io(std::io::Error),
+    /// The peer has not consumed the previous message yet
+    WouldBlock,
+    /// The operation did not complete before the timeout elapsed
+    Timeout,
+    /// The message does not fit in the buffer and fragmentation is disabled
+    MessageTooLarge {
+        /// Length of the message in bytes
+        len: usize,
+        /// Largest message the buffer can hold in bytes
+        capacity: usize,
+    },
+    /// Waiting for the peer's signal failed
+    WaitFailed {
+        /// OS error code, if the failure came from the OS
+        code: Option<i32>,
+    },
+    /// The received message is not valid UTF-8; holds the raw bytes
+    InvalidUtf8(Vec<u8>),
+    /// The state flag in the header holds an unknown value
+    UnknownState(u32),
+    /// The peer sent a close signal
+    PeerClosed,
+    /// The peer's process exited or its heartbeat stopped
+    PeerDied,
+    /// The message ended before all of it was received
+    Truncated {
+        /// Number of bytes received before the message was cut off
+        received: usize,
+    },
+    /// The shared memory header is missing, corrupt or incompatible
+    HeaderMismatch(String),
+    /// The RPC handler on the server returned an error
+    Remote(String),
+    /// Every client slot of the server is already claimed
+    ServerFull {
+        /// Number of client slots the server was created with
+        max_clients: usize,
+    },
+    /// A client sent a close signal while the server was receiving from any client
+    ClientClosed(ClientId),
+    /// The shared memory name is already in use, see [`CreationPolicy`](crate::CreationPolicy)
+    AlreadyExists,
+    /// A data buffer holds an offset or length that no well-behaved peer writes
+    Corrupt(String),
+}
+
+impl Error {
+    /// Wraps an error returned while waiting for a signal.
+    pub(crate) fn wait_failed(e: Error) -> Self {
+        match e {
+            Error::Io(e) => Error::WaitFailed {
+                code: e.raw_os_error(),
+            },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "OS error: {}", e),
+            Error::WouldBlock => write!(f, "the peer has not consumed the previous message"),
+            Error::Timeout => write!(f, "operation timed out"),
+            Error::MessageTooLarge { len, capacity } => {
+                write!(f, "message of {} bytes exceeds the buffer capacity of {} bytes", len, capacity)
+            }
+            Error::WaitFailed { code: Some(code) } => write!(f, "Event wait failed (OS error {})", code),
+            Error::WaitFailed { code: None } => write!(f, "Event wait failed"),
+            Error::InvalidUtf8(_) => write!(f, "UTF-8 conversion failed"),
+            Error::UnknownState(state) => write!(f, "Unknown state {}", state),
+            Error::PeerClosed => write!(f, "the peer closed the connection"),
+            Error::PeerDied => write!(f, "the peer process exited or stopped responding"),
+            Error::Truncated { received } => write!(f, "message was cut off after {} bytes", received),
+            Error::HeaderMismatch(reason) => write!(f, "incompatible shared memory header: {}", reason),
+            Error::Remote(message) => write!(f, "remote error: {}", message),
+            Error::ServerFull { max_clients } => write!(f, "all {} client slots are in use", max_clients),
+            Error::ClientClosed(client) => write!(f, "client {} closed the connection", client.0),
+            Error::AlreadyExists => write!(f, "the shared memory name is already in use"),
+            Error::Corrupt(reason) => write!(f, "corrupt shared memory: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[cfg(windows)]
+impl From<windows::core::Error> for Error {
+    fn from(e: windows::core::Error) -> Self {
+        Error::Io(e.into())
+    }
+}
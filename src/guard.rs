@@ -0,0 +1,353 @@
+use crate::ring::{Peek, Reservation, Ring};
+use crate::shared_memory::{
+    acknowledge_close, is_ring, lane, max_message_len, ring, scrubs, wait_for_peer, FRAGMENT_HEADER,
+};
+use crate::{
+    recv_bytes_from_shared_memory_until, ChannelMode, Direction, Error, Result, SharedDataHeader,
+    ShmTransport, Transport,
+};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Instant;
+
+/// Where the bytes of a [`WriteGuard`] are written.
+enum Target<'a> {
+    /// Straight into the mailbox buffer
+    Mailbox {
+        flag: &'a AtomicU32,
+        data_len: *mut u32,
+        buffer: &'a mut [u8],
+    },
+    /// Straight into the ring, behind the head
+    Ring { ring: Ring<'a>, buffer: &'a mut [u8] },
+    /// Into a local buffer, because the record would wrap around the end of the ring
+    Staged { ring: Ring<'a>, buffer: Vec<u8> },
+}
+
+/// Space for one message in a shared data buffer, returned by
+/// [`Server::reserve`](crate::Server::reserve) and [`Client::reserve`](crate::Client::reserve).
+///
+/// The guard dereferences to the bytes of the message, which are written in
+/// place instead of being copied from a separate buffer. Nothing is visible to
+/// the peer until [`commit`](Self::commit); dropping the guard without
+/// committing abandons the message.
+///
+/// In ring buffer mode a message whose bytes would wrap around the end of the
+/// ring is staged in local memory and copied in on commit.
+///
+/// The bytes start out with whatever the buffer held before, so every byte of
+/// the message should be written.
+pub struct WriteGuard<'a, T: Transport + ?Sized = ShmTransport> {
+    transport: &'a T,
+    direction: Direction,
+    slot: usize,
+    target: Target<'a>,
+}
+
+impl<'a, T: Transport + ?Sized> WriteGuard<'a, T> {
+    /// Reserves `len` bytes in the data buffer for `direction` of `slot`.
+    ///
+    /// Returns [`Error::WouldBlock`] if the peer has not consumed the previous
+    /// message (or, in ring buffer mode, if the ring is full) and
+    /// [`Error::MessageTooLarge`] if `len` does not fit in one buffer.
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as [`write_to_shared_memory`](crate::write_to_shared_memory),
+    /// and nothing else may write to the buffer while the guard is alive.
+    pub(crate) unsafe fn new(
+        transport: &'a T,
+        buffer_size: usize,
+        len: usize,
+        direction: Direction,
+        slot: usize,
+    ) -> Result<Self> {
+        unsafe {
+            let header = transport.as_ptr() as *mut SharedDataHeader;
+            let mode = if is_ring(header) { ChannelMode::Ring } else { ChannelMode::Mailbox };
+            let capacity = max_message_len(mode, buffer_size);
+            if len > capacity {
+                return Err(Error::MessageTooLarge { len, capacity });
+            }
+
+            let target = if mode == ChannelMode::Ring {
+                let ring = ring(header, buffer_size, direction, slot);
+                match ring.reserve(len)? {
+                    Reservation::Full => return Err(Error::WouldBlock),
+                    Reservation::Wrapped => Target::Staged {
+                        ring,
+                        buffer: vec![0; len],
+                    },
+                    Reservation::InPlace(ptr) => Target::Ring {
+                        ring,
+                        buffer: std::slice::from_raw_parts_mut(ptr, len),
+                    },
+                }
+            } else {
+                let (flag, data_len, data_buffer_offset) = lane(header, direction, slot);
+                // State 1, 4 or 6 means the peer has not read the previous message yet
+                if matches!(flag.load(Ordering::Acquire), 1 | 4 | 6) {
+                    return Err(Error::WouldBlock);
+                }
+                Target::Mailbox {
+                    flag,
+                    data_len,
+                    buffer: std::slice::from_raw_parts_mut(transport.as_ptr().add(data_buffer_offset), len),
+                }
+            };
+
+            Ok(Self {
+                transport,
+                direction,
+                slot,
+                target,
+            })
+        }
+    }
+
+    /// Publishes the message and signals the peer.
+    pub fn commit(self) -> Result<()> {
+        match self.target {
+            Target::Mailbox { flag, data_len, buffer } => {
+                unsafe { *data_len = buffer.len() as u32 };
+                // Set flag (1: data sent)
+                flag.store(1, Ordering::Release);
+            }
+            Target::Ring { ring, buffer } => ring.commit(buffer.len())?,
+            Target::Staged { ring, buffer } => {
+                // The space was reserved and only the consumer frees more
+                let pushed = ring.push(&buffer)?;
+                debug_assert!(pushed, "reserved ring space was taken");
+            }
+        }
+        self.transport.signal(self.direction, self.slot)
+    }
+}
+
+impl<T: Transport + ?Sized> Deref for WriteGuard<'_, T> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.target {
+            Target::Mailbox { buffer, .. } | Target::Ring { buffer, .. } => buffer,
+            Target::Staged { buffer, .. } => buffer,
+        }
+    }
+}
+
+impl<T: Transport + ?Sized> DerefMut for WriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match &mut self.target {
+            Target::Mailbox { buffer, .. } | Target::Ring { buffer, .. } => buffer,
+            Target::Staged { buffer, .. } => buffer,
+        }
+    }
+}
+
+/// How the bytes of a [`ReadGuard`] are handed back to the sender.
+enum Source<'a> {
+    /// In the mailbox buffer, released by marking the message as received
+    Mailbox {
+        flag: &'a AtomicU32,
+        data: &'a mut [u8],
+        scrub: bool,
+    },
+    /// In the ring, released by moving the tail past the record and clearing the lane's state
+    Ring {
+        ring: Ring<'a>,
+        data: &'a [u8],
+        flag: &'a AtomicU32,
+    },
+    /// Copied out already, because the message was fragmented or wrapped around the ring
+    Owned(Vec<u8>),
+}
+
+/// A received message borrowed from the shared data buffer, returned by
+/// [`Server::receive_borrowed`](crate::Server::receive_borrowed) and
+/// [`Client::receive_borrowed`](crate::Client::receive_borrowed).
+///
+/// The guard dereferences to the bytes of the message without copying them.
+/// The message is marked as being read until the guard is dropped, so no send
+/// on the other side can reuse the buffer in the meantime: `send` and
+/// `try_send` return [`Error::WouldBlock`], `send_timeout` waits. A client that
+/// takes over the slot in the meantime does not reset the buffer either.
+///
+/// Fragmented messages, and in ring buffer mode messages that wrap around the
+/// end of the ring, cannot be borrowed and are copied out instead.
+pub struct ReadGuard<'a> {
+    source: Source<'a>,
+}
+
+impl<'a> ReadGuard<'a> {
+    /// Waits until `deadline` for a message in `direction` of `slot`.
+    ///
+    /// Returns `Ok(None)` on timeout and [`Error::PeerClosed`] if the peer sent a
+    /// close signal. A deadline of None waits indefinitely. A message longer than
+    /// `max_len` is left unread and reported as [`Error::MessageTooLarge`], unless
+    /// its fragments claimed a smaller total, in which case it is dropped.
+    ///
+    /// Fragments left over from a message whose start was missed are dropped.
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as [`read_bytes_from_shared_memory`](crate::read_bytes_from_shared_memory),
+    /// and nothing else may read from the buffer while the guard is alive.
+    pub(crate) unsafe fn receive<T: Transport + ?Sized>(
+        transport: &'a T,
+        buffer_size: usize,
+        direction: Direction,
+        slot: usize,
+        deadline: Option<Instant>,
+        max_len: usize,
+    ) -> Result<Option<Self>> {
+        unsafe {
+            let header = transport.as_ptr() as *mut SharedDataHeader;
+            let (flag, data_len, data_buffer_offset) = lane(header, direction, slot);
+            let data_ptr = transport.as_ptr().add(data_buffer_offset);
+            let too_large = |len: usize| Error::MessageTooLarge { len, capacity: max_len };
+            let copy = || {
+                match recv_bytes_from_shared_memory_until(transport, buffer_size, direction, slot, deadline)? {
+                    Some(bytes) if bytes.len() > max_len => Err(too_large(bytes.len())),
+                    bytes => Ok(bytes.map(Self::owned)),
+                }
+            };
+
+            loop {
+                if is_ring(header) {
+                    let ring = ring(header, buffer_size, direction, slot);
+                    ring.drop_stray()?;
+                    if let Some(len) = ring.peek_len()?
+                        && len > max_len
+                    {
+                        return Err(too_large(len));
+                    }
+                    match ring.peek()? {
+                        Peek::InPlace(_) => {
+                            // Mark the ring as being read (6), so that a client claiming the slot
+                            // does not reset it, then look again in case one just did
+                            if flag.compare_exchange(0, 6, Ordering::AcqRel, Ordering::Acquire).is_err() {
+                                return copy();
+                            }
+                            match ring.peek() {
+                                Ok(Peek::InPlace(data)) if data.len() <= max_len => {
+                                    return Ok(Some(Self {
+                                        source: Source::Ring { ring, data, flag },
+                                    }));
+                                }
+                                peek => {
+                                    let _ = flag.compare_exchange(6, 0, Ordering::AcqRel, Ordering::Acquire);
+                                    peek?;
+                                    continue;
+                                }
+                            }
+                        }
+                        Peek::Split => return copy(),
+                        Peek::Empty => {}
+                    }
+                    if matches!(flag.load(Ordering::Acquire), 3 | 5) {
+                        acknowledge_close(flag);
+                        return Err(Error::PeerClosed);
+                    }
+                } else {
+                    // State - 0: waiting, 1: data sent, 2: data received, 3: close requested,
+                    // 4: fragment sent, 5: close acknowledged, 6: data being read in place
+                    match flag.load(Ordering::Acquire) {
+                        1 => {
+                            let len = *data_len as usize;
+                            if len > buffer_size {
+                                flag.store(2, Ordering::Release);
+                                return Err(Error::Truncated { received: buffer_size });
+                            }
+                            if len > max_len {
+                                return Err(too_large(len));
+                            }
+                            // Mark data as being read (6), so that no send rewrites it
+                            if flag.compare_exchange(1, 6, Ordering::AcqRel, Ordering::Acquire).is_err() {
+                                continue;
+                            }
+                            let data = std::slice::from_raw_parts_mut(data_ptr, len);
+                            return Ok(Some(Self {
+                                source: Source::Mailbox {
+                                    flag,
+                                    data,
+                                    scrub: scrubs(header),
+                                },
+                            }));
+                        }
+                        // Reported as corrupt by the copy
+                        4 if buffer_size < FRAGMENT_HEADER => return copy(),
+                        4 => {
+                            // The first fragment starts with the total length and offset 0
+                            let fragment = std::slice::from_raw_parts(data_ptr, FRAGMENT_HEADER);
+                            let total_len = u32::from_ne_bytes(fragment[..4].try_into().unwrap()) as usize;
+                            let offset = u32::from_ne_bytes(fragment[4..].try_into().unwrap());
+                            if offset != 0 {
+                                // The start of this message was missed, drop the fragment
+                                if scrubs(header) {
+                                    std::slice::from_raw_parts_mut(data_ptr, (*data_len as usize).min(buffer_size))
+                                        .fill(0);
+                                }
+                                let _ = flag.compare_exchange(4, 2, Ordering::AcqRel, Ordering::Acquire);
+                                continue;
+                            }
+                            if total_len > max_len {
+                                return Err(too_large(total_len));
+                            }
+                            return copy();
+                        }
+                        3 | 5 => {
+                            acknowledge_close(flag);
+                            return Err(Error::PeerClosed);
+                        }
+                        0 | 2 | 6 => {}
+                        state => return Err(Error::UnknownState(state)),
+                    }
+                }
+
+                // A signal may be left over from a message that was already read, or meant for
+                // another slot, so keep waiting until the deadline
+                if !wait_for_peer(transport, direction, slot, deadline)? {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn owned(bytes: Vec<u8>) -> Self {
+        Self {
+            source: Source::Owned(bytes),
+        }
+    }
+}
+
+impl Deref for ReadGuard<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.source {
+            Source::Mailbox { data, .. } => data,
+            Source::Ring { data, .. } => data,
+            Source::Owned(bytes) => bytes,
+        }
+    }
+}
+
+impl Drop for ReadGuard<'_> {
+    fn drop(&mut self) {
+        match &mut self.source {
+            // Mark data as received (2), unless the sender has asked to close since
+            Source::Mailbox { flag, data, scrub } => {
+                if *scrub {
+                    data.fill(0);
+                }
+                let _ = flag.compare_exchange(6, 2, Ordering::AcqRel, Ordering::Acquire);
+            }
+            // Unless the sender has asked to close since
+            Source::Ring { ring, data, flag } => {
+                ring.discard(data.len());
+                let _ = flag.compare_exchange(6, 0, Ordering::AcqRel, Ordering::Acquire);
+            }
+            Source::Owned(_) => {}
+        }
+    }
+}
@@ -4,13 +4,20 @@
 //! This library provides a simple API for inter-process communication (IPC)
 //! using Windows shared memory and events.
 //!
+//! The same API is available on Linux, backed by POSIX shared memory
+//! (`shm_open` + `mmap`) and futex-based events. The shared memory layout is
+//! identical on both platforms.
+//!
 //! ## Features
 //!
 //! - Server-client architecture for shared memory communication
 //! - Support for 32-bit and 64-bit processes communication
-//! - Synchronization using Windows events
+//! - Synchronization using Windows events (futexes on Linux)
 //! - Thread-safe operations using atomic operations
 //! - **Dynamic buffer size** - configure buffer size at runtime
+//! - **Ring buffer mode** - queue many messages per direction instead of a single slot
+//! - **Pluggable transports** - run over OS shared memory or an in-process [`HeapTransport`]
+//! - **Async** - `AsyncServer` / `AsyncClient` with the `async` feature
 //!
 //! ## Example
 //!
@@ -33,14 +40,52 @@
 //! }
 //! ```
 
+#[cfg(not(any(windows, target_os = "linux")))]
+compile_error!("windows_shared_memory supports only Windows and Linux");
+
+#[cfg(feature = "async")]
+mod async_io;
+mod builder;
 mod client;
+mod error;
+mod guard;
+mod liveness;
+mod locked;
+#[cfg(target_os = "linux")]
+mod posix;
+mod reconnect;
+mod ring;
+mod rpc;
 mod server;
 mod shared_memory;
 mod skima;
+mod transport;
+#[cfg(windows)]
 mod utils;
+#[cfg(windows)]
+mod win32;
 
+#[cfg(feature = "async")]
+pub use async_io::*;
+pub use builder::*;
 pub use client::*;
+pub use error::*;
+pub use guard::*;
+pub use locked::*;
+pub use reconnect::*;
+pub use rpc::*;
 pub use server::*;
 pub use shared_memory::*;
 pub use skima::*;
+pub use transport::*;
+#[cfg(windows)]
 pub use utils::*;
+
+#[cfg(target_os = "linux")]
+use posix::{monotonic_ms, process_alive, Mapping, Security};
+#[cfg(target_os = "linux")]
+pub use posix::Event;
+#[cfg(windows)]
+use win32::{monotonic_ms, process_alive, Mapping, Security};
+#[cfg(windows)]
+pub use win32::Event;
@@ -0,0 +1,79 @@
+use crate::{monotonic_ms, process_alive};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Number of heartbeat intervals a peer may miss before it counts as dead
+const MISSED_HEARTBEATS: u64 = 3;
+
+/// Returns false if the process `pid` has exited or its heartbeat has expired.
+///
+/// A `pid` or `heartbeat` of 0 means the peer did not record it.
+pub(crate) fn peer_alive(pid: u32, heartbeat: u64) -> bool {
+    if heartbeat != 0 && monotonic_ms() > heartbeat {
+        return false;
+    }
+    pid == 0 || process_alive(pid)
+}
+
+/// Background thread that keeps a heartbeat field in the header fresh.
+///
+/// The field is cleared when the heartbeat is dropped.
+pub(crate) struct Heartbeat {
+    field: *const AtomicU64,
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+// The field is only accessed atomically
+unsafe impl Send for Heartbeat {}
+
+/// Heartbeat field handed to the heartbeat thread.
+struct Field(*const AtomicU64);
+
+unsafe impl Send for Field {}
+
+impl Heartbeat {
+    /// Starts refreshing `field` every `interval_ms`.
+    ///
+    /// # Safety
+    ///
+    /// `field` must stay valid until the returned heartbeat is dropped.
+    pub(crate) unsafe fn start(field: *const AtomicU64, interval_ms: u32) -> Self {
+        let interval = interval_ms.max(1) as u64;
+        let beat = move |field: &AtomicU64| {
+            field.store(monotonic_ms() + interval * MISSED_HEARTBEATS, Ordering::Release);
+        };
+        beat(unsafe { &*field });
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let target = Field(field);
+        let thread = thread::spawn(move || {
+            // Capture the whole wrapper rather than just its pointer
+            let target = target;
+            // Wakes up early when the sender is dropped
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(Duration::from_millis(interval)) {
+                beat(unsafe { &*target.0 });
+            }
+        });
+
+        Self {
+            field,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            eprintln!("Failed to join heartbeat thread");
+        }
+        unsafe { (*self.field).store(0, Ordering::Release) };
+    }
+}
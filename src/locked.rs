@@ -0,0 +1,480 @@
+use crate::{
+    BufferSizes, ChannelMode, Client, ClientId, ReceiveBytes, ReceiveMessage, Result, Server, ShmTransport,
+    Transport,
+};
+use std::io::IoSlice;
+use std::ops::Deref;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// An endpoint shared between threads that never send at the same time and never
+/// receive at the same time.
+///
+/// [`Server`] and [`Client`] are not `Sync` because concurrent sends (or concurrent
+/// receives) would race on one buffer. Sends and receives use separate buffers, so
+/// [`SyncServer`], [`SyncClient`], `RpcClient` and the async wrappers share an endpoint
+/// through this type and serialize sends and receives themselves.
+pub(crate) struct Shared<E>(pub(crate) E);
+
+// Sharing the endpoint shares its transport, which therefore has to be Sync itself
+unsafe impl<T: Transport + Send + Sync> Sync for Shared<Server<T>> {}
+unsafe impl<T: Transport + Send + Sync> Sync for Shared<Client<T>> {}
+
+impl<E> Deref for Shared<E> {
+    type Target = E;
+
+    fn deref(&self) -> &E {
+        &self.0
+    }
+}
+
+/// A [`Server`] that can be shared between threads.
+///
+/// `Server` is `Send` but not `Sync`: two threads sending (or two threads
+/// receiving) at the same time would race on one buffer. `SyncServer`
+/// serializes sends with one lock and receives with another, so one thread
+/// can wait for a message while others keep sending.
+///
+/// Every thread uses the transport, so `SyncServer` is only `Sync` if the transport is:
+///
+/// ```compile_fail
+/// use std::cell::Cell;
+/// use windows_shared_memory::{Direction, Result, SyncServer, Transport};
+///
+/// struct SingleThreaded(Cell<u32>);
+///
+/// impl Transport for SingleThreaded {
+///     fn as_ptr(&self) -> *mut u8 { std::ptr::null_mut() }
+///     fn size(&self) -> usize { 0 }
+///     fn signal(&self, _: Direction, _: usize) -> Result<()> { Ok(()) }
+///     fn wait(&self, _: Direction, _: usize, _: Option<u32>) -> Result<bool> { Ok(false) }
+/// }
+///
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<SyncServer<SingleThreaded>>();
+/// ```
+///
+/// # Example
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use windows_shared_memory::{Server, SyncServer};
+///
+/// let server = Arc::new(SyncServer::new(Server::new(None).unwrap()));
+/// let sender = server.clone();
+/// std::thread::spawn(move || sender.send(b"from a worker thread").unwrap());
+/// let reply = server.recv_bytes(Some(1000)).unwrap();
+/// ```
+pub struct SyncServer<T: Transport = ShmTransport> {
+    server: Shared<Server<T>>,
+    send_lock: Mutex<()>,
+    recv_lock: Mutex<()>,
+}
+
+impl<T: Transport> SyncServer<T> {
+    /// Wraps a server.
+    pub fn new(server: Server<T>) -> Self {
+        Self {
+            server: Shared(server),
+            send_lock: Mutex::new(()),
+            recv_lock: Mutex::new(()),
+        }
+    }
+
+    /// Returns the underlying server.
+    pub fn into_inner(self) -> Server<T> {
+        self.server.0
+    }
+
+    /// Returns the size of the buffer this server sends through (server to client).
+    pub fn buffer_size(&self) -> usize {
+        self.server.buffer_size()
+    }
+
+    /// Returns the buffer sizes of both directions.
+    pub fn buffer_sizes(&self) -> BufferSizes {
+        self.server.buffer_sizes()
+    }
+
+    /// Returns the largest message that can be sent without fragmentation.
+    pub fn max_message_len(&self) -> usize {
+        self.server.max_message_len()
+    }
+
+    /// Returns the channel mode for this server.
+    pub fn mode(&self) -> ChannelMode {
+        self.server.mode()
+    }
+
+    /// Returns the number of client slots.
+    pub fn max_clients(&self) -> usize {
+        self.server.max_clients()
+    }
+
+    /// Returns the clients that currently hold a slot.
+    pub fn connected_clients(&self) -> Vec<ClientId> {
+        self.server.connected_clients()
+    }
+
+    /// See [`Server::is_peer_alive`].
+    pub fn is_peer_alive(&self) -> bool {
+        self.server.is_peer_alive()
+    }
+
+    /// See [`Server::is_client_alive`].
+    pub fn is_client_alive(&self, client: ClientId) -> bool {
+        self.server.is_client_alive(client)
+    }
+
+    /// See [`Server::send_close`].
+    pub fn send_close(&self) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.server.send_close()
+    }
+
+    /// See [`Server::close`].
+    pub fn close(&self, timeout_ms: Option<u32>) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.server.close(timeout_ms)
+    }
+
+    /// See [`Server::send`].
+    pub fn send(&self, data: &[u8]) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.server.send(data)
+    }
+
+    /// See [`Server::send_vectored`].
+    pub fn send_vectored(&self, bufs: &[IoSlice<'_>]) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.server.send_vectored(bufs)
+    }
+
+    /// See [`Server::try_send`].
+    pub fn try_send(&self, data: &[u8]) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.server.try_send(data)
+    }
+
+    /// See [`Server::send_timeout`]. Other senders wait while this one waits for the client.
+    pub fn send_timeout(&self, data: &[u8], timeout_ms: Option<u32>) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.server.send_timeout(data, timeout_ms)
+    }
+
+    /// See [`Server::send_to`].
+    pub fn send_to(&self, client: ClientId, data: &[u8]) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.server.send_to(client, data)
+    }
+
+    /// See [`Server::send_vectored_to`].
+    pub fn send_vectored_to(&self, client: ClientId, bufs: &[IoSlice<'_>]) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.server.send_vectored_to(client, bufs)
+    }
+
+    /// See [`Server::try_send_to`].
+    pub fn try_send_to(&self, client: ClientId, data: &[u8]) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.server.try_send_to(client, data)
+    }
+
+    /// See [`Server::send_timeout_to`].
+    pub fn send_timeout_to(&self, client: ClientId, data: &[u8], timeout_ms: Option<u32>) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.server.send_timeout_to(client, data, timeout_ms)
+    }
+
+    /// See [`Server::receive`]. Other receivers wait while this one waits for a message.
+    pub fn receive(&self, timeout_ms: Option<u32>) -> ReceiveMessage {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.receive(timeout_ms)
+    }
+
+    /// See [`Server::receive_bytes`].
+    pub fn receive_bytes(&self, timeout_ms: Option<u32>) -> ReceiveBytes {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.receive_bytes(timeout_ms)
+    }
+
+    /// See [`Server::recv`].
+    pub fn recv(&self, timeout_ms: Option<u32>) -> Result<Option<String>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.recv(timeout_ms)
+    }
+
+    /// See [`Server::recv_bytes`].
+    pub fn recv_bytes(&self, timeout_ms: Option<u32>) -> Result<Option<Vec<u8>>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.recv_bytes(timeout_ms)
+    }
+
+    /// See [`Server::receive_into`].
+    pub fn receive_into(&self, buffer: &mut Vec<u8>, timeout_ms: Option<u32>) -> Result<Option<usize>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.receive_into(buffer, timeout_ms)
+    }
+
+    /// See [`Server::receive_into_slice`].
+    pub fn receive_into_slice(&self, buffer: &mut [u8], timeout_ms: Option<u32>) -> Result<Option<usize>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.receive_into_slice(buffer, timeout_ms)
+    }
+
+    /// See [`Server::try_receive`].
+    pub fn try_receive(&self) -> ReceiveMessage {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.try_receive()
+    }
+
+    /// See [`Server::receive_until`].
+    pub fn receive_until(&self, deadline: Instant) -> ReceiveMessage {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.receive_until(deadline)
+    }
+
+    /// See [`Server::try_receive_bytes`].
+    pub fn try_receive_bytes(&self) -> ReceiveBytes {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.try_receive_bytes()
+    }
+
+    /// See [`Server::receive_bytes_until`].
+    pub fn receive_bytes_until(&self, deadline: Instant) -> ReceiveBytes {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.receive_bytes_until(deadline)
+    }
+
+    /// See [`Server::try_recv`].
+    pub fn try_recv(&self) -> Result<Option<String>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.try_recv()
+    }
+
+    /// See [`Server::recv_until`].
+    pub fn recv_until(&self, deadline: Instant) -> Result<Option<String>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.recv_until(deadline)
+    }
+
+    /// See [`Server::try_recv_bytes`].
+    pub fn try_recv_bytes(&self) -> Result<Option<Vec<u8>>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.try_recv_bytes()
+    }
+
+    /// See [`Server::recv_bytes_until`].
+    pub fn recv_bytes_until(&self, deadline: Instant) -> Result<Option<Vec<u8>>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.recv_bytes_until(deadline)
+    }
+
+    /// See [`Server::recv_from`].
+    pub fn recv_from(&self, client: ClientId, timeout_ms: Option<u32>) -> Result<Option<Vec<u8>>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.recv_from(client, timeout_ms)
+    }
+
+    /// See [`Server::receive_any`].
+    pub fn receive_any(&self, timeout_ms: Option<u32>) -> Result<Option<(ClientId, Vec<u8>)>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.server.receive_any(timeout_ms)
+    }
+}
+
+/// A [`Client`] that can be shared between threads.
+///
+/// `Client` is `Send` but not `Sync`: two threads sending (or two threads
+/// receiving) at the same time would race on one buffer. `SyncClient`
+/// serializes sends with one lock and receives with another, so one thread
+/// can wait for a message while others keep sending.
+///
+/// Every thread uses the transport, so `SyncClient` is only `Sync` if the transport is.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use windows_shared_memory::{Client, SyncClient};
+///
+/// let client = Arc::new(SyncClient::new(Client::new(None).unwrap()));
+/// let handles: Vec<_> = (0..4)
+///     .map(|i| {
+///         let client = client.clone();
+///         std::thread::spawn(move || client.send_timeout(&[i], None).unwrap())
+///     })
+///     .collect();
+/// ```
+pub struct SyncClient<T: Transport = ShmTransport> {
+    client: Shared<Client<T>>,
+    send_lock: Mutex<()>,
+    recv_lock: Mutex<()>,
+}
+
+impl<T: Transport> SyncClient<T> {
+    /// Wraps a client.
+    pub fn new(client: Client<T>) -> Self {
+        Self {
+            client: Shared(client),
+            send_lock: Mutex::new(()),
+            recv_lock: Mutex::new(()),
+        }
+    }
+
+    /// Returns the underlying client.
+    pub fn into_inner(self) -> Client<T> {
+        self.client.0
+    }
+
+    /// Returns the size of the buffer this client sends through (client to server,
+    /// set by the server).
+    pub fn buffer_size(&self) -> usize {
+        self.client.buffer_size()
+    }
+
+    /// Returns the buffer sizes of both directions (set by the server).
+    pub fn buffer_sizes(&self) -> BufferSizes {
+        self.client.buffer_sizes()
+    }
+
+    /// Returns the largest message that can be sent without fragmentation.
+    pub fn max_message_len(&self) -> usize {
+        self.client.max_message_len()
+    }
+
+    /// Returns the client slot this client claimed on the server.
+    pub fn id(&self) -> ClientId {
+        self.client.id()
+    }
+
+    /// Returns the channel mode for this client (set by the server).
+    pub fn mode(&self) -> ChannelMode {
+        self.client.mode()
+    }
+
+    /// See [`Client::is_peer_alive`].
+    pub fn is_peer_alive(&self) -> bool {
+        self.client.is_peer_alive()
+    }
+
+    /// See [`Client::send_close`].
+    pub fn send_close(&self) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.client.send_close()
+    }
+
+    /// See [`Client::close`].
+    pub fn close(&self, timeout_ms: Option<u32>) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.client.close(timeout_ms)
+    }
+
+    /// See [`Client::send`].
+    pub fn send(&self, data: &[u8]) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.client.send(data)
+    }
+
+    /// See [`Client::send_vectored`].
+    pub fn send_vectored(&self, bufs: &[IoSlice<'_>]) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.client.send_vectored(bufs)
+    }
+
+    /// See [`Client::try_send`].
+    pub fn try_send(&self, data: &[u8]) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.client.try_send(data)
+    }
+
+    /// See [`Client::send_timeout`]. Other senders wait while this one waits for the server.
+    pub fn send_timeout(&self, data: &[u8], timeout_ms: Option<u32>) -> Result<()> {
+        let _guard = self.send_lock.lock().unwrap();
+        self.client.send_timeout(data, timeout_ms)
+    }
+
+    /// See [`Client::receive`]. Other receivers wait while this one waits for a message.
+    pub fn receive(&self, timeout_ms: Option<u32>) -> ReceiveMessage {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.receive(timeout_ms)
+    }
+
+    /// See [`Client::receive_bytes`].
+    pub fn receive_bytes(&self, timeout_ms: Option<u32>) -> ReceiveBytes {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.receive_bytes(timeout_ms)
+    }
+
+    /// See [`Client::recv`].
+    pub fn recv(&self, timeout_ms: Option<u32>) -> Result<Option<String>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.recv(timeout_ms)
+    }
+
+    /// See [`Client::recv_bytes`].
+    pub fn recv_bytes(&self, timeout_ms: Option<u32>) -> Result<Option<Vec<u8>>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.recv_bytes(timeout_ms)
+    }
+
+    /// See [`Client::receive_into`].
+    pub fn receive_into(&self, buffer: &mut Vec<u8>, timeout_ms: Option<u32>) -> Result<Option<usize>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.receive_into(buffer, timeout_ms)
+    }
+
+    /// See [`Client::receive_into_slice`].
+    pub fn receive_into_slice(&self, buffer: &mut [u8], timeout_ms: Option<u32>) -> Result<Option<usize>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.receive_into_slice(buffer, timeout_ms)
+    }
+
+    /// See [`Client::try_receive`].
+    pub fn try_receive(&self) -> ReceiveMessage {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.try_receive()
+    }
+
+    /// See [`Client::receive_until`].
+    pub fn receive_until(&self, deadline: Instant) -> ReceiveMessage {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.receive_until(deadline)
+    }
+
+    /// See [`Client::try_receive_bytes`].
+    pub fn try_receive_bytes(&self) -> ReceiveBytes {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.try_receive_bytes()
+    }
+
+    /// See [`Client::receive_bytes_until`].
+    pub fn receive_bytes_until(&self, deadline: Instant) -> ReceiveBytes {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.receive_bytes_until(deadline)
+    }
+
+    /// See [`Client::try_recv`].
+    pub fn try_recv(&self) -> Result<Option<String>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.try_recv()
+    }
+
+    /// See [`Client::recv_until`].
+    pub fn recv_until(&self, deadline: Instant) -> Result<Option<String>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.recv_until(deadline)
+    }
+
+    /// See [`Client::try_recv_bytes`].
+    pub fn try_recv_bytes(&self) -> Result<Option<Vec<u8>>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.try_recv_bytes()
+    }
+
+    /// See [`Client::recv_bytes_until`].
+    pub fn recv_bytes_until(&self, deadline: Instant) -> Result<Option<Vec<u8>>> {
+        let _guard = self.recv_lock.lock().unwrap();
+        self.client.recv_bytes_until(deadline)
+    }
+}
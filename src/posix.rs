@@ -0,0 +1,287 @@
+use crate::{Error, Result};
+use std::ffi::CString;
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+/// Converts a Windows-style object name into a POSIX shared memory name.
+///
+/// The `Local\` and `Global\` namespace prefixes are dropped and any remaining
+/// path separators are replaced, so `Local\MySharedMemory` becomes `/MySharedMemory`.
+pub(crate) fn posix_name(name: &str) -> Result<CString> {
+    let name = name
+        .strip_prefix("Local\\")
+        .or_else(|| name.strip_prefix("Global\\"))
+        .unwrap_or(name);
+    let name = format!("/{}", name.replace(['\\', '/'], "_"));
+
+    CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e).into())
+}
+
+/// Returns false if no process with ID `pid` exists.
+pub(crate) fn process_alive(pid: u32) -> bool {
+    // Signal 0 only checks whether the process exists; EPERM means it exists
+    // but belongs to another user
+    let exists = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+    exists || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Returns the milliseconds since an arbitrary point that all processes share.
+///
+/// The clock is monotonic, so heartbeats survive changes of the wall-clock time.
+pub(crate) fn monotonic_ms() -> u64 {
+    let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // CLOCK_MONOTONIC cannot fail with a valid pointer
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    now.tv_sec as u64 * 1000 + now.tv_nsec as u64 / 1_000_000
+}
+
+/// Access rights of newly created shared memory objects.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Security {
+    /// Permission bits, owner read/write (0o600) if None
+    pub(crate) permissions: Option<u32>,
+}
+
+/// A named POSIX shared memory object and its mapping.
+pub(crate) struct Mapping {
+    ptr: *mut u8,
+    size: usize,
+    fd: libc::c_int,
+    name: CString,
+    owner: bool,
+}
+
+// The mapping and its descriptor are not tied to the thread that created them
+unsafe impl Send for Mapping {}
+// Shared references only read the pointer; the memory behind it is accessed atomically
+// or under the protocol's flags
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    /// Creates (or opens, if it already exists) a named object and maps `size` bytes.
+    ///
+    /// The object is unlinked when the returned mapping is dropped.
+    pub(crate) fn create(name: &str, size: usize, exclusive: bool, security: &Security) -> Result<Self> {
+        let name = posix_name(name)?;
+        let flags = libc::O_CREAT | libc::O_RDWR | if exclusive { libc::O_EXCL } else { 0 };
+        let mode = security.permissions.unwrap_or(0o600) as libc::mode_t;
+        let fd = unsafe { libc::shm_open(name.as_ptr(), flags, mode) };
+        if fd < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::AlreadyExists {
+                return Err(Error::AlreadyExists);
+            }
+            return Err(err.into());
+        }
+
+        // shm_open applies the umask, so explicit permissions are set again
+        if security.permissions.is_some() && unsafe { libc::fchmod(fd, mode) } < 0 {
+            let err = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(err.into());
+        }
+
+        // Only ever grow the object, a peer may already have a larger view mapped
+        if let Err(e) = Self::object_size(fd).and_then(|current| {
+            if current < size && unsafe { libc::ftruncate(fd, size as libc::off_t) } < 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            Ok(())
+        }) {
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+
+        Self::map(fd, name, size, true)
+    }
+
+    /// Opens an existing named object and maps `size` bytes of it.
+    pub(crate) fn open(name: &str, size: usize) -> Result<Self> {
+        let name = posix_name(name)?;
+        let fd = unsafe { libc::shm_open(name.as_ptr(), libc::O_RDWR, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Self::map(fd, name, size, false)
+    }
+
+    fn object_size(fd: libc::c_int) -> Result<usize> {
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(stat.st_size as usize)
+    }
+
+    fn map_view(fd: libc::c_int, size: usize) -> Result<*mut u8> {
+        // Touching pages past the end of the object raises SIGBUS, refuse instead
+        if Self::object_size(fd)? < size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "shared memory object is smaller than the requested view",
+            )
+            .into());
+        }
+
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(ptr as *mut u8)
+    }
+
+    fn map(fd: libc::c_int, name: CString, size: usize, owner: bool) -> Result<Self> {
+        match Self::map_view(fd, size) {
+            Ok(ptr) => Ok(Self {
+                ptr,
+                size,
+                fd,
+                name,
+                owner,
+            }),
+            Err(e) => {
+                unsafe {
+                    libc::close(fd);
+                    if owner {
+                        libc::shm_unlink(name.as_ptr());
+                    }
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Replaces the current mapping with a mapping of `size` bytes.
+    pub(crate) fn remap(&mut self, size: usize) -> Result<()> {
+        let ptr = Self::map_view(self.fd, size)?;
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.size) };
+        self.ptr = ptr;
+        self.size = size;
+        Ok(())
+    }
+
+    /// Returns the base address of the mapping.
+    pub(crate) fn as_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            if libc::munmap(self.ptr as *mut libc::c_void, self.size) < 0 {
+                eprintln!("Failed to unmap shared memory: {:?}", io::Error::last_os_error());
+            }
+
+            if libc::close(self.fd) < 0 {
+                eprintln!("Failed to close shared memory object: {:?}", io::Error::last_os_error());
+            }
+
+            if self.owner && libc::shm_unlink(self.name.as_ptr()) < 0 {
+                eprintln!("Failed to unlink shared memory object: {:?}", io::Error::last_os_error());
+            }
+        }
+    }
+}
+
+/// A named auto-reset event used to wake the peer process.
+///
+/// The event state is a single word in its own shared memory object, and
+/// waiters sleep on it with a process-shared futex.
+pub struct Event {
+    mapping: Mapping,
+}
+
+impl Event {
+    /// Creates a named event, or opens it if it already exists.
+    pub fn create(name: &str) -> Result<Self> {
+        Self::create_secured(name, &Security::default())
+    }
+
+    /// Creates a named event with the given access rights, or opens it if it already exists.
+    pub(crate) fn create_secured(name: &str, security: &Security) -> Result<Self> {
+        Ok(Self {
+            mapping: Mapping::create(name, std::mem::size_of::<AtomicU32>(), false, security)?,
+        })
+    }
+
+    /// Opens an existing named event.
+    pub fn open(name: &str) -> Result<Self> {
+        Ok(Self {
+            mapping: Mapping::open(name, std::mem::size_of::<AtomicU32>())?,
+        })
+    }
+
+    fn state(&self) -> &AtomicU32 {
+        unsafe { &*(self.mapping.as_ptr() as *const AtomicU32) }
+    }
+
+    /// Signals the event, releasing one waiter.
+    pub fn set(&self) -> Result<()> {
+        self.state().store(1, Ordering::Release);
+        unsafe {
+            libc::syscall(libc::SYS_futex, self.state().as_ptr(), libc::FUTEX_WAKE, 1);
+        }
+        Ok(())
+    }
+
+    /// Waits for the event to be signaled.
+    ///
+    /// Returns `Ok(false)` if the timeout elapsed. `None` waits indefinitely.
+    pub fn wait(&self, timeout_ms: Option<u32>) -> Result<bool> {
+        let deadline = timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms as u64));
+
+        loop {
+            // Auto-reset: consume the signal
+            if self.state().swap(0, Ordering::AcqRel) == 1 {
+                return Ok(true);
+            }
+
+            let timespec = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Ok(false);
+                    }
+                    Some(libc::timespec {
+                        tv_sec: remaining.as_secs() as libc::time_t,
+                        tv_nsec: remaining.subsec_nanos() as libc::c_long,
+                    })
+                }
+                None => None,
+            };
+
+            let ret = unsafe {
+                libc::syscall(
+                    libc::SYS_futex,
+                    self.state().as_ptr(),
+                    libc::FUTEX_WAIT,
+                    0,
+                    timespec
+                        .as_ref()
+                        .map_or(std::ptr::null(), |t| t as *const libc::timespec),
+                )
+            };
+
+            if ret < 0 {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::EAGAIN) | Some(libc::EINTR) | Some(libc::ETIMEDOUT) => {}
+                    _ => return Err(err.into()),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use crate::shared_memory::{deadline, remaining_ms};
+use crate::{Client, Error, ReceiveBytes, ReceiveMessage, Result, ShmTransport, DEFAULT_MAPPING_NAME};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Default delay before the second connection attempt (10 ms)
+pub const DEFAULT_RECONNECT_BACKOFF_MS: u32 = 10;
+
+/// Default upper bound of the delay between connection attempts (1 s)
+pub const DEFAULT_MAX_RECONNECT_BACKOFF_MS: u32 = 1000;
+
+/// How long a wait runs before checking whether the server was restarted (ms)
+const RECONNECT_CHECK_MS: u32 = 100;
+
+type ReconnectCallback = Box<dyn FnMut(&Client) + Send>;
+
+/// Client that reconnects when its server is restarted.
+///
+/// A [`Client`] stays bound to the shared memory of the server it connected to,
+/// so it never sees a server that is started again under the same name. This
+/// wrapper notices when the server was dropped, replaced by a new server
+/// (a different [`generation`](Client::generation)), has died or has sent a close
+/// signal, and then connects again like [`Client::new`].
+///
+/// Connection attempts back off exponentially from
+/// [`DEFAULT_RECONNECT_BACKOFF_MS`] up to [`DEFAULT_MAX_RECONNECT_BACKOFF_MS`],
+/// see [`set_backoff`](Self::set_backoff). Waiting for a new server counts
+/// against the timeout of the call that noticed the restart. Only a missing or
+/// still initializing server is waited for; any other failure to connect, such as
+/// an incompatible header or a full server, is returned right away.
+///
+/// A message sent to a server that goes away before reading it is lost.
+///
+/// # Example
+///
+/// ```no_run
+/// use windows_shared_memory::ReconnectingClient;
+///
+/// let mut client = ReconnectingClient::new(None).unwrap();
+/// client.set_on_reconnect(|_| println!("server restarted"));
+/// loop {
+///     if let Some(message) = client.recv_bytes(None).unwrap() {
+///         client.send(&message).unwrap();
+///     }
+/// }
+/// ```
+pub struct ReconnectingClient {
+    mapping_path: Option<String>,
+    client: Option<Client>,
+    // Generation of the last server this client left, never reconnected to
+    stale_generation: u64,
+    initial_backoff_ms: u32,
+    max_backoff_ms: u32,
+    fragmentation: bool,
+    on_reconnect: Option<ReconnectCallback>,
+}
+
+impl ReconnectingClient {
+    /// Connects to the server, failing like [`Client::new`] if it is not running.
+    ///
+    /// # Arguments
+    ///
+    /// * `mapping_path` - Optional custom path for the shared memory mapping.
+    ///   If None, uses "Local\\MySharedMemory" as default.
+    pub fn new(mapping_path: Option<&str>) -> Result<Self> {
+        let client = Client::new(mapping_path)?;
+        Ok(Self {
+            mapping_path: mapping_path.map(str::to_owned),
+            client: Some(client),
+            stale_generation: 0,
+            initial_backoff_ms: DEFAULT_RECONNECT_BACKOFF_MS,
+            max_backoff_ms: DEFAULT_MAX_RECONNECT_BACKOFF_MS,
+            fragmentation: false,
+            on_reconnect: None,
+        })
+    }
+
+    /// Sets the delay before the second connection attempt and its upper bound.
+    ///
+    /// The delay doubles after every failed attempt. It is at least 1 ms, so that
+    /// waiting for a server never spins.
+    pub fn set_backoff(&mut self, initial_ms: u32, max_ms: u32) {
+        self.initial_backoff_ms = initial_ms.max(1);
+        self.max_backoff_ms = max_ms.max(self.initial_backoff_ms);
+    }
+
+    /// Sets a callback that runs with the new client after every reconnect.
+    pub fn set_on_reconnect<F>(&mut self, callback: F)
+    where
+        F: FnMut(&Client) + Send + 'static,
+    {
+        self.on_reconnect = Some(Box::new(callback));
+    }
+
+    /// Enables or disables fragmentation, see [`Client::set_fragmentation`].
+    ///
+    /// The setting carries over to reconnected clients.
+    pub fn set_fragmentation(&mut self, enabled: bool) {
+        self.fragmentation = enabled;
+        if let Some(client) = &mut self.client {
+            client.set_fragmentation(enabled);
+        }
+    }
+
+    /// Returns the current client, or None while waiting for a new server.
+    pub fn client(&self) -> Option<&Client> {
+        self.client.as_ref()
+    }
+
+    /// Returns true if the client is connected to a running server.
+    pub fn is_connected(&self) -> bool {
+        self.client.as_ref().is_some_and(Client::is_connected)
+    }
+
+    /// Connects to a new server if the current one is gone.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, waits indefinitely.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::Timeout`] if no new server was found in time.
+    pub fn reconnect(&mut self, timeout_ms: Option<u32>) -> Result<()> {
+        match self.connect_until(deadline(timeout_ms))? {
+            Some(_) => Ok(()),
+            None => Err(Error::Timeout),
+        }
+    }
+
+    /// Sends data to the server, waiting indefinitely for a server to connect to first
+    /// if needed.
+    ///
+    /// A server that has sent a close signal is replaced like in [`recv_bytes`](Self::recv_bytes).
+    /// Use [`send_timeout`](Self::send_timeout) to bound the wait. See [`Client::send`].
+    pub fn send(&mut self, data: &[u8]) -> Result<()> {
+        loop {
+            self.disconnect_if_closed();
+            if let Some(client) = self.connect_until(None)? {
+                return client.send(data);
+            }
+        }
+    }
+
+    /// Sends data, waiting until the server has consumed the previous message.
+    ///
+    /// If the server is restarted in the meantime, the data is sent to the new server.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Byte slice to send.
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, waits indefinitely.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::Timeout`] if no server read the previous message in time.
+    pub fn send_timeout(&mut self, data: &[u8], timeout_ms: Option<u32>) -> Result<()> {
+        let deadline = deadline(timeout_ms);
+        loop {
+            self.disconnect_if_closed();
+            let Some(client) = self.connect_until(deadline)? else {
+                return Err(Error::Timeout);
+            };
+            // A fragmented message cannot be retried halfway, so it is sent in one call
+            if data.len() > client.max_message_len() {
+                return client.send_timeout(data, deadline.map(remaining_ms));
+            }
+            match client.send_timeout(data, Some(check_ms(deadline))) {
+                Err(Error::Timeout) if !expired(deadline) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    /// Receives a message from the server.
+    ///
+    /// See [`recv_bytes`](Self::recv_bytes).
+    pub fn receive(&mut self, timeout_ms: Option<u32>) -> ReceiveMessage {
+        self.recv(timeout_ms).into()
+    }
+
+    /// Receives raw bytes from the server.
+    ///
+    /// See [`recv_bytes`](Self::recv_bytes).
+    pub fn receive_bytes(&mut self, timeout_ms: Option<u32>) -> ReceiveBytes {
+        self.recv_bytes(timeout_ms).into()
+    }
+
+    /// Like [`recv_bytes`](Self::recv_bytes), but returns the message as a string.
+    ///
+    /// Returns [`Error::InvalidUtf8`] with the raw bytes if the message is not UTF-8.
+    pub fn recv(&mut self, timeout_ms: Option<u32>) -> Result<Option<String>> {
+        match self.recv_bytes(timeout_ms)? {
+            Some(bytes) => String::from_utf8(bytes)
+                .map(Some)
+                .map_err(|e| Error::InvalidUtf8(e.into_bytes())),
+            None => Ok(None),
+        }
+    }
+
+    /// Receives raw bytes from the server, reconnecting if the server is restarted.
+    ///
+    /// A close signal or the death of the server is not reported; the client
+    /// waits for the next server instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, waits indefinitely.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(None)` on timeout, including when no new server was found in time.
+    pub fn recv_bytes(&mut self, timeout_ms: Option<u32>) -> Result<Option<Vec<u8>>> {
+        let deadline = deadline(timeout_ms);
+        loop {
+            let Some(client) = self.connect_until(deadline)? else {
+                return Ok(None);
+            };
+            let wait_until = Instant::now() + Duration::from_millis(check_ms(deadline) as u64);
+            match client.recv_bytes_until(wait_until) {
+                Ok(None) if expired(deadline) => return Ok(None),
+                Ok(None) => {}
+                Err(Error::PeerClosed | Error::PeerDied) => self.disconnect(),
+                result => return result,
+            }
+        }
+    }
+
+    /// Drops the current client and remembers not to connect to its server again.
+    fn disconnect(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.stale_generation = client.generation();
+        }
+    }
+
+    /// Drops the current client if its server has sent a close signal.
+    ///
+    /// Receiving notices the close signal by itself, after any messages queued before it.
+    fn disconnect_if_closed(&mut self) {
+        if self.client.as_ref().is_some_and(Client::close_requested) {
+            self.disconnect();
+        }
+    }
+
+    /// Returns a connected client, connecting to a new server until `deadline` if needed.
+    ///
+    /// Returns `Ok(None)` on timeout. Only a missing or still initializing server is
+    /// retried, other errors are returned.
+    fn connect_until(&mut self, deadline: Option<Instant>) -> Result<Option<&Client>> {
+        if self.client.as_ref().is_some_and(|client| !client.is_connected()) {
+            self.disconnect();
+        }
+
+        let mapping_name = self.mapping_path.as_deref().unwrap_or(DEFAULT_MAPPING_NAME);
+        let mut backoff_ms = self.initial_backoff_ms;
+        while self.client.is_none() {
+            let connected = if ShmTransport::server_ready(mapping_name)? {
+                match Client::new(Some(mapping_name)) {
+                    // The server went away again in between
+                    Err(_) if !ShmTransport::server_ready(mapping_name)? => None,
+                    result => Some(result?),
+                }
+            } else {
+                None
+            };
+            match connected {
+                // The old server's shared memory may still be around
+                Some(mut client) if client.is_connected() && client.generation() != self.stale_generation => {
+                    client.set_fragmentation(self.fragmentation);
+                    if let Some(on_reconnect) = &mut self.on_reconnect {
+                        on_reconnect(&client);
+                    }
+                    self.client = Some(client);
+                }
+                // Not started yet
+                _ => {
+                    let sleep_ms = match deadline.map(remaining_ms) {
+                        Some(0) => return Ok(None),
+                        Some(remaining) => backoff_ms.min(remaining),
+                        None => backoff_ms,
+                    };
+                    thread::sleep(Duration::from_millis(sleep_ms as u64));
+                    backoff_ms = backoff_ms.saturating_mul(2).min(self.max_backoff_ms);
+                }
+            }
+        }
+        Ok(self.client.as_ref())
+    }
+}
+
+/// Returns how long to wait before checking the connection again.
+fn check_ms(deadline: Option<Instant>) -> u32 {
+    deadline.map_or(RECONNECT_CHECK_MS, |deadline| remaining_ms(deadline).min(RECONNECT_CHECK_MS))
+}
+
+fn expired(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
@@ -0,0 +1,604 @@
+use crate::{Error, Result};
+use std::io::IoSlice;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Size of the length prefix in front of every record
+const LEN_PREFIX: usize = std::mem::size_of::<u32>();
+
+/// Length prefix bit set on every record of a fragmented message except the last
+const MORE: u32 = 1 << 31;
+
+/// Length prefix bit set on every record of a fragmented message except the first
+const CONTINUATION: u32 = 1 << 30;
+
+/// Length prefix bits holding the payload length
+const LEN_MASK: u32 = CONTINUATION - 1;
+
+/// Size of the total message length at the start of the first record of a fragmented message
+pub(crate) const TOTAL_LEN_PREFIX: usize = std::mem::size_of::<u32>();
+
+/// One record popped from the ring.
+pub(crate) struct Record {
+    pub(crate) data: Vec<u8>,
+    /// This record starts a message
+    pub(crate) first: bool,
+    /// This record ends a message
+    pub(crate) last: bool,
+}
+
+/// Free space at the head of the ring for one record, see [`Ring::reserve`].
+pub(crate) enum Reservation {
+    /// Not enough free space
+    Full,
+    /// The payload would wrap around the end, so it has to be pushed as a copy
+    Wrapped,
+    /// The payload can be written in place here and published with [`Ring::commit`]
+    InPlace(*mut u8),
+}
+
+/// The oldest record of the ring, see [`Ring::peek`].
+pub(crate) enum Peek<'a> {
+    /// The ring is empty
+    Empty,
+    /// A complete message stored without wrapping, released with [`Ring::discard`]
+    InPlace(&'a [u8]),
+    /// A fragment or a message wrapping around the end, which has to be popped as a copy
+    Split,
+}
+
+/// Lock-free single-producer single-consumer queue of length-prefixed records.
+///
+/// `head` is the write offset owned by the producer and `tail` is the read
+/// offset owned by the consumer, both in `0..capacity`. The ring is empty when
+/// they are equal, so one byte always stays unused. Records may wrap around
+/// the end of the buffer.
+///
+/// Both offsets and every length prefix come from memory the peer can write,
+/// so they are checked before use and [`Error::Corrupt`] is returned instead of
+/// touching anything outside the buffer.
+pub(crate) struct Ring<'a> {
+    head: &'a AtomicU32,
+    tail: &'a AtomicU32,
+    buffer: *mut u8,
+    capacity: usize,
+    /// Zero every record before moving the tail past it
+    scrub: bool,
+}
+
+impl<'a> Ring<'a> {
+    /// Creates a view of a ring stored in `buffer`.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must be valid for reads and writes of `capacity` bytes for `'a`,
+    /// and at most one producer and one consumer may use the ring at a time.
+    pub(crate) unsafe fn new(
+        head: &'a AtomicU32,
+        tail: &'a AtomicU32,
+        buffer: *mut u8,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            head,
+            tail,
+            buffer,
+            capacity,
+            scrub: false,
+        }
+    }
+
+    /// Makes the consumer zero every record it removes.
+    pub(crate) fn scrub_after_read(mut self, scrub: bool) -> Self {
+        self.scrub = scrub;
+        self
+    }
+
+    /// Smallest capacity whose first record of a fragmented message still carries a byte
+    /// of the message after its total length.
+    pub(crate) const MIN_CAPACITY: usize = LEN_PREFIX + TOTAL_LEN_PREFIX + 2;
+
+    /// Returns the largest payload a ring of `capacity` bytes can hold in one record.
+    pub(crate) fn max_message_len(capacity: usize) -> usize {
+        capacity.saturating_sub(LEN_PREFIX + 1).min(LEN_MASK as usize)
+    }
+
+    fn used(&self, head: usize, tail: usize) -> usize {
+        (head + self.capacity - tail) % self.capacity
+    }
+
+    /// Loads the head and tail, checking that both lie inside the buffer.
+    fn offsets(&self) -> Result<(usize, usize)> {
+        let head = self.head.load(Ordering::Acquire) as usize;
+        let tail = self.tail.load(Ordering::Acquire) as usize;
+        // An empty ring of capacity 0 has both offsets at 0
+        let limit = self.capacity.max(1);
+        if head >= limit || tail >= limit {
+            return Err(Error::Corrupt(format!(
+                "ring offsets head {} and tail {} outside a buffer of {} bytes",
+                head, tail, self.capacity
+            )));
+        }
+        Ok((head, tail))
+    }
+
+    /// Appends a complete message. Returns `false` if there is not enough free space.
+    pub(crate) fn push(&self, data: &[u8]) -> Result<bool> {
+        self.push_record(data, true, true)
+    }
+
+    /// Appends one record of a possibly fragmented message.
+    /// Returns `false` if there is not enough free space.
+    pub(crate) fn push_record(&self, data: &[u8], first: bool, last: bool) -> Result<bool> {
+        self.push_slices(&[IoSlice::new(data)], first, last)
+    }
+
+    /// Appends one record whose payload is `parts` one after another.
+    /// Returns `false` if there is not enough free space.
+    pub(crate) fn push_slices(&self, parts: &[IoSlice<'_>], first: bool, last: bool) -> Result<bool> {
+        let data_len: usize = parts.iter().map(|part| part.len()).sum();
+        if self.capacity == 0 || data_len > LEN_MASK as usize {
+            return Ok(false);
+        }
+
+        let (head, tail) = self.offsets()?;
+        let free = self.capacity - 1 - self.used(head, tail);
+        if LEN_PREFIX + data_len > free {
+            return Ok(false);
+        }
+
+        let mut len = data_len as u32;
+        if !first {
+            len |= CONTINUATION;
+        }
+        if !last {
+            len |= MORE;
+        }
+
+        let len = len.to_ne_bytes();
+        let mut offset = self.write_at(head, &len);
+        for part in parts {
+            offset = self.write_at(offset, part);
+        }
+
+        self.head.store(offset as u32, Ordering::Release);
+        Ok(true)
+    }
+
+    /// Finds room for a complete message of `len` bytes without writing anything.
+    ///
+    /// The room stays free until the producer pushes or commits, since the
+    /// consumer only ever frees space.
+    pub(crate) fn reserve(&self, len: usize) -> Result<Reservation> {
+        if self.capacity == 0 || len > LEN_MASK as usize {
+            return Ok(Reservation::Full);
+        }
+
+        let (head, tail) = self.offsets()?;
+        let free = self.capacity - 1 - self.used(head, tail);
+        if LEN_PREFIX + len > free {
+            return Ok(Reservation::Full);
+        }
+
+        let start = (head + LEN_PREFIX) % self.capacity;
+        if start + len > self.capacity {
+            return Ok(Reservation::Wrapped);
+        }
+        Ok(Reservation::InPlace(unsafe { self.buffer.add(start) }))
+    }
+
+    /// Publishes a complete message of `len` bytes written in place after [`reserve`](Self::reserve).
+    pub(crate) fn commit(&self, len: usize) -> Result<()> {
+        let (head, _) = self.offsets()?;
+        let offset = self.write_at(head, &(len as u32).to_ne_bytes());
+        self.head.store(((offset + len) % self.capacity) as u32, Ordering::Release);
+        Ok(())
+    }
+
+    /// Reads the length prefix of the oldest record, or returns `None` if the ring is empty.
+    ///
+    /// Returns the tail, the prefix and the offset of the payload.
+    fn front(&self) -> Result<Option<(usize, u32, usize)>> {
+        let (head, tail) = self.offsets()?;
+        if head == tail {
+            return Ok(None);
+        }
+
+        let used = self.used(head, tail);
+        if used < LEN_PREFIX {
+            return Err(Error::Corrupt(format!("ring holds {} bytes, less than a length prefix", used)));
+        }
+        let mut len = [0u8; LEN_PREFIX];
+        let start = self.read_at(tail, &mut len);
+        let len = u32::from_ne_bytes(len);
+        if LEN_PREFIX + (len & LEN_MASK) as usize > used {
+            return Err(Error::Corrupt(format!(
+                "ring record of {} bytes is longer than the {} bytes queued",
+                len & LEN_MASK,
+                used - LEN_PREFIX
+            )));
+        }
+        Ok(Some((tail, len, start)))
+    }
+
+    /// Removes the oldest record, or returns `None` if the ring is empty.
+    pub(crate) fn pop(&self) -> Result<Option<Record>> {
+        let Some((tail, len, start)) = self.front()? else {
+            return Ok(None);
+        };
+
+        let mut data = vec![0u8; (len & LEN_MASK) as usize];
+        self.read_at(start, &mut data);
+
+        self.release(tail, LEN_PREFIX + data.len());
+        Ok(Some(Record {
+            data,
+            first: len & CONTINUATION == 0,
+            last: len & MORE == 0,
+        }))
+    }
+
+    /// Returns the oldest record without removing it. Only the consumer may call this.
+    pub(crate) fn peek(&self) -> Result<Peek<'a>> {
+        let Some((_, len, start)) = self.front()? else {
+            return Ok(Peek::Empty);
+        };
+        if len & (MORE | CONTINUATION) != 0 {
+            return Ok(Peek::Split);
+        }
+
+        let len = (len & LEN_MASK) as usize;
+        if start + len > self.capacity {
+            return Ok(Peek::Split);
+        }
+        Ok(Peek::InPlace(unsafe { std::slice::from_raw_parts(self.buffer.add(start), len) }))
+    }
+
+    /// Returns the length of the message starting with the oldest record without removing it.
+    ///
+    /// Returns `None` if the ring is empty or the oldest record continues a message whose
+    /// start was missed.
+    pub(crate) fn peek_len(&self) -> Result<Option<usize>> {
+        let Some((_, len, start)) = self.front()? else {
+            return Ok(None);
+        };
+        if len & CONTINUATION != 0 {
+            return Ok(None);
+        }
+        if len & MORE == 0 {
+            return Ok(Some((len & LEN_MASK) as usize));
+        }
+
+        if ((len & LEN_MASK) as usize) < TOTAL_LEN_PREFIX {
+            return Err(Error::Corrupt(format!(
+                "first fragment of {} bytes has no total length",
+                len & LEN_MASK
+            )));
+        }
+        let mut total = [0u8; TOTAL_LEN_PREFIX];
+        self.read_at(start, &mut total);
+        Ok(Some(u32::from_ne_bytes(total) as usize))
+    }
+
+    /// Removes the records at the front that continue a message whose start was missed.
+    pub(crate) fn drop_stray(&self) -> Result<()> {
+        while let Some((tail, len, _)) = self.front()?
+            && len & CONTINUATION != 0
+        {
+            self.release(tail, LEN_PREFIX + (len & LEN_MASK) as usize);
+        }
+        Ok(())
+    }
+
+    /// Removes the oldest record after [`peek`](Self::peek) returned its `len` byte payload.
+    ///
+    /// Does nothing if the offsets were corrupted since, the next `peek` reports it.
+    pub(crate) fn discard(&self, len: usize) {
+        if let Ok((head, tail)) = self.offsets()
+            && LEN_PREFIX + len <= self.used(head, tail)
+        {
+            self.release(tail, LEN_PREFIX + len);
+        }
+    }
+
+    /// Moves the tail `len` bytes past `tail`, zeroing them first when scrubbing.
+    fn release(&self, tail: usize, len: usize) {
+        if self.scrub {
+            let first = len.min(self.capacity - tail);
+            unsafe {
+                std::ptr::write_bytes(self.buffer.add(tail), 0, first);
+                std::ptr::write_bytes(self.buffer, 0, len - first);
+            }
+        }
+        self.tail.store(((tail + len) % self.capacity) as u32, Ordering::Release);
+    }
+
+    /// Discards all queued records. Only the consumer may call this.
+    pub(crate) fn clear(&self) {
+        self.tail.store(self.head.load(Ordering::Acquire), Ordering::Release);
+    }
+
+    /// Copies `data` in at `offset`, wrapping at the end. Returns the next offset.
+    fn write_at(&self, offset: usize, data: &[u8]) -> usize {
+        let first = data.len().min(self.capacity - offset);
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), self.buffer.add(offset), first);
+            std::ptr::copy_nonoverlapping(data[first..].as_ptr(), self.buffer, data.len() - first);
+        }
+        (offset + data.len()) % self.capacity
+    }
+
+    /// Copies out `data.len()` bytes from `offset`, wrapping at the end. Returns the next offset.
+    fn read_at(&self, offset: usize, data: &mut [u8]) -> usize {
+        let first = data.len().min(self.capacity - offset);
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.buffer.add(offset), data.as_mut_ptr(), first);
+            std::ptr::copy_nonoverlapping(self.buffer, data[first..].as_mut_ptr(), data.len() - first);
+        }
+        (offset + data.len()) % self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Region {
+        head: AtomicU32,
+        tail: AtomicU32,
+        buffer: Vec<u8>,
+    }
+
+    impl Region {
+        fn new(capacity: usize) -> Self {
+            Self {
+                head: AtomicU32::new(0),
+                tail: AtomicU32::new(0),
+                buffer: vec![0; capacity],
+            }
+        }
+
+        fn ring(&mut self) -> Ring<'_> {
+            let capacity = self.buffer.len();
+            unsafe { Ring::new(&self.head, &self.tail, self.buffer.as_mut_ptr(), capacity) }
+        }
+    }
+
+    #[test]
+    fn push_pop_fifo() {
+        let mut region = Region::new(64);
+        let ring = region.ring();
+
+        assert!(ring.push(b"one").unwrap());
+        assert!(ring.push(b"two").unwrap());
+        assert!(ring.push(b"").unwrap());
+        assert!(ring.push(b"three").unwrap());
+
+        assert_eq!(ring.pop().unwrap().unwrap().data, b"one");
+        assert_eq!(ring.pop().unwrap().unwrap().data, b"two");
+        assert_eq!(ring.pop().unwrap().unwrap().data, b"");
+        assert_eq!(ring.pop().unwrap().unwrap().data, b"three");
+        assert!(ring.pop().unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_when_full() {
+        let mut region = Region::new(32);
+        let ring = region.ring();
+
+        // 4 + 10 bytes per record, 31 usable bytes
+        assert!(ring.push(&[1; 10]).unwrap());
+        assert!(ring.push(&[2; 10]).unwrap());
+        assert!(!ring.push(&[3; 10]).unwrap());
+
+        assert_eq!(ring.pop().unwrap().unwrap().data, vec![1; 10]);
+        assert!(ring.push(&[3; 10]).unwrap());
+        assert_eq!(ring.pop().unwrap().unwrap().data, vec![2; 10]);
+        assert_eq!(ring.pop().unwrap().unwrap().data, vec![3; 10]);
+    }
+
+    #[test]
+    fn max_message_fits_only_when_empty() {
+        let mut region = Region::new(32);
+        let ring = region.ring();
+        let max = Ring::max_message_len(32);
+
+        assert!(!ring.push(&vec![0; max + 1]).unwrap());
+        assert!(ring.push(&vec![7; max]).unwrap());
+        assert!(!ring.push(b"").unwrap());
+        assert_eq!(ring.pop().unwrap().unwrap().data, vec![7; max]);
+        assert!(ring.pop().unwrap().is_none());
+    }
+
+    #[test]
+    fn records_wrap_around_the_end() {
+        let mut region = Region::new(29);
+        let ring = region.ring();
+
+        for round in 0..100u8 {
+            let a: Vec<u8> = (0..(round % 9)).map(|i| i ^ round).collect();
+            let b: Vec<u8> = (0..(round % 7 + 3)).map(|i| i.wrapping_mul(round)).collect();
+            assert!(ring.push(&a).unwrap());
+            assert!(ring.push(&b).unwrap());
+            assert_eq!(ring.pop().unwrap().unwrap().data, a);
+            assert_eq!(ring.pop().unwrap().unwrap().data, b);
+        }
+        assert!(ring.pop().unwrap().is_none());
+    }
+
+    #[test]
+    fn record_flags_round_trip() {
+        let mut region = Region::new(64);
+        let ring = region.ring();
+
+        assert!(ring.push_record(b"ab", true, false).unwrap());
+        assert!(ring.push_record(b"cd", false, false).unwrap());
+        assert!(ring.push_record(b"ef", false, true).unwrap());
+
+        let flags: Vec<_> = std::iter::from_fn(|| ring.pop().unwrap())
+            .map(|record| (record.data, record.first, record.last))
+            .collect();
+        assert_eq!(
+            flags,
+            vec![
+                (b"ab".to_vec(), true, false),
+                (b"cd".to_vec(), false, false),
+                (b"ef".to_vec(), false, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn push_slices_gathers_parts() {
+        let mut region = Region::new(32);
+        let ring = region.ring();
+
+        assert!(ring.push(&[0; 18]).unwrap());
+        assert_eq!(ring.pop().unwrap().unwrap().data, vec![0; 18]);
+
+        // Starts 22 bytes in, so the second part wraps around the end
+        let parts = [IoSlice::new(b"head"), IoSlice::new(b""), IoSlice::new(b"payload")];
+        assert!(ring.push_slices(&parts, true, true).unwrap());
+        assert_eq!(ring.pop().unwrap().unwrap().data, b"headpayload".to_vec());
+
+        let too_long = [IoSlice::new(&[1; 16]), IoSlice::new(&[2; 12])];
+        assert!(!ring.push_slices(&too_long, true, true).unwrap());
+    }
+
+    #[test]
+    fn reserve_in_place_then_commit() {
+        let mut region = Region::new(32);
+        let ring = region.ring();
+
+        let Reservation::InPlace(ptr) = ring.reserve(5).unwrap() else {
+            panic!("expected room in place");
+        };
+        // Nothing is visible before the commit
+        assert!(ring.pop().unwrap().is_none());
+        unsafe { std::ptr::copy_nonoverlapping(b"hello".as_ptr(), ptr, 5) };
+        ring.commit(5).unwrap();
+
+        let record = ring.pop().unwrap().unwrap();
+        assert_eq!(record.data, b"hello");
+        assert!(record.first && record.last);
+        assert!(matches!(ring.reserve(Ring::max_message_len(32) + 1).unwrap(), Reservation::Full));
+    }
+
+    #[test]
+    fn reserve_reports_wrapped_payload() {
+        let mut region = Region::new(32);
+        let ring = region.ring();
+
+        // Leaves the head 22 bytes in, so a 10 byte payload would wrap
+        assert!(ring.push(&[1; 18]).unwrap());
+        assert_eq!(ring.pop().unwrap().unwrap().data, vec![1; 18]);
+        assert!(matches!(ring.reserve(10).unwrap(), Reservation::Wrapped));
+        assert!(matches!(ring.reserve(6).unwrap(), Reservation::InPlace(_)));
+    }
+
+    #[test]
+    fn peek_borrows_contiguous_messages() {
+        let mut region = Region::new(32);
+        let ring = region.ring();
+
+        assert!(matches!(ring.peek().unwrap(), Peek::Empty));
+        assert!(ring.push(&[1; 18]).unwrap());
+        let Peek::InPlace(data) = ring.peek().unwrap() else {
+            panic!("expected a message in place");
+        };
+        assert_eq!(data, &[1; 18]);
+        ring.discard(data.len());
+
+        // Starts 26 bytes in and wraps around the end
+        assert!(ring.push(&[2; 10]).unwrap());
+        assert!(matches!(ring.peek().unwrap(), Peek::Split));
+        assert_eq!(ring.pop().unwrap().unwrap().data, vec![2; 10]);
+
+        assert!(ring.push_record(b"ab", true, false).unwrap());
+        assert!(matches!(ring.peek().unwrap(), Peek::Split));
+    }
+
+    #[test]
+    fn peek_len_reads_total_of_fragmented_messages() {
+        let mut region = Region::new(64);
+        let ring = region.ring();
+
+        assert_eq!(ring.peek_len().unwrap(), None);
+        assert!(ring.push(&[1; 10]).unwrap());
+        assert_eq!(ring.peek_len().unwrap(), Some(10));
+        ring.pop().unwrap();
+
+        let total = 100u32.to_ne_bytes();
+        assert!(ring.push_slices(&[IoSlice::new(&total), IoSlice::new(&[2; 8])], true, false).unwrap());
+        assert_eq!(ring.peek_len().unwrap(), Some(100));
+        ring.pop().unwrap();
+
+        assert!(ring.push_record(&[3; 8], false, true).unwrap());
+        assert_eq!(ring.peek_len().unwrap(), None);
+    }
+
+    #[test]
+    fn drop_stray_removes_leading_continuations() {
+        let mut region = Region::new(64);
+        let ring = region.ring();
+
+        assert!(ring.push_record(&[1; 8], false, false).unwrap());
+        assert!(ring.push_record(&[2; 8], false, true).unwrap());
+        assert!(ring.push(b"next").unwrap());
+        ring.drop_stray().unwrap();
+        assert_eq!(ring.peek_len().unwrap(), Some(4));
+        assert_eq!(ring.pop().unwrap().unwrap().data, b"next");
+
+        // A message that starts at the front is kept
+        assert!(ring.push_record(&[3; 8], true, false).unwrap());
+        ring.drop_stray().unwrap();
+        assert!(ring.pop().unwrap().unwrap().first);
+    }
+
+    #[test]
+    fn scrub_zeroes_released_records() {
+        let mut region = Region::new(32);
+        {
+            let ring = region.ring().scrub_after_read(true);
+            assert!(ring.push(&[1; 18]).unwrap());
+            let Peek::InPlace(data) = ring.peek().unwrap() else {
+                panic!("expected a message in place");
+            };
+            ring.discard(data.len());
+
+            // Starts 22 bytes in and wraps around the end
+            assert!(ring.push(&[2; 10]).unwrap());
+            assert_eq!(ring.pop().unwrap().unwrap().data, vec![2; 10]);
+        }
+        assert!(region.buffer.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn rejects_corrupt_offsets_and_lengths() {
+        let mut region = Region::new(32);
+        region.tail.store(40, Ordering::Relaxed);
+        {
+            let ring = region.ring();
+            assert!(matches!(ring.pop(), Err(Error::Corrupt(_))));
+            assert!(matches!(ring.peek(), Err(Error::Corrupt(_))));
+            assert!(matches!(ring.push(b"x"), Err(Error::Corrupt(_))));
+            assert!(matches!(ring.reserve(1), Err(Error::Corrupt(_))));
+        }
+
+        region.tail.store(0, Ordering::Relaxed);
+        assert!(region.ring().push(b"abc").unwrap());
+        // The length prefix claims more than is queued
+        region.buffer[..LEN_PREFIX].copy_from_slice(&1000u32.to_ne_bytes());
+        let ring = region.ring();
+        assert!(matches!(ring.pop(), Err(Error::Corrupt(_))));
+        assert!(matches!(ring.peek(), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn zero_capacity_never_accepts() {
+        let mut region = Region::new(0);
+        let ring = region.ring();
+
+        assert!(!ring.push(b"").unwrap());
+        assert!(ring.pop().unwrap().is_none());
+    }
+}
@@ -0,0 +1,281 @@
+use crate::shared_memory::{deadline, remaining_ms};
+use crate::{Client, ClientId, Error, Result, Server, Shared, ShmTransport, Transport};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Size of the frame header: correlation ID (u64) and frame kind (u8)
+const FRAME_HEADER: usize = 9;
+
+/// Default time [`RpcServer`] waits for a client to read the previous reply (5 s)
+pub const DEFAULT_REPLY_TIMEOUT_MS: u32 = 5000;
+
+// Frame kinds
+const KIND_REQUEST: u8 = 0;
+const KIND_REPLY: u8 = 1;
+const KIND_ERROR: u8 = 2;
+
+fn encode_frame(id: u64, kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(FRAME_HEADER + payload.len());
+    frame.extend_from_slice(&id.to_le_bytes());
+    frame.push(kind);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn decode_frame(frame: &[u8]) -> Option<(u64, u8, &[u8])> {
+    if frame.len() < FRAME_HEADER {
+        return None;
+    }
+    let id = u64::from_le_bytes(frame[..8].try_into().unwrap());
+    Some((id, frame[8], &frame[FRAME_HEADER..]))
+}
+
+/// Replies read by one caller on behalf of the others.
+#[derive(Default)]
+struct Pending {
+    /// A caller is currently reading replies from the client
+    reading: bool,
+    /// Calls still waiting for their reply
+    waiting: HashSet<u64>,
+    replies: HashMap<u64, Result<Vec<u8>>>,
+}
+
+/// Request/response client on top of a [`Client`].
+///
+/// Every request carries a correlation ID that the server echoes in its
+/// reply, so several threads can have calls outstanding at the same time.
+/// Whichever caller is waiting reads replies for all of them.
+///
+/// # Example
+///
+/// ```no_run
+/// use windows_shared_memory::{Client, RpcClient};
+///
+/// let rpc = RpcClient::new(Client::new(None).unwrap());
+/// let reply = rpc.call(b"ping", Some(1000)).unwrap();
+/// ```
+pub struct RpcClient<T: Transport = ShmTransport> {
+    // Sends are serialized by `send_lock`, and only the caller that set `Pending::reading` receives
+    client: Shared<Client<T>>,
+    next_id: AtomicU64,
+    send_lock: Mutex<()>,
+    pending: Mutex<Pending>,
+    replies: Condvar,
+}
+
+impl<T: Transport> RpcClient<T> {
+    /// Wraps a connected client. Fragmentation is enabled so requests may be
+    /// larger than the buffer.
+    pub fn new(mut client: Client<T>) -> Self {
+        client.set_fragmentation(true);
+        Self {
+            client: Shared(client),
+            next_id: AtomicU64::new(0),
+            send_lock: Mutex::new(()),
+            pending: Mutex::new(Pending::default()),
+            replies: Condvar::new(),
+        }
+    }
+
+    /// Returns the underlying client.
+    pub fn into_inner(self) -> Client<T> {
+        self.client.0
+    }
+
+    /// Sends a request and waits for its reply.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Request payload.
+    /// * `timeout_ms` - Optional timeout in milliseconds for the whole call. If None, waits indefinitely.
+    ///
+    /// # Returns
+    ///
+    /// Returns the reply payload, [`Error::Remote`] if the handler failed, or
+    /// [`Error::Timeout`] if no reply arrived in time.
+    pub fn call(&self, request: &[u8], timeout_ms: Option<u32>) -> Result<Vec<u8>> {
+        let deadline = deadline(timeout_ms);
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.pending.lock().unwrap().waiting.insert(id);
+
+        let sent = {
+            let _guard = self.send_lock.lock().unwrap();
+            self.client.send_timeout(&encode_frame(id, KIND_REQUEST, request), deadline.map(remaining_ms))
+        };
+        if let Err(e) = sent {
+            self.pending.lock().unwrap().waiting.remove(&id);
+            return Err(e);
+        }
+
+        let result = self.wait_reply(id, deadline);
+        if result.is_err() {
+            self.pending.lock().unwrap().waiting.remove(&id);
+        }
+        result
+    }
+
+    fn wait_reply(&self, id: u64, deadline: Option<Instant>) -> Result<Vec<u8>> {
+        loop {
+            // Wait until the reply arrives or no one else is reading
+            let mut pending = self.pending.lock().unwrap();
+            loop {
+                if let Some(reply) = pending.replies.remove(&id) {
+                    return reply;
+                }
+                let timeout = deadline.map(remaining_ms);
+                if timeout == Some(0) {
+                    return Err(Error::Timeout);
+                }
+                if !pending.reading {
+                    pending.reading = true;
+                    break;
+                }
+                pending = match timeout {
+                    Some(ms) => {
+                        let timeout = Duration::from_millis(ms as u64);
+                        self.replies.wait_timeout(pending, timeout).unwrap().0
+                    }
+                    None => self.replies.wait(pending).unwrap(),
+                };
+            }
+            drop(pending);
+
+            let received = self.client.recv_bytes(deadline.map(remaining_ms));
+
+            let mut pending = self.pending.lock().unwrap();
+            pending.reading = false;
+            self.replies.notify_all();
+
+            let Some(frame) = received? else {
+                continue;
+            };
+            let Some((reply_id, kind, payload)) = decode_frame(&frame) else {
+                continue;
+            };
+            let reply = match kind {
+                KIND_ERROR => Err(Error::Remote(String::from_utf8_lossy(payload).into_owned())),
+                _ => Ok(payload.to_vec()),
+            };
+            // Replies to calls that already timed out are dropped
+            if pending.waiting.remove(&reply_id) {
+                pending.replies.insert(reply_id, reply);
+            }
+        }
+    }
+}
+
+/// Request/response server on top of a [`Server`].
+///
+/// Requests from every client slot are passed to a handler, and its result is
+/// sent back to the calling client with the request's correlation ID.
+///
+/// # Example
+///
+/// ```no_run
+/// use windows_shared_memory::{RpcServer, Server};
+///
+/// let rpc = RpcServer::new(Server::with_clients(None, 16 * 1024, 4).unwrap());
+/// rpc.serve(|request| {
+///     if request.is_empty() {
+///         return Err("empty request");
+///     }
+///     Ok(request.to_vec())
+/// })
+/// .unwrap();
+/// ```
+pub struct RpcServer<T: Transport = ShmTransport> {
+    server: Server<T>,
+    reply_timeout_ms: Option<u32>,
+}
+
+impl<T: Transport> RpcServer<T> {
+    /// Wraps a server. Fragmentation is enabled so replies may be larger than the buffer.
+    pub fn new(mut server: Server<T>) -> Self {
+        server.set_fragmentation(true);
+        Self {
+            server,
+            reply_timeout_ms: Some(DEFAULT_REPLY_TIMEOUT_MS),
+        }
+    }
+
+    /// Returns the underlying server.
+    pub fn into_inner(self) -> Server<T> {
+        self.server
+    }
+
+    /// Sets how long to wait for a client to read the previous reply.
+    ///
+    /// If None, waits indefinitely. Defaults to [`DEFAULT_REPLY_TIMEOUT_MS`].
+    pub fn set_reply_timeout(&mut self, timeout_ms: Option<u32>) {
+        self.reply_timeout_ms = timeout_ms;
+    }
+
+    /// Handles requests until an error occurs that affects the whole server.
+    ///
+    /// The handler's `Err` is sent back as [`Error::Remote`] and does not stop serving,
+    /// nor does a client that leaves, crashes or does not read its reply in time.
+    pub fn serve<F, E>(&self, mut handler: F) -> Result<()>
+    where
+        F: FnMut(&[u8]) -> std::result::Result<Vec<u8>, E>,
+        E: fmt::Display,
+    {
+        loop {
+            self.serve_once(&mut handler, None)?;
+        }
+    }
+
+    /// Handles at most one request.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - Called with the request payload.
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, waits indefinitely.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(false)` if no request arrived in time. A reply that cannot be delivered
+    /// to its client, because the client left or did not read it in time, is dropped.
+    pub fn serve_once<F, E>(&self, handler: F, timeout_ms: Option<u32>) -> Result<bool>
+    where
+        F: FnOnce(&[u8]) -> std::result::Result<Vec<u8>, E>,
+        E: fmt::Display,
+    {
+        let (client, frame) = match self.server.receive_any(timeout_ms) {
+            Ok(Some(received)) => received,
+            Ok(None) => return Ok(false),
+            // A client leaving, crashing or sending a broken message does not stop the server
+            Err(Error::ClientClosed(_) | Error::PeerDied | Error::Truncated { .. }) => return Ok(true),
+            Err(e) => return Err(e),
+        };
+        let Some((id, kind, payload)) = decode_frame(&frame) else {
+            // Not a request frame, nothing to reply to
+            return Ok(true);
+        };
+
+        let reply = match kind {
+            KIND_REQUEST => match handler(payload) {
+                Ok(reply) => encode_frame(id, KIND_REPLY, &reply),
+                Err(e) => encode_frame(id, KIND_ERROR, e.to_string().as_bytes()),
+            },
+            _ => encode_frame(id, KIND_ERROR, format!("unknown frame kind {}", kind).as_bytes()),
+        };
+        match self.reply(client, &reply) {
+            Ok(())
+            | Err(
+                Error::Timeout
+                | Error::WouldBlock
+                | Error::PeerClosed
+                | Error::PeerDied
+                | Error::MessageTooLarge { .. },
+            ) => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn reply(&self, client: ClientId, frame: &[u8]) -> Result<()> {
+        self.server.send_timeout_to(client, frame, self.reply_timeout_ms)
+    }
+}
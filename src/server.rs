@@ -1,24 +1,84 @@
 use crate::{
-    create_event, read_from_shared_memory, str_to_pcwstr, write_to_shared_memory, ReceiveBytes,
-    ReceiveMessage, SharedDataHeader, DEFAULT_BUFFER_SIZE,
-};
-use std::sync::atomic::Ordering;
-use windows::core::Result;
-use windows::Win32::{
-    Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE},
-    System::{Memory::*, Threading::*},
+    max_message_len, read_bytes_from_shared_memory, read_from_shared_memory,
+    recv_bytes_from_shared_memory, recv_bytes_from_shared_memory_until, recv_from_shared_memory,
+    recv_from_shared_memory_until, try_write_to_shared_memory,
+    write_fragmented_to_shared_memory, write_to_shared_memory, write_to_shared_memory_timeout,
+    write_vectored_to_shared_memory, BufferSizes, ChannelMode, ClientId, ClientSlot, Direction, Error, EventNames, ReadGuard,
+    ReceiveBytes, ReceiveMessage, Result, ServerBuilder, SharedDataHeader, ShmTransport,
+    Transport, WriteGuard, DEFAULT_BUFFER_SIZE, DEFAULT_CLOSE_TIMEOUT_MS, DEFAULT_MAPPING_NAME,
+    FEATURE_FRAGMENTATION,
 };
+use crate::liveness::{self, Heartbeat};
+use crate::shared_memory::{client_slot, deadline, gather, min_buffer_size, retry_until, wait_for_any_client};
+use std::cell::Cell;
+use std::io::IoSlice;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Returns the size of the shared memory layout, checking that the header can record it.
+///
+/// Fails if there is no client slot, if a buffer or the slot count does not fit in
+/// the header's 32-bit fields, or if a buffer cannot hold a fragment.
+pub(crate) fn layout_size(buffers: BufferSizes, mode: ChannelMode, max_clients: usize) -> Result<usize> {
+    let invalid = |reason: String| Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, reason).into());
+    if max_clients == 0 {
+        return invalid("a server needs at least one client slot".to_string());
+    }
+    if max_clients > u32::MAX as usize {
+        return invalid(format!("{} client slots do not fit in the header", max_clients));
+    }
+    for size in [buffers.server_to_client, buffers.client_to_server] {
+        if size > u32::MAX as usize {
+            return invalid(format!("buffer of {} bytes does not fit in the header", size));
+        }
+        if size < min_buffer_size(mode) {
+            return invalid(format!(
+                "buffer of {} bytes is smaller than the {} bytes a fragment needs in {:?} mode",
+                size,
+                min_buffer_size(mode),
+                mode
+            ));
+        }
+    }
+    match SharedDataHeader::checked_total_size_with_buffers(buffers, max_clients) {
+        Some(size) if size <= isize::MAX as usize => Ok(size),
+        _ => invalid(format!("{} client slots with {:?} do not fit in memory", max_clients, buffers)),
+    }
+}
 
 /// Server instance for shared memory communication.
 ///
 /// The server creates the shared memory and events, and can communicate
 /// with clients that connect to the same shared memory.
-pub struct Server {
-    header_address: *mut SharedDataHeader,
-    buffer_size: usize,
-    h_map_file: HANDLE,
-    h_event_s2c: HANDLE,
-    h_event_c2s: HANDLE,
+///
+/// By default the server runs over named OS shared memory ([`ShmTransport`]),
+/// but any [`Transport`] can be used with [`Server::with_transport`].
+///
+/// A server has one client slot unless it is created with
+/// [`Server::with_clients`]. `send`, `receive` and their variants talk to the
+/// first slot; use [`send_to`](Server::send_to), [`recv_from`](Server::recv_from)
+/// and [`receive_any`](Server::receive_any) to talk to the others.
+///
+/// A server can be moved to another thread, but not shared between threads;
+/// wrap it in a [`SyncServer`](crate::SyncServer) for that.
+pub struct Server<T: Transport = ShmTransport> {
+    transport: T,
+    buffers: BufferSizes,
+    mode: ChannelMode,
+    max_clients: usize,
+    fragmentation: bool,
+    close_on_drop: bool,
+    close_timeout_ms: Option<u32>,
+    // Used by calls that pass a timeout of None
+    receive_timeout_ms: Option<u32>,
+    send_timeout_ms: Option<u32>,
+    heartbeat: Option<Heartbeat>,
+    generation: u64,
+    // Slot that receive_any checks first, so busy clients cannot starve the others
+    next_slot: Cell<usize>,
+    // Concurrent sends or receives on one instance would race on the buffers, see SyncServer
+    _not_sync: PhantomData<Cell<()>>,
 }
 
 impl Server {
@@ -40,6 +100,19 @@ impl Server {
         Self::with_buffer_size(mapping_path, DEFAULT_BUFFER_SIZE)
     }
 
+    /// Returns a builder for configuring every option of a server.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use windows_shared_memory::Server;
+    ///
+    /// let server = Server::builder().name("Local\\MyShm").max_clients(4).build().unwrap();
+    /// ```
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+
     /// Creates a new server instance with shared memory and a custom buffer size.
     ///
     /// # Arguments
@@ -57,84 +130,412 @@ impl Server {
     /// let server = Server::with_buffer_size(None, 64 * 1024).unwrap();
     /// ```
     pub fn with_buffer_size(mapping_path: Option<&str>, buffer_size: usize) -> Result<Self> {
-        let mapping_name = mapping_path.unwrap_or("Local\\MySharedMemory");
-        let mapping_name_pcwstr = str_to_pcwstr(mapping_name);
+        Self::builder()
+            .name(mapping_path.unwrap_or(DEFAULT_MAPPING_NAME))
+            .buffer_size(buffer_size)
+            .build()
+    }
 
-        let total_size = SharedDataHeader::total_size(buffer_size);
+    /// Creates a new server instance with a custom buffer size and explicit event names.
+    ///
+    /// Clients must connect with the same names using [`Client::with_event_names`](crate::Client::with_event_names).
+    ///
+    /// # Arguments
+    ///
+    /// * `mapping_path` - Optional custom path for the shared memory mapping.
+    ///   If None, uses "Local\\MySharedMemory" as default.
+    /// * `buffer_size` - Size of each data buffer in bytes.
+    /// * `events` - Names of the server-to-client and client-to-server events.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use windows_shared_memory::{EventNames, Server, DEFAULT_BUFFER_SIZE};
+    ///
+    /// let events = EventNames {
+    ///     server_to_client: "Local\\MyAppReady".to_string(),
+    ///     client_to_server: "Local\\MyAppRequest".to_string(),
+    /// };
+    /// let server = Server::with_event_names(None, DEFAULT_BUFFER_SIZE, &events).unwrap();
+    /// ```
+    pub fn with_event_names(
+        mapping_path: Option<&str>,
+        buffer_size: usize,
+        events: &EventNames,
+    ) -> Result<Self> {
+        Self::builder()
+            .name(mapping_path.unwrap_or(DEFAULT_MAPPING_NAME))
+            .buffer_size(buffer_size)
+            .event_names(events.clone())
+            .build()
+    }
 
-        // Create file mapping object
-        let h_map_file = unsafe {
-            CreateFileMappingW(
-                INVALID_HANDLE_VALUE,
-                None,
-                PAGE_READWRITE,
-                0,
-                total_size as u32,
-                &mapping_name_pcwstr,
-            )?
-        };
+    /// Creates a new server instance in ring buffer mode.
+    ///
+    /// Instead of a single-slot mailbox, each direction is a ring buffer of
+    /// `capacity` bytes that queues messages until the peer reads them.
+    /// Each message uses its length plus 4 bytes of the capacity, and `send`
+    /// fails with `WouldBlock` when the ring is full.
+    ///
+    /// # Arguments
+    ///
+    /// * `mapping_path` - Optional custom path for the shared memory mapping.
+    ///   If None, uses "Local\\MySharedMemory" as default.
+    /// * `capacity` - Size of each ring buffer in bytes.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use windows_shared_memory::Server;
+    ///
+    /// let server = Server::with_ring_buffer(None, 64 * 1024).unwrap();
+    /// server.send(b"first").unwrap();
+    /// server.send(b"second").unwrap(); // queued, "first" is kept
+    /// ```
+    pub fn with_ring_buffer(mapping_path: Option<&str>, capacity: usize) -> Result<Self> {
+        Self::builder()
+            .name(mapping_path.unwrap_or(DEFAULT_MAPPING_NAME))
+            .buffer_size(capacity)
+            .mode(ChannelMode::Ring)
+            .build()
+    }
 
-        // Map shared memory
-        let p_buf = unsafe {
-            MapViewOfFile(
-                h_map_file,
-                FILE_MAP_ALL_ACCESS,
-                0,
-                0,
-                total_size,
-            )
-        };
+    /// Creates a new server instance with `max_clients` client slots.
+    ///
+    /// Each [`Client`](crate::Client) that connects claims a free slot and gets
+    /// its own pair of buffers, so clients never overwrite each other.
+    /// Connecting fails with [`Error::ServerFull`](crate::Error::ServerFull) when
+    /// every slot is claimed.
+    ///
+    /// # Arguments
+    ///
+    /// * `mapping_path` - Optional custom path for the shared memory mapping.
+    ///   If None, uses "Local\\MySharedMemory" as default.
+    /// * `buffer_size` - Size of each data buffer in bytes.
+    /// * `max_clients` - Number of client slots.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use windows_shared_memory::Server;
+    ///
+    /// let server = Server::with_clients(None, 16 * 1024, 4).unwrap();
+    /// if let Ok(Some((client, request))) = server.receive_any(Some(1000)) {
+    ///     server.send_to(client, &request).unwrap();
+    /// }
+    /// ```
+    pub fn with_clients(mapping_path: Option<&str>, buffer_size: usize, max_clients: usize) -> Result<Self> {
+        Self::builder()
+            .name(mapping_path.unwrap_or(DEFAULT_MAPPING_NAME))
+            .buffer_size(buffer_size)
+            .max_clients(max_clients)
+            .build()
+    }
+}
+
+impl<T: Transport> Server<T> {
+    /// Creates a server over an existing transport.
+    ///
+    /// The header and data buffers in the transport region are initialized,
+    /// so the region must be at least `SharedDataHeader::total_size(buffer_size)` bytes.
+    pub fn with_transport(transport: T, buffer_size: usize) -> Result<Self> {
+        Self::with_transport_mode(transport, buffer_size, ChannelMode::Mailbox)
+    }
+
+    /// Creates a server over an existing transport using the given channel mode.
+    pub fn with_transport_mode(transport: T, buffer_size: usize, mode: ChannelMode) -> Result<Self> {
+        Self::with_transport_clients(transport, buffer_size, mode, 1)
+    }
 
-        if p_buf.Value.is_null() {
-            unsafe { CloseHandle(h_map_file)? };
-            return Err(windows::core::Error::from_thread());
+    /// Creates a server over an existing transport with `max_clients` client slots.
+    ///
+    /// The region must be at least
+    /// `SharedDataHeader::total_size_with_clients(buffer_size, max_clients)` bytes.
+    pub fn with_transport_clients(
+        transport: T,
+        buffer_size: usize,
+        mode: ChannelMode,
+        max_clients: usize,
+    ) -> Result<Self> {
+        Self::with_transport_buffers(transport, BufferSizes::symmetric(buffer_size), mode, max_clients)
+    }
+
+    /// Creates a server over an existing transport with separate buffer sizes per direction.
+    ///
+    /// The region must be at least
+    /// `SharedDataHeader::total_size_with_buffers(buffers, max_clients)` bytes.
+    pub fn with_transport_buffers(
+        transport: T,
+        buffers: BufferSizes,
+        mode: ChannelMode,
+        max_clients: usize,
+    ) -> Result<Self> {
+        Self::with_transport_features(transport, buffers, mode, max_clients, FEATURE_FRAGMENTATION)
+    }
+
+    /// Creates a server over an existing transport whose header advertises `features`.
+    pub(crate) fn with_transport_features(
+        transport: T,
+        buffers: BufferSizes,
+        mode: ChannelMode,
+        max_clients: usize,
+        features: u32,
+    ) -> Result<Self> {
+        let total_size = layout_size(buffers, mode, max_clients)?;
+        if transport.size() < total_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "transport region is smaller than the shared memory layout",
+            )
+            .into());
         }
 
-        // Initialize header
+        // Initialize header and client slots
         unsafe {
             std::ptr::write(
-                p_buf.Value as *mut SharedDataHeader,
-                SharedDataHeader::new(buffer_size),
+                transport.as_ptr() as *mut SharedDataHeader,
+                SharedDataHeader {
+                    features,
+                    ..SharedDataHeader::with_buffers(buffers, mode, max_clients)
+                },
             );
+            for slot in 0..max_clients {
+                let slot_ptr = transport.as_ptr().add(SharedDataHeader::slot_offset(slot));
+                std::ptr::write(slot_ptr as *mut ClientSlot, ClientSlot::new());
+            }
             // Zero out the data buffers
-            let data_ptr = (p_buf.Value as *mut u8).add(SharedDataHeader::offset_s2c());
-            std::ptr::write_bytes(data_ptr, 0, buffer_size * 2);
+            let data_ptr = transport.as_ptr().add(SharedDataHeader::offset_s2c(buffers, max_clients, 0));
+            std::ptr::write_bytes(data_ptr, 0, buffers.per_slot() * max_clients);
         }
-
-        // Create event objects
-        let h_event_s2c = create_event("Local\\MyEventS2C")?;
-        let h_event_c2s = create_event("Local\\MyEventC2S")?;
+        let header = unsafe { &*(transport.as_ptr() as *const SharedDataHeader) };
+        header.server_pid.store(std::process::id(), Ordering::Release);
+        // Written last, clients wait for it before connecting
+        let generation = new_generation();
+        header.generation.store(generation, Ordering::Release);
 
         Ok(Self {
-            header_address: p_buf.Value as *mut SharedDataHeader,
-            buffer_size,
-            h_map_file,
-            h_event_s2c,
-            h_event_c2s,
+            transport,
+            buffers,
+            mode,
+            max_clients,
+            fragmentation: false,
+            close_on_drop: false,
+            close_timeout_ms: Some(DEFAULT_CLOSE_TIMEOUT_MS),
+            receive_timeout_ms: None,
+            send_timeout_ms: None,
+            heartbeat: None,
+            generation,
+            next_slot: Cell::new(0),
+            _not_sync: PhantomData,
         })
     }
 
-    /// Returns the buffer size for this server.
+    /// Returns the size of the buffer this server sends through (server to client).
     pub fn buffer_size(&self) -> usize {
-        self.buffer_size
+        self.buffers.server_to_client
+    }
+
+    /// Returns the buffer sizes of both directions.
+    pub fn buffer_sizes(&self) -> BufferSizes {
+        self.buffers
+    }
+
+    /// Returns the largest message that can be sent without fragmentation.
+    pub fn max_message_len(&self) -> usize {
+        max_message_len(self.mode, self.buffers.server_to_client)
+    }
+
+    /// Enables or disables fragmentation of messages larger than [`max_message_len`](Self::max_message_len).
+    ///
+    /// When enabled, `send` and `send_timeout` split large messages across
+    /// multiple buffer fills, waiting for the client to read each one, and
+    /// `receive_bytes` on the other side reassembles them transparently. When
+    /// disabled (the default), large messages fail with
+    /// [`Error::MessageTooLarge`](crate::Error::MessageTooLarge). `try_send` never fragments.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::Server;
+    /// # let mut server = Server::new(None).unwrap();
+    /// server.set_fragmentation(true);
+    /// server.send(&vec![0u8; 4 * 1024 * 1024]).unwrap();
+    /// ```
+    pub fn set_fragmentation(&mut self, enabled: bool) {
+        self.fragmentation = enabled;
+    }
+
+    /// Returns true if fragmentation of large messages is enabled.
+    pub fn fragmentation(&self) -> bool {
+        self.fragmentation
+    }
+
+    /// Returns the channel mode for this server.
+    pub fn mode(&self) -> ChannelMode {
+        self.mode
+    }
+
+    /// Returns the number of client slots.
+    pub fn max_clients(&self) -> usize {
+        self.max_clients
+    }
+
+    /// Returns the clients that currently hold a slot.
+    pub fn connected_clients(&self) -> Vec<ClientId> {
+        (0..self.max_clients)
+            .filter(|&slot| self.slot(slot).state.load(Ordering::Acquire) == 1)
+            .map(ClientId)
+            .collect()
+    }
+
+    /// Returns `timeout_ms`, or the default receive timeout if it is None.
+    fn receive_timeout_or_default(&self, timeout_ms: Option<u32>) -> Option<u32> {
+        timeout_ms.or(self.receive_timeout_ms)
+    }
+
+    /// Returns `timeout_ms`, or the default send timeout if it is None.
+    fn send_timeout_or_default(&self, timeout_ms: Option<u32>) -> Option<u32> {
+        timeout_ms.or(self.send_timeout_ms)
+    }
+
+    fn header(&self) -> &SharedDataHeader {
+        unsafe { &*(self.transport.as_ptr() as *const SharedDataHeader) }
+    }
+
+    fn slot(&self, slot: usize) -> &ClientSlot {
+        unsafe { client_slot(self.transport.as_ptr() as *const SharedDataHeader, slot) }
+    }
+
+    fn check_client(&self, client: ClientId) -> Result<usize> {
+        if client.0 >= self.max_clients {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("no client slot {}", client.0),
+            )
+            .into());
+        }
+        Ok(client.0)
     }
 
     /// Sends a close signal to all connected clients.
+    ///
+    /// Each client reports [`ReceiveMessage::Exit`] on its next receive and
+    /// acknowledges the close. Use [`close`](Self::close) to wait for that.
     pub fn send_close(&self) -> Result<()> {
-        unsafe {
-            (*self.header_address)
-                .flag_server
-                .store(3, Ordering::Release);
-            SetEvent(self.h_event_s2c)?;
+        for slot in 0..self.max_clients {
+            self.slot(slot).flag_server.store(3, Ordering::Release);
+            self.transport.signal(Direction::ServerToClient, slot)?;
         }
         Ok(())
     }
 
-    /// Sends data to connected clients.
+    /// Closes the connection to all clients, waiting until each has seen the close.
+    ///
+    /// A client acknowledges the close by receiving it; clients that disconnect count
+    /// as acknowledged.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, waits indefinitely.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::Timeout`](crate::Error::Timeout) if a client has not
+    /// acknowledged the close in time.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::Server;
+    /// # let server = Server::new(None).unwrap();
+    /// if server.close(Some(1000)).is_err() {
+    ///     println!("client did not see the close");
+    /// }
+    /// ```
+    pub fn close(&self, timeout_ms: Option<u32>) -> Result<()> {
+        self.send_close()?;
+        retry_until(deadline(timeout_ms), || {
+            let pending = (0..self.max_clients).any(|slot| {
+                let slot = self.slot(slot);
+                slot.state.load(Ordering::Acquire) == 1 && slot.flag_server.load(Ordering::Acquire) == 3
+            });
+            if pending { Err(Error::WouldBlock) } else { Ok(()) }
+        })
+    }
+
+    /// Makes dropping the server [`close`](Self::close) the connection first.
+    ///
+    /// The drop waits up to the [close timeout](Self::set_close_timeout)
+    /// for the clients to acknowledge. Disabled by default.
+    pub fn set_close_on_drop(&mut self, enabled: bool) {
+        self.close_on_drop = enabled;
+    }
+
+    /// Sets how long a close on drop waits for the clients to acknowledge.
+    ///
+    /// If None, waits indefinitely. Defaults to [`DEFAULT_CLOSE_TIMEOUT_MS`](crate::DEFAULT_CLOSE_TIMEOUT_MS).
+    pub fn set_close_timeout(&mut self, timeout_ms: Option<u32>) {
+        self.close_timeout_ms = timeout_ms;
+    }
+
+    /// Sets the timeout of receives that are called with a timeout of None.
+    ///
+    /// If None, such receives wait indefinitely. Defaults to None.
+    pub fn set_receive_timeout(&mut self, timeout_ms: Option<u32>) {
+        self.receive_timeout_ms = timeout_ms;
+    }
+
+    /// Sets the timeout of [`send_timeout`](Self::send_timeout) and fragmented sends
+    /// that are called with a timeout of None.
+    ///
+    /// If None, such sends wait indefinitely. Defaults to None.
+    pub fn set_send_timeout(&mut self, timeout_ms: Option<u32>) {
+        self.send_timeout_ms = timeout_ms;
+    }
+
+    /// Starts a background thread that refreshes the server's heartbeat every `interval_ms`.
+    ///
+    /// Clients treat the server as dead if it misses three heartbeats in a row,
+    /// which also catches a server that hangs without exiting. Without a heartbeat
+    /// clients only notice when the server process exits.
+    pub fn start_heartbeat(&mut self, interval_ms: u32) {
+        self.heartbeat = None;
+        let field = &self.header().server_heartbeat as *const _;
+        self.heartbeat = Some(unsafe { Heartbeat::start(field, interval_ms) });
+    }
+
+    /// Stops the heartbeat started by [`start_heartbeat`](Self::start_heartbeat).
+    pub fn stop_heartbeat(&mut self) {
+        self.heartbeat = None;
+    }
+
+    /// Returns true if the client in the first slot is connected and alive.
+    pub fn is_peer_alive(&self) -> bool {
+        self.is_client_alive(ClientId(0))
+    }
+
+    /// Returns true if `client` is connected and its process is alive.
+    ///
+    /// A client counts as dead once its process has exited or, if it sends
+    /// heartbeats, once its heartbeat is stale.
+    pub fn is_client_alive(&self, client: ClientId) -> bool {
+        if client.0 >= self.max_clients {
+            return false;
+        }
+        let slot = self.slot(client.0);
+        slot.state.load(Ordering::Acquire) == 1
+            && liveness::peer_alive(
+                slot.client_pid.load(Ordering::Acquire),
+                slot.client_heartbeat.load(Ordering::Acquire),
+            )
+    }
+
+    /// Sends data to the client in the first slot.
     ///
     /// # Arguments
     ///
-    /// * `data` - Byte slice to send. Maximum size is the configured buffer size.
+    /// * `data` - Byte slice to send. Maximum size is [`max_message_len`](Self::max_message_len)
+    ///   unless fragmentation is enabled.
     ///
     /// # Example
     ///
@@ -144,22 +545,101 @@ impl Server {
     /// server.send(b"Hello, client!").unwrap();
     /// ```
     pub fn send(&self, data: &[u8]) -> Result<()> {
-        unsafe {
-            write_to_shared_memory(
-                self.header_address,
-                self.buffer_size,
-                data,
-                true,
-                self.h_event_s2c,
-            )
-        }
+        self.send_to(ClientId(0), data)
+    }
+
+    /// Sends one message made of `bufs` one after another to the client in the first slot.
+    ///
+    /// Each piece is copied straight into the shared buffer, so a header and a
+    /// payload do not have to be joined first. Otherwise behaves like
+    /// [`send`](Self::send), with the total length checked against
+    /// [`max_message_len`](Self::max_message_len). With fragmentation an
+    /// oversized message is gathered into one allocation and sent in fragments.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::Server;
+    /// use std::io::IoSlice;
+    ///
+    /// # let server = Server::new(None).unwrap();
+    /// let header = 7u32.to_le_bytes();
+    /// server.send_vectored(&[IoSlice::new(&header), IoSlice::new(b"payload")]).unwrap();
+    /// ```
+    pub fn send_vectored(&self, bufs: &[IoSlice<'_>]) -> Result<()> {
+        self.send_vectored_to(ClientId(0), bufs)
     }
 
-    /// Receives data from connected clients as a String.
+    /// Sends data only if the client has consumed the previous message.
+    ///
+    /// Returns [`Error::WouldBlock`](crate::Error::WouldBlock) instead of overwriting
+    /// an unread message (or, in ring buffer mode, if the ring is full).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::{Server, Error};
+    /// # let server = Server::new(None).unwrap();
+    /// match server.try_send(b"update") {
+    ///     Ok(()) => {}
+    ///     Err(Error::WouldBlock) => println!("previous message not read yet"),
+    ///     Err(e) => panic!("{}", e),
+    /// }
+    /// ```
+    pub fn try_send(&self, data: &[u8]) -> Result<()> {
+        self.try_send_to(ClientId(0), data)
+    }
+
+    /// Sends data, waiting until the client has consumed the previous message.
     ///
     /// # Arguments
     ///
-    /// * `timeout_ms` - Optional timeout in milliseconds. If None, waits indefinitely.
+    /// * `data` - Byte slice to send. Maximum size is [`max_message_len`](Self::max_message_len)
+    ///   unless fragmentation is enabled.
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default send timeout](Self::set_send_timeout).
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::Timeout`](crate::Error::Timeout) if the previous message
+    /// is still unread when the timeout elapses. With fragmentation the timeout
+    /// applies to the whole message.
+    pub fn send_timeout(&self, data: &[u8], timeout_ms: Option<u32>) -> Result<()> {
+        self.send_timeout_to(ClientId(0), data, timeout_ms)
+    }
+
+    /// Reserves `len` bytes in the buffer to the client in the first slot,
+    /// to build a message in place.
+    ///
+    /// The message is sent when the returned guard is committed, and dropped
+    /// if the guard is dropped without committing.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`Error::WouldBlock`](crate::Error::WouldBlock) if the client has not
+    /// consumed the previous message (or, in ring buffer mode, if the ring is full)
+    /// and [`Error::MessageTooLarge`](crate::Error::MessageTooLarge) if `len` exceeds
+    /// [`max_message_len`](Self::max_message_len).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::Server;
+    /// # let mut server = Server::new(None).unwrap();
+    /// let mut frame = server.reserve(4).unwrap();
+    /// frame.copy_from_slice(&42u32.to_le_bytes());
+    /// frame.commit().unwrap();
+    /// ```
+    pub fn reserve(&mut self, len: usize) -> Result<WriteGuard<'_, T>> {
+        self.reserve_to(ClientId(0), len)
+    }
+
+    /// Receives data from the client in the first slot as a String.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default receive timeout](Self::set_receive_timeout).
     ///
     /// # Returns
     ///
@@ -167,57 +647,520 @@ impl Server {
     pub fn receive(&self, timeout_ms: Option<u32>) -> ReceiveMessage {
         unsafe {
             read_from_shared_memory(
-                self.header_address,
-                self.buffer_size,
-                true,
-                timeout_ms,
-                self.h_event_c2s,
+                &self.transport,
+                self.buffers.client_to_server,
+                Direction::ClientToServer,
+                0,
+                self.receive_timeout_or_default(timeout_ms),
             )
         }
     }
 
-    /// Receives raw bytes from connected clients.
+    /// Receives raw bytes from the client in the first slot.
     ///
     /// # Arguments
     ///
-    /// * `timeout_ms` - Optional timeout in milliseconds. If None, waits indefinitely.
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default receive timeout](Self::set_receive_timeout).
     ///
     /// # Returns
     ///
     /// Returns a ReceiveBytes enum containing the bytes or status.
     pub fn receive_bytes(&self, timeout_ms: Option<u32>) -> ReceiveBytes {
         unsafe {
-            crate::read_bytes_from_shared_memory(
-                self.header_address,
-                self.buffer_size,
-                true,
-                timeout_ms,
-                self.h_event_c2s,
+            read_bytes_from_shared_memory(
+                &self.transport,
+                self.buffers.client_to_server,
+                Direction::ClientToServer,
+                0,
+                self.receive_timeout_or_default(timeout_ms),
             )
         }
     }
-}
 
-impl Drop for Server {
-    fn drop(&mut self) {
+    /// Receives data from the client in the first slot as a String.
+    ///
+    /// Like [`receive`](Self::receive), but reports failures as a typed [`Error`](crate::Error).
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default receive timeout](Self::set_receive_timeout).
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(None)` on timeout and [`Error::PeerClosed`](crate::Error::PeerClosed)
+    /// when the close signal is received.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::{Server, Error};
+    /// # let server = Server::new(None).unwrap();
+    /// match server.recv(Some(1000)) {
+    ///     Ok(Some(message)) => println!("{}", message),
+    ///     Ok(None) => println!("timed out"),
+    ///     Err(Error::PeerClosed) => println!("closed"),
+    ///     Err(Error::InvalidUtf8(bytes)) => println!("{} bytes of binary data", bytes.len()),
+    ///     Err(e) => eprintln!("{}", e),
+    /// }
+    /// ```
+    pub fn recv(&self, timeout_ms: Option<u32>) -> Result<Option<String>> {
+        unsafe {
+            recv_from_shared_memory(
+                &self.transport,
+                self.buffers.client_to_server,
+                Direction::ClientToServer,
+                0,
+                self.receive_timeout_or_default(timeout_ms),
+            )
+        }
+    }
+
+    /// Receives raw bytes from the client in the first slot.
+    ///
+    /// Like [`receive_bytes`](Self::receive_bytes), but reports failures as a typed [`Error`](crate::Error).
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default receive timeout](Self::set_receive_timeout).
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(None)` on timeout and [`Error::PeerClosed`](crate::Error::PeerClosed)
+    /// when the close signal is received.
+    pub fn recv_bytes(&self, timeout_ms: Option<u32>) -> Result<Option<Vec<u8>>> {
+        unsafe {
+            recv_bytes_from_shared_memory(
+                &self.transport,
+                self.buffers.client_to_server,
+                Direction::ClientToServer,
+                0,
+                self.receive_timeout_or_default(timeout_ms),
+            )
+        }
+    }
+
+    /// Receives a message from the client in the first slot without copying it out of shared memory.
+    ///
+    /// The client cannot reuse the buffer until the returned guard is dropped,
+    /// see [`ReadGuard`].
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default receive timeout](Self::set_receive_timeout).
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(None)` on timeout and [`Error::PeerClosed`](crate::Error::PeerClosed)
+    /// when the close signal is received.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::Server;
+    /// # let mut server = Server::new(None).unwrap();
+    /// if let Some(frame) = server.receive_borrowed(Some(1000)).unwrap() {
+    ///     println!("{} bytes", frame.len());
+    /// }
+    /// ```
+    pub fn receive_borrowed(&mut self, timeout_ms: Option<u32>) -> Result<Option<ReadGuard<'_>>> {
+        self.receive_borrowed_from(ClientId(0), timeout_ms)
+    }
+
+    /// Receives raw bytes from the client in the first slot into `buffer`, reusing its allocation.
+    ///
+    /// The previous contents of `buffer` are replaced by the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - Vector the message is copied into.
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default receive timeout](Self::set_receive_timeout).
+    ///
+    /// # Returns
+    ///
+    /// Returns the length of the message, `Ok(None)` on timeout and
+    /// [`Error::PeerClosed`](crate::Error::PeerClosed) when the close signal is received.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::Server;
+    /// # let server = Server::new(None).unwrap();
+    /// let mut buffer = Vec::with_capacity(server.buffer_sizes().client_to_server);
+    /// while let Ok(Some(len)) = server.receive_into(&mut buffer, Some(1000)) {
+    ///     println!("{} bytes", len);
+    /// }
+    /// ```
+    pub fn receive_into(&self, buffer: &mut Vec<u8>, timeout_ms: Option<u32>) -> Result<Option<usize>> {
+        let Some(message) = self.receive_guard(timeout_ms, usize::MAX)? else {
+            return Ok(None);
+        };
+        buffer.clear();
+        buffer.extend_from_slice(&message);
+        Ok(Some(message.len()))
+    }
+
+    /// Receives raw bytes from the client in the first slot into the start of `buffer`.
+    ///
+    /// Like [`receive_into`](Self::receive_into), but if the message is longer than
+    /// `buffer` it is left unread and [`Error::MessageTooLarge`](crate::Error::MessageTooLarge)
+    /// holds its length, so the call can be retried with a larger buffer.
+    pub fn receive_into_slice(&self, buffer: &mut [u8], timeout_ms: Option<u32>) -> Result<Option<usize>> {
+        let Some(message) = self.receive_guard(timeout_ms, buffer.len())? else {
+            return Ok(None);
+        };
+        buffer[..message.len()].copy_from_slice(&message);
+        Ok(Some(message.len()))
+    }
+
+    /// Waits for a message from the client in the first slot, left in place
+    /// until the returned guard is dropped.
+    fn receive_guard(&self, timeout_ms: Option<u32>, max_len: usize) -> Result<Option<ReadGuard<'_>>> {
+        // The guard does not outlive the calling method, and a server is not shared between threads
+        unsafe {
+            ReadGuard::receive(
+                &self.transport,
+                self.buffers.client_to_server,
+                Direction::ClientToServer,
+                0,
+                deadline(self.receive_timeout_or_default(timeout_ms)),
+                max_len,
+            )
+        }
+    }
+
+    /// Receives a message from the client in the first slot as a String if one has already arrived.
+    ///
+    /// Never waits; returns [`ReceiveMessage::Timeout`] if there is no message.
+    pub fn try_receive(&self) -> ReceiveMessage {
+        self.receive_until(Instant::now())
+    }
+
+    /// Receives data from the client in the first slot as a String, waiting until `deadline`.
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - Point in time after which the wait gives up. A deadline in the past
+    ///   only checks for a message that has already arrived.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::{Server, ReceiveMessage};
+    /// # use std::time::{Duration, Instant};
+    /// # let server = Server::new(None).unwrap();
+    /// // Several receives sharing one overall deadline
+    /// let deadline = Instant::now() + Duration::from_secs(1);
+    /// while let ReceiveMessage::Message(msg) = server.receive_until(deadline) {
+    ///     println!("{}", msg);
+    /// }
+    /// ```
+    pub fn receive_until(&self, deadline: Instant) -> ReceiveMessage {
+        self.recv_until(deadline).into()
+    }
+
+    /// Receives raw bytes from the client in the first slot if a message has already arrived.
+    ///
+    /// Never waits; returns [`ReceiveBytes::Timeout`] if there is no message.
+    pub fn try_receive_bytes(&self) -> ReceiveBytes {
+        self.receive_bytes_until(Instant::now())
+    }
+
+    /// Receives raw bytes from the client in the first slot, waiting until `deadline`.
+    ///
+    /// See [`receive_until`](Self::receive_until).
+    pub fn receive_bytes_until(&self, deadline: Instant) -> ReceiveBytes {
+        self.recv_bytes_until(deadline).into()
+    }
+
+    /// Like [`try_receive`](Self::try_receive), but reports failures as a typed [`Error`](crate::Error).
+    ///
+    /// Returns `Ok(None)` if there is no message.
+    pub fn try_recv(&self) -> Result<Option<String>> {
+        self.recv_until(Instant::now())
+    }
+
+    /// Like [`receive_until`](Self::receive_until), but reports failures as a typed [`Error`](crate::Error).
+    ///
+    /// Returns `Ok(None)` if the deadline passes without a message.
+    pub fn recv_until(&self, deadline: Instant) -> Result<Option<String>> {
+        unsafe {
+            recv_from_shared_memory_until(
+                &self.transport,
+                self.buffers.client_to_server,
+                Direction::ClientToServer,
+                0,
+                Some(deadline),
+            )
+        }
+    }
+
+    /// Like [`try_receive_bytes`](Self::try_receive_bytes), but reports failures as a typed [`Error`](crate::Error).
+    ///
+    /// Returns `Ok(None)` if there is no message.
+    pub fn try_recv_bytes(&self) -> Result<Option<Vec<u8>>> {
+        self.recv_bytes_until(Instant::now())
+    }
+
+    /// Like [`receive_bytes_until`](Self::receive_bytes_until), but reports failures as a typed [`Error`](crate::Error).
+    ///
+    /// Returns `Ok(None)` if the deadline passes without a message.
+    pub fn recv_bytes_until(&self, deadline: Instant) -> Result<Option<Vec<u8>>> {
+        unsafe {
+            recv_bytes_from_shared_memory_until(
+                &self.transport,
+                self.buffers.client_to_server,
+                Direction::ClientToServer,
+                0,
+                Some(deadline),
+            )
+        }
+    }
+
+    /// Sends data to one client.
+    ///
+    /// Behaves like [`send`](Self::send) for the given client slot.
+    pub fn send_to(&self, client: ClientId, data: &[u8]) -> Result<()> {
+        if self.fragmentation && data.len() > self.max_message_len() {
+            return self.send_timeout_to(client, data, None);
+        }
+
+        unsafe {
+            write_to_shared_memory(
+                &self.transport,
+                self.buffers.server_to_client,
+                data,
+                Direction::ServerToClient,
+                self.check_client(client)?,
+            )
+        }
+    }
+
+    /// Sends one message made of `bufs` to one client.
+    ///
+    /// Behaves like [`send_vectored`](Self::send_vectored) for the given client slot.
+    pub fn send_vectored_to(&self, client: ClientId, bufs: &[IoSlice<'_>]) -> Result<()> {
+        let len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        if self.fragmentation && len > self.max_message_len() {
+            return self.send_timeout_to(client, &gather(bufs), None);
+        }
+
+        unsafe {
+            write_vectored_to_shared_memory(
+                &self.transport,
+                self.buffers.server_to_client,
+                bufs,
+                Direction::ServerToClient,
+                self.check_client(client)?,
+            )
+        }
+    }
+
+    /// Sends data to one client only if it has consumed the previous message.
+    ///
+    /// Behaves like [`try_send`](Self::try_send) for the given client slot.
+    pub fn try_send_to(&self, client: ClientId, data: &[u8]) -> Result<()> {
+        unsafe {
+            try_write_to_shared_memory(
+                &self.transport,
+                self.buffers.server_to_client,
+                data,
+                Direction::ServerToClient,
+                self.check_client(client)?,
+            )
+        }
+    }
+
+    /// Sends data to one client, waiting until it has consumed the previous message.
+    ///
+    /// Behaves like [`send_timeout`](Self::send_timeout) for the given client slot.
+    pub fn send_timeout_to(&self, client: ClientId, data: &[u8], timeout_ms: Option<u32>) -> Result<()> {
+        let slot = self.check_client(client)?;
         unsafe {
-            if let Err(e) = UnmapViewOfFile(MEMORY_MAPPED_VIEW_ADDRESS {
-                Value: self.header_address as *mut _,
-            }) {
-                eprintln!("Failed to unmap shared memory: {:?}", e);
+            if self.fragmentation {
+                write_fragmented_to_shared_memory(
+                    &self.transport,
+                    self.buffers.server_to_client,
+                    data,
+                    Direction::ServerToClient,
+                    slot,
+                    self.send_timeout_or_default(timeout_ms),
+                )
+            } else {
+                write_to_shared_memory_timeout(
+                    &self.transport,
+                    self.buffers.server_to_client,
+                    data,
+                    Direction::ServerToClient,
+                    slot,
+                    self.send_timeout_or_default(timeout_ms),
+                )
             }
+        }
+    }
 
-            if let Err(e) = CloseHandle(self.h_event_s2c) {
-                eprintln!("Failed to close event handle (s2c): {:?}", e);
+    /// Sends to the client in the first slot like [`send_timeout`](Self::send_timeout) without a timeout, but
+    /// gives up with [`Error::Timeout`](crate::Error::Timeout) once `cancelled` returns true
+    /// while waiting.
+    #[cfg(feature = "async")]
+    pub(crate) fn send_cancellable(&self, data: &[u8], cancelled: &dyn Fn() -> bool) -> Result<()> {
+        use crate::shared_memory::{retry_until_cancelled, write_fragmented_until};
+
+        let (buffer_size, direction) = (self.buffers.server_to_client, Direction::ServerToClient);
+        unsafe {
+            if self.fragmentation {
+                write_fragmented_until(&self.transport, buffer_size, data, direction, 0, None, cancelled)
+            } else {
+                retry_until_cancelled(None, cancelled, || {
+                    try_write_to_shared_memory(&self.transport, buffer_size, data, direction, 0)
+                })
             }
+        }
+    }
+
+    /// Reserves `len` bytes in the buffer to one client.
+    ///
+    /// Behaves like [`reserve`](Self::reserve) for the given client slot.
+    pub fn reserve_to(&mut self, client: ClientId, len: usize) -> Result<WriteGuard<'_, T>> {
+        let slot = self.check_client(client)?;
+        unsafe {
+            WriteGuard::new(
+                &self.transport,
+                self.buffers.server_to_client,
+                len,
+                Direction::ServerToClient,
+                slot,
+            )
+        }
+    }
+
+    /// Receives raw bytes from one client.
+    ///
+    /// Behaves like [`recv_bytes`](Self::recv_bytes) for the given client slot.
+    pub fn recv_from(&self, client: ClientId, timeout_ms: Option<u32>) -> Result<Option<Vec<u8>>> {
+        unsafe {
+            recv_bytes_from_shared_memory(
+                &self.transport,
+                self.buffers.client_to_server,
+                Direction::ClientToServer,
+                self.check_client(client)?,
+                self.receive_timeout_or_default(timeout_ms),
+            )
+        }
+    }
+
+    /// Receives a message from one client without copying it out of shared memory.
+    ///
+    /// Behaves like [`receive_borrowed`](Self::receive_borrowed) for the given client slot.
+    pub fn receive_borrowed_from(
+        &mut self,
+        client: ClientId,
+        timeout_ms: Option<u32>,
+    ) -> Result<Option<ReadGuard<'_>>> {
+        let slot = self.check_client(client)?;
+        unsafe {
+            ReadGuard::receive(
+                &self.transport,
+                self.buffers.client_to_server,
+                Direction::ClientToServer,
+                slot,
+                deadline(self.receive_timeout_or_default(timeout_ms)),
+                usize::MAX,
+            )
+        }
+    }
 
-            if let Err(e) = CloseHandle(self.h_event_c2s) {
-                eprintln!("Failed to close event handle (c2s): {:?}", e);
+    /// Receives raw bytes from whichever client sends first.
+    ///
+    /// Slots are checked round-robin, so one busy client cannot starve the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_ms` - Optional timeout in milliseconds. If None, uses the
+    ///   [default receive timeout](Self::set_receive_timeout).
+    ///
+    /// # Returns
+    ///
+    /// Returns the client the message came from and its bytes, or `Ok(None)` on timeout.
+    /// A client's close signal is reported once as [`Error::ClientClosed`](crate::Error::ClientClosed),
+    /// a crashed client once as [`Error::PeerDied`](crate::Error::PeerDied).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use windows_shared_memory::Server;
+    /// # let server = Server::with_clients(None, 1024, 4).unwrap();
+    /// while let Ok(Some((client, request))) = server.receive_any(Some(1000)) {
+    ///     println!("client {} sent {} bytes", client.0, request.len());
+    /// }
+    /// ```
+    pub fn receive_any(&self, timeout_ms: Option<u32>) -> Result<Option<(ClientId, Vec<u8>)>> {
+        let deadline = deadline(self.receive_timeout_or_default(timeout_ms));
+
+        loop {
+            let start = self.next_slot.get();
+            for i in 0..self.max_clients {
+                let slot = (start + i) % self.max_clients;
+                // A close that was already reported stays until a new client claims the slot
+                if self.slot(slot).flag_client.load(Ordering::Acquire) == 5 {
+                    continue;
+                }
+                let received = unsafe {
+                    recv_bytes_from_shared_memory_until(
+                        &self.transport,
+                        self.buffers.client_to_server,
+                        Direction::ClientToServer,
+                        slot,
+                        Some(Instant::now()),
+                    )
+                };
+                match received {
+                    Ok(Some(bytes)) => {
+                        self.next_slot.set((slot + 1) % self.max_clients);
+                        return Ok(Some((ClientId(slot), bytes)));
+                    }
+                    Ok(None) => {}
+                    Err(Error::PeerClosed) => {
+                        self.next_slot.set((slot + 1) % self.max_clients);
+                        return Err(Error::ClientClosed(ClientId(slot)));
+                    }
+                    Err(e) => return Err(e),
+                }
             }
 
-            if let Err(e) = CloseHandle(self.h_map_file) {
-                eprintln!("Failed to close file mapping handle: {:?}", e);
+            if !unsafe { wait_for_any_client(&self.transport, deadline)? } {
+                return Ok(None);
             }
         }
     }
 }
+
+impl<T: Transport> Drop for Server<T> {
+    fn drop(&mut self) {
+        if self.close_on_drop
+            && let Err(e) = self.close(self.close_timeout_ms)
+        {
+            eprintln!("Failed to close connection: {}", e);
+        }
+        // The heartbeat thread writes into the transport, stop it before the transport goes away
+        self.heartbeat.take();
+        // Tells clients that still map the region that this server is gone, unless
+        // another server has taken the region over
+        let generation = &self.header().generation;
+        let _ = generation.compare_exchange(self.generation, 0, Ordering::AcqRel, Ordering::Acquire);
+    }
+}
+
+/// Returns a nonzero generation that differs from the ones of earlier servers.
+fn new_generation() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    // The counter keeps servers created in the same clock tick apart
+    (nanos ^ COUNTER.fetch_add(1, Ordering::Relaxed)).max(1)
+}
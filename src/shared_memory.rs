@@ -1,57 +1,632 @@
-use crate::{ReceiveBytes, ReceiveMessage, SharedDataHeader};
-use std::sync::atomic::Ordering;
-use windows::core::Result;
-use windows::Win32::Foundation::{HANDLE, WAIT_OBJECT_0, WAIT_TIMEOUT};
-use windows::Win32::System::Threading::{SetEvent, WaitForSingleObject};
+use crate::liveness;
+use crate::ring::{Ring, TOTAL_LEN_PREFIX};
+use crate::{
+    ChannelMode, ClientSlot, Direction, Error, ReceiveBytes, ReceiveMessage, Result, SharedDataHeader,
+    Transport, FEATURE_SCRUB_AFTER_READ,
+};
+use std::io::IoSlice;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+/// Size of the total length and offset in front of each mailbox fragment
+pub(crate) const FRAGMENT_HEADER: usize = 2 * std::mem::size_of::<u32>();
+
+/// How long a reader with a deadline waits for each remaining fragment of a
+/// message that has started, even past the deadline (1 s)
+const FRAGMENT_TIMEOUT_MS: u32 = 1000;
+
+/// How often a waiting reader checks that the peer is still alive (ms)
+const LIVENESS_CHECK_MS: u32 = 100;
+
+/// Returns the [`ClientSlot`] of `slot`.
+///
+/// # Safety
+///
+/// `header` must be a valid, properly aligned pointer to initialized SharedDataHeader
+/// followed by at least `slot + 1` client slots
+pub(crate) unsafe fn client_slot<'a>(header: *const SharedDataHeader, slot: usize) -> &'a ClientSlot {
+    unsafe { &*((header as *const u8).add(SharedDataHeader::slot_offset(slot)) as *const ClientSlot) }
+}
+
+/// Claims a free client slot, or one whose client died without releasing it.
+///
+/// Whatever the previous client left for the server is discarded, unless the
+/// server is reading it in place right now. Messages the server sent to a free
+/// slot are kept for the new client, while those sent to a dead client are
+/// discarded too.
+///
+/// # Safety
+///
+/// `header` must be a valid, properly aligned pointer to initialized SharedDataHeader
+/// followed by its client slots
+pub(crate) unsafe fn claim_slot(header: *const SharedDataHeader) -> Result<usize> {
+    unsafe {
+        let max_clients = (*header).max_clients as usize;
+        let pid = std::process::id();
+        for slot in 0..max_clients {
+            let client = client_slot(header, slot);
+            let free = client.state.compare_exchange(0, 1, Ordering::AcqRel, Ordering::Acquire).is_ok();
+            if !free && !take_over(client, pid) {
+                continue;
+            }
+            if !free {
+                clear_lane(client, Direction::ServerToClient);
+            }
+            clear_unread_lane(client, Direction::ClientToServer);
+            client.client_heartbeat.store(0, Ordering::Release);
+            client.client_pid.store(pid, Ordering::Release);
+            return Ok(slot);
+        }
+        Err(Error::ServerFull { max_clients })
+    }
+}
+
+/// Moves a claimed slot whose client has died over to the process `pid`.
+///
+/// Only one claimer can win, since the dead client's process ID is swapped out atomically.
+fn take_over(client: &ClientSlot, pid: u32) -> bool {
+    // 0 means the slot is being claimed or released right now
+    let dead = client.client_pid.load(Ordering::Acquire);
+    dead != 0
+        && !liveness::peer_alive(dead, client.client_heartbeat.load(Ordering::Acquire))
+        && client.client_pid.compare_exchange(dead, pid, Ordering::AcqRel, Ordering::Acquire).is_ok()
+}
+
+/// Drops everything queued in `direction` of a slot.
+fn clear_lane(client: &ClientSlot, direction: Direction) {
+    let (flag, head, tail) = match direction {
+        Direction::ServerToClient => (&client.flag_server, &client.ring_head_s2c, &client.ring_tail_s2c),
+        Direction::ClientToServer => (&client.flag_client, &client.ring_head_c2s, &client.ring_tail_c2s),
+    };
+    flag.store(0, Ordering::Release);
+    head.store(0, Ordering::Release);
+    tail.store(0, Ordering::Release);
+}
+
+/// Drops everything queued in `direction` of a slot, unless the reader is borrowing a
+/// message from it (state 6).
+///
+/// A borrowed message stays put until the reader is done with it, and sends wait until then.
+fn clear_unread_lane(client: &ClientSlot, direction: Direction) {
+    let flag = match direction {
+        Direction::ServerToClient => &client.flag_server,
+        Direction::ClientToServer => &client.flag_client,
+    };
+    // Holding the lane in state 6 keeps the reader from borrowing while it is reset
+    if flag.fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| (state != 6).then_some(6)).is_ok() {
+        clear_lane(client, direction);
+    }
+}
+
+/// Frees `slot` if the process `pid` still holds it. Returns false if another client took it over.
+///
+/// # Safety
+///
+/// `header` must be a valid, properly aligned pointer to initialized SharedDataHeader
+/// followed by at least `slot + 1` client slots
+pub(crate) unsafe fn release_slot(header: *const SharedDataHeader, slot: usize, pid: u32) -> bool {
+    let client = unsafe { client_slot(header, slot) };
+    if client.client_pid.compare_exchange(pid, 0, Ordering::AcqRel, Ordering::Acquire).is_err() {
+        return false;
+    }
+    client.client_heartbeat.store(0, Ordering::Release);
+    client.state.store(0, Ordering::Release);
+    true
+}
+
+/// Returns the state flag, data length and data buffer offset for `direction` of `slot`.
+///
+/// # Safety
+///
+/// `header` must be a valid, properly aligned pointer to initialized SharedDataHeader
+/// followed by at least `slot + 1` client slots
+pub(crate) unsafe fn lane<'a>(
+    header: *mut SharedDataHeader,
+    direction: Direction,
+    slot: usize,
+) -> (&'a AtomicU32, *mut u32, usize) {
+    unsafe {
+        let max_clients = (*header).max_clients as usize;
+        let buffers = (*header).buffer_sizes();
+        let client = (header as *mut u8).add(SharedDataHeader::slot_offset(slot)) as *mut ClientSlot;
+        match direction {
+            Direction::ServerToClient => (
+                &(*client).flag_server,
+                &raw mut (*client).data_len_server_to_client,
+                SharedDataHeader::offset_s2c(buffers, max_clients, slot),
+            ),
+            Direction::ClientToServer => (
+                &(*client).flag_client,
+                &raw mut (*client).data_len_client_to_server,
+                SharedDataHeader::offset_c2s(buffers, max_clients, slot),
+            ),
+        }
+    }
+}
+
+/// Returns false if the peer that writes `direction` of `slot` has died.
+///
+/// A slot without a client has no peer to lose.
+///
+/// # Safety
+///
+/// `header` must point to an initialized header with more than `slot` client slots.
+pub(crate) unsafe fn peer_alive(header: *const SharedDataHeader, direction: Direction, slot: usize) -> bool {
+    unsafe {
+        match direction {
+            Direction::ServerToClient => liveness::peer_alive(
+                (*header).server_pid.load(Ordering::Acquire),
+                (*header).server_heartbeat.load(Ordering::Acquire),
+            ),
+            Direction::ClientToServer => {
+                let client = client_slot(header, slot);
+                client.state.load(Ordering::Acquire) != 1
+                    || liveness::peer_alive(
+                        client.client_pid.load(Ordering::Acquire),
+                        client.client_heartbeat.load(Ordering::Acquire),
+                    )
+            }
+        }
+    }
+}
+
+/// Waits for a signal in `direction` until `deadline`, checking regularly that the peer is alive.
+///
+/// Returns `Ok(false)` once the deadline has passed and [`Error::PeerDied`] if the peer died.
+pub(crate) unsafe fn wait_for_peer<T: Transport + ?Sized>(
+    transport: &T,
+    direction: Direction,
+    slot: usize,
+    deadline: Option<Instant>,
+) -> Result<bool> {
+    let header = transport.as_ptr() as *const SharedDataHeader;
+    wait_checking(
+        deadline,
+        |slice_ms| transport.wait(direction, slot, Some(slice_ms)),
+        || match direction {
+            Direction::ServerToClient => unsafe { peer_alive(header, direction, slot) },
+            Direction::ClientToServer => !unsafe { reap_client(header, slot) },
+        },
+    )
+}
+
+/// Waits for a client-to-server signal from any slot until `deadline`, checking regularly
+/// that every connected client is alive.
+///
+/// Returns `Ok(false)` once the deadline has passed and [`Error::PeerDied`] once for each
+/// client that died, after releasing its slot.
+pub(crate) unsafe fn wait_for_any_client<T: Transport + ?Sized>(
+    transport: &T,
+    deadline: Option<Instant>,
+) -> Result<bool> {
+    let header = transport.as_ptr() as *const SharedDataHeader;
+    let max_clients = unsafe { (*header).max_clients } as usize;
+    wait_checking(
+        deadline,
+        |slice_ms| transport.wait_any(Some(slice_ms)),
+        || !(0..max_clients).any(|slot| unsafe { reap_client(header, slot) }),
+    )
+}
+
+/// Waits in slices of [`LIVENESS_CHECK_MS`] until `wait` reports a signal, the deadline
+/// passes or `alive` returns `false`.
+fn wait_checking(
+    deadline: Option<Instant>,
+    wait: impl Fn(u32) -> Result<bool>,
+    alive: impl Fn() -> bool,
+) -> Result<bool> {
+    loop {
+        let slice_ms = match deadline {
+            Some(deadline) => match remaining_ms(deadline) {
+                0 => return Ok(false),
+                ms => ms.min(LIVENESS_CHECK_MS),
+            },
+            None => LIVENESS_CHECK_MS,
+        };
+        if wait(slice_ms).map_err(Error::wait_failed)? {
+            return Ok(true);
+        }
+        if !alive() {
+            return Err(Error::PeerDied);
+        }
+    }
+}
+
+/// Frees the slot of a client that died without releasing it.
+///
+/// Returns `true` if `slot` was claimed by a dead client and has been released.
+unsafe fn reap_client(header: *const SharedDataHeader, slot: usize) -> bool {
+    unsafe {
+        if peer_alive(header, Direction::ClientToServer, slot) {
+            return false;
+        }
+        let client = client_slot(header, slot);
+        let released = release_slot(header, slot, client.client_pid.load(Ordering::Acquire));
+        if released {
+            clear_lane(client, Direction::ServerToClient);
+        }
+        released
+    }
+}
+
+/// Returns the ring buffer stored in the data buffer for `direction` of `slot`.
+///
+/// # Safety
+///
+/// `header` must be a valid, properly aligned pointer to initialized SharedDataHeader
+/// followed by its client slots and data buffers, `buffer_size` being the size for `direction`
+pub(crate) unsafe fn ring<'a>(
+    header: *mut SharedDataHeader,
+    buffer_size: usize,
+    direction: Direction,
+    slot: usize,
+) -> Ring<'a> {
+    unsafe {
+        let client = client_slot(header, slot);
+        let (head, tail) = match direction {
+            Direction::ServerToClient => (&client.ring_head_s2c, &client.ring_tail_s2c),
+            Direction::ClientToServer => (&client.ring_head_c2s, &client.ring_tail_c2s),
+        };
+        let (_, _, data_buffer_offset) = lane(header, direction, slot);
+        Ring::new(head, tail, (header as *mut u8).add(data_buffer_offset), buffer_size)
+            .scrub_after_read(scrubs(header))
+    }
+}
+
+/// Returns true if the header was initialized in ring buffer mode.
+///
+/// # Safety
+///
+/// `header` must be a valid, properly aligned pointer to initialized SharedDataHeader
+pub(crate) unsafe fn is_ring(header: *const SharedDataHeader) -> bool {
+    unsafe { (*header).mode == ChannelMode::Ring.as_u32() }
+}
+
+/// Returns true if readers zero each message once they have consumed it.
+///
+/// # Safety
+///
+/// `header` must be a valid, properly aligned pointer to initialized SharedDataHeader
+pub(crate) unsafe fn scrubs(header: *const SharedDataHeader) -> bool {
+    unsafe { (*header).features & FEATURE_SCRUB_AFTER_READ != 0 }
+}
+
+/// Returns the largest message that fits in a single buffer fill.
+pub(crate) fn max_message_len(mode: ChannelMode, buffer_size: usize) -> usize {
+    match mode {
+        ChannelMode::Mailbox => buffer_size,
+        ChannelMode::Ring => Ring::max_message_len(buffer_size),
+    }
+}
+
+/// Returns the smallest data buffer that can carry a fragment with at least one byte of the message.
+pub(crate) fn min_buffer_size(mode: ChannelMode) -> usize {
+    match mode {
+        ChannelMode::Mailbox => FRAGMENT_HEADER + 1,
+        ChannelMode::Ring => Ring::MIN_CAPACITY,
+    }
+}
+
+pub(crate) fn deadline(timeout_ms: Option<u32>) -> Option<Instant> {
+    timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms as u64))
+}
+
+/// Returns how long a reader waits for the next signal.
+///
+/// Once a fragmented message has started, each remaining fragment may take up to
+/// [`FRAGMENT_TIMEOUT_MS`], even past the deadline.
+fn wait_until(message: &Option<Vec<u8>>, deadline: Option<Instant>) -> Option<Instant> {
+    match (message, deadline) {
+        (Some(_), Some(_)) => self::deadline(Some(FRAGMENT_TIMEOUT_MS)),
+        (_, deadline) => deadline,
+    }
+}
+
+/// Milliseconds left until `deadline`, rounded up.
+pub(crate) fn remaining_ms(deadline: Instant) -> u32 {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    remaining.as_micros().div_ceil(1000) as u32
+}
+
+/// Retries `attempt` while it fails with [`Error::WouldBlock`] until `deadline`.
+pub(crate) fn retry_until<R>(deadline: Option<Instant>, mut attempt: impl FnMut() -> Result<R>) -> Result<R> {
+    let mut backoff = Duration::from_micros(50);
+
+    loop {
+        match attempt() {
+            Err(Error::WouldBlock) => {}
+            other => return other,
+        }
+
+        // The reader does not signal consumption, so poll the flag with a short backoff
+        let mut sleep = backoff;
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::Timeout);
+            }
+            sleep = sleep.min(remaining);
+        }
+        std::thread::sleep(sleep);
+        backoff = (backoff * 2).min(Duration::from_millis(1));
+    }
+}
+
+/// Like [`retry_until`], but gives up with [`Error::Timeout`] once `cancelled` returns true
+/// after an attempt that would block.
+pub(crate) fn retry_until_cancelled<R>(
+    deadline: Option<Instant>,
+    cancelled: &dyn Fn() -> bool,
+    mut attempt: impl FnMut() -> Result<R>,
+) -> Result<R> {
+    retry_until(deadline, || match attempt() {
+        Err(Error::WouldBlock) if cancelled() => Err(Error::Timeout),
+        result => result,
+    })
+}
+
+/// Tells the peer that its close request was seen (3 -> 5).
+pub(crate) fn acknowledge_close(flag: &AtomicU32) {
+    let _ = flag.compare_exchange(3, 5, Ordering::AcqRel, Ordering::Acquire);
+}
 
 /// Writes data to shared memory
 ///
+/// In mailbox mode the previous message is overwritten even if the peer has
+/// not read it yet. Use [`try_write_to_shared_memory`] or
+/// [`write_to_shared_memory_timeout`] to respect unread messages.
+///
+/// Returns [`Error::MessageTooLarge`] if `data` does not fit in one buffer and
+/// [`Error::WouldBlock`] while the peer borrows the previous message through a
+/// [`ReadGuard`](crate::ReadGuard).
+///
 /// # Safety
 ///
 /// The caller must ensure:
-/// - `header` is a valid, properly aligned pointer to initialized SharedDataHeader
+/// - The transport region starts with an initialized SharedDataHeader
 /// - No other threads are concurrently writing to the same buffer
-/// - `event_handle` is a valid Windows event handle
-/// - `buffer_size` matches the actual buffer size allocated after the header
-pub unsafe fn write_to_shared_memory(
-    header: *mut SharedDataHeader,
+/// - `buffer_size` matches the size of the data buffer for `direction`
+/// - `slot` is less than the number of client slots in the header
+pub unsafe fn write_to_shared_memory<T: Transport + ?Sized>(
+    transport: &T,
     buffer_size: usize,
     data: &[u8],
-    is_server: bool,
-    event_handle: HANDLE,
+    direction: Direction,
+    slot: usize,
+) -> Result<()> {
+    unsafe { write_message(transport, buffer_size, &[IoSlice::new(data)], direction, slot, true) }
+}
+
+/// Writes a message made of `bufs` one after another to shared memory
+///
+/// Each piece is copied straight into the data buffer. Otherwise behaves like
+/// [`write_to_shared_memory`], with the total length checked against the buffer.
+///
+/// # Safety
+///
+/// Same requirements as [`write_to_shared_memory`].
+pub unsafe fn write_vectored_to_shared_memory<T: Transport + ?Sized>(
+    transport: &T,
+    buffer_size: usize,
+    bufs: &[IoSlice<'_>],
+    direction: Direction,
+    slot: usize,
+) -> Result<()> {
+    unsafe { write_message(transport, buffer_size, bufs, direction, slot, true) }
+}
+
+/// Copies `bufs` into one contiguous message.
+pub(crate) fn gather(bufs: &[IoSlice<'_>]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(bufs.iter().map(|buf| buf.len()).sum());
+    for buf in bufs {
+        data.extend_from_slice(buf);
+    }
+    data
+}
+
+/// Writes data to shared memory only if the peer has consumed the previous message
+///
+/// Returns [`Error::WouldBlock`] if the previous message is still unread
+/// (or, in ring buffer mode, if the ring is full).
+///
+/// # Safety
+///
+/// Same requirements as [`write_to_shared_memory`].
+pub unsafe fn try_write_to_shared_memory<T: Transport + ?Sized>(
+    transport: &T,
+    buffer_size: usize,
+    data: &[u8],
+    direction: Direction,
+    slot: usize,
+) -> Result<()> {
+    unsafe { write_message(transport, buffer_size, &[IoSlice::new(data)], direction, slot, false) }
+}
+
+/// Writes data to shared memory, waiting until the peer has consumed the previous message
+///
+/// Returns [`Error::Timeout`] if there is still no room when the timeout elapses.
+/// `None` waits indefinitely.
+///
+/// # Safety
+///
+/// Same requirements as [`write_to_shared_memory`].
+pub unsafe fn write_to_shared_memory_timeout<T: Transport + ?Sized>(
+    transport: &T,
+    buffer_size: usize,
+    data: &[u8],
+    direction: Direction,
+    slot: usize,
+    timeout_ms: Option<u32>,
+) -> Result<()> {
+    retry_until(deadline(timeout_ms), || unsafe {
+        try_write_to_shared_memory(transport, buffer_size, data, direction, slot)
+    })
+}
+
+/// Writes data of any size to shared memory, splitting it into fragments if needed
+///
+/// Each fragment waits until the peer has consumed the previous one, and the
+/// reader reassembles them into a single message. The timeout applies to the
+/// whole message. `None` waits indefinitely.
+///
+/// # Safety
+///
+/// Same requirements as [`write_to_shared_memory`].
+pub unsafe fn write_fragmented_to_shared_memory<T: Transport + ?Sized>(
+    transport: &T,
+    buffer_size: usize,
+    data: &[u8],
+    direction: Direction,
+    slot: usize,
+    timeout_ms: Option<u32>,
+) -> Result<()> {
+    let deadline = deadline(timeout_ms);
+    unsafe { write_fragmented_until(transport, buffer_size, data, direction, slot, deadline, &|| false) }
+}
+
+/// Like [`write_fragmented_to_shared_memory`], but gives up with [`Error::Timeout`] once
+/// `cancelled` returns true while waiting for the peer.
+pub(crate) unsafe fn write_fragmented_until<T: Transport + ?Sized>(
+    transport: &T,
+    buffer_size: usize,
+    data: &[u8],
+    direction: Direction,
+    slot: usize,
+    deadline: Option<Instant>,
+    cancelled: &dyn Fn() -> bool,
 ) -> Result<()> {
     unsafe {
-        let base_ptr = header as *mut u8;
-
-        let (flag, data_buffer_offset, data_len) = if is_server {
-            (
-                &(*header).flag_server,
-                SharedDataHeader::offset_s2c(),
-                &mut (*header).data_len_server_to_client,
-            )
-        } else {
-            (
-                &(*header).flag_client,
-                SharedDataHeader::offset_c2s(buffer_size),
-                &mut (*header).data_len_client_to_server,
-            )
+        let header = transport.as_ptr() as *mut SharedDataHeader;
+        let ring_mode = is_ring(header);
+        let mode = if ring_mode { ChannelMode::Ring } else { ChannelMode::Mailbox };
+
+        if data.len() <= max_message_len(mode, buffer_size) {
+            return retry_until_cancelled(deadline, cancelled, || {
+                try_write_to_shared_memory(transport, buffer_size, data, direction, slot)
+            });
+        }
+
+        // Mailbox fragments start with the total length and offset, the first ring record with
+        // the total length
+        let (chunk_size, first_prefix) = match mode {
+            ChannelMode::Mailbox => (buffer_size.saturating_sub(FRAGMENT_HEADER), 0),
+            ChannelMode::Ring => (Ring::max_message_len(buffer_size), TOTAL_LEN_PREFIX),
         };
+        if chunk_size <= first_prefix || data.len() > u32::MAX as usize {
+            return Err(Error::MessageTooLarge {
+                len: data.len(),
+                capacity: max_message_len(mode, buffer_size),
+            });
+        }
 
-        let data_buffer = std::slice::from_raw_parts_mut(base_ptr.add(data_buffer_offset), buffer_size);
+        let ring = ring(header, buffer_size, direction, slot);
+        let total_len = (data.len() as u32).to_ne_bytes();
 
-        // Initialize and copy data buffer
-        data_buffer.fill(0);
-        let copy_len = std::cmp::min(data.len(), buffer_size);
-        data_buffer[..copy_len].copy_from_slice(&data[..copy_len]);
-        *data_len = copy_len as u32;
+        let mut offset = 0;
+        while offset < data.len() {
+            let first = offset == 0;
+            let len = if first { chunk_size - first_prefix } else { chunk_size };
+            let chunk = &data[offset..(offset + len).min(data.len())];
+            retry_until_cancelled(deadline, cancelled, || {
+                if !ring_mode {
+                    return write_fragment(transport, buffer_size, data.len(), offset, chunk, direction, slot);
+                }
+                let last = offset + chunk.len() == data.len();
+                let prefix: &[u8] = if first { &total_len } else { &[] };
+                if !ring.push_slices(&[IoSlice::new(prefix), IoSlice::new(chunk)], first, last)? {
+                    return Err(Error::WouldBlock);
+                }
+                transport.signal(direction, slot)
+            })?;
+            offset += chunk.len();
+        }
+        Ok(())
+    }
+}
+
+unsafe fn write_message<T: Transport + ?Sized>(
+    transport: &T,
+    buffer_size: usize,
+    bufs: &[IoSlice<'_>],
+    direction: Direction,
+    slot: usize,
+    overwrite: bool,
+) -> Result<()> {
+    unsafe {
+        let base_ptr = transport.as_ptr();
+        let header = base_ptr as *mut SharedDataHeader;
+
+        let len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        let mode = if is_ring(header) { ChannelMode::Ring } else { ChannelMode::Mailbox };
+        let capacity = max_message_len(mode, buffer_size);
+        if len > capacity {
+            return Err(Error::MessageTooLarge { len, capacity });
+        }
+
+        if mode == ChannelMode::Ring {
+            if !ring(header, buffer_size, direction, slot).push_slices(bufs, true, true)? {
+                return Err(Error::WouldBlock);
+            }
+            return transport.signal(direction, slot);
+        }
+
+        let (flag, data_len, data_buffer_offset) = lane(header, direction, slot);
+
+        // State 1 or 4 means the peer has not read the previous message yet, and 6 that it is
+        // still reading it in place, which not even an overwrite may disturb
+        match flag.load(Ordering::Acquire) {
+            6 => return Err(Error::WouldBlock),
+            1 | 4 if !overwrite => return Err(Error::WouldBlock),
+            _ => {}
+        }
+
+        // Only the first data_len bytes are ever read, so the rest of the buffer is left as is
+        let mut dst = base_ptr.add(data_buffer_offset);
+        for buf in bufs {
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), dst, buf.len());
+            dst = dst.add(buf.len());
+        }
+        *data_len = len as u32;
 
         // Set flag (1: data sent)
         flag.store(1, Ordering::Release);
 
         // Set event signal
-        SetEvent(event_handle)?;
+        transport.signal(direction, slot)
+    }
+}
 
-        Ok(())
+/// Writes one fragment of a message that is larger than the mailbox buffer.
+///
+/// Returns [`Error::WouldBlock`] if the peer has not read the previous fragment yet.
+unsafe fn write_fragment<T: Transport + ?Sized>(
+    transport: &T,
+    buffer_size: usize,
+    total_len: usize,
+    offset: usize,
+    chunk: &[u8],
+    direction: Direction,
+    slot: usize,
+) -> Result<()> {
+    unsafe {
+        let base_ptr = transport.as_ptr();
+        let (flag, data_len, data_buffer_offset) =
+            lane(base_ptr as *mut SharedDataHeader, direction, slot);
+
+        if matches!(flag.load(Ordering::Acquire), 1 | 4 | 6) {
+            return Err(Error::WouldBlock);
+        }
+
+        let data_buffer = std::slice::from_raw_parts_mut(base_ptr.add(data_buffer_offset), buffer_size);
+        data_buffer[..4].copy_from_slice(&(total_len as u32).to_ne_bytes());
+        data_buffer[4..FRAGMENT_HEADER].copy_from_slice(&(offset as u32).to_ne_bytes());
+        data_buffer[FRAGMENT_HEADER..FRAGMENT_HEADER + chunk.len()].copy_from_slice(chunk);
+        *data_len = (FRAGMENT_HEADER + chunk.len()) as u32;
+
+        // Set flag (4: fragment sent)
+        flag.store(4, Ordering::Release);
+
+        transport.signal(direction, slot)
     }
 }
 
@@ -60,125 +635,269 @@ pub unsafe fn write_to_shared_memory(
 /// # Safety
 ///
 /// The caller must ensure:
-/// - `header` is a valid, properly aligned pointer to initialized SharedDataHeader
+/// - The transport region starts with an initialized SharedDataHeader
 /// - No other threads are concurrently reading from the same buffer
-/// - `event_handle` is a valid Windows event handle
-/// - `buffer_size` matches the actual buffer size allocated after the header
-pub unsafe fn read_from_shared_memory(
-    header: *mut SharedDataHeader,
+/// - `buffer_size` matches the size of the data buffer for `direction`
+/// - `slot` is less than the number of client slots in the header
+pub unsafe fn read_from_shared_memory<T: Transport + ?Sized>(
+    transport: &T,
     buffer_size: usize,
-    is_server_reading: bool,
+    direction: Direction,
+    slot: usize,
     timeout_ms: Option<u32>,
-    event_handle: HANDLE,
 ) -> ReceiveMessage {
+    unsafe { recv_from_shared_memory(transport, buffer_size, direction, slot, timeout_ms) }.into()
+}
+
+/// Reads raw bytes from shared memory
+///
+/// Fragmented messages are reassembled into a single message.
+///
+/// # Safety
+///
+/// The caller must ensure:
+/// - The transport region starts with an initialized SharedDataHeader
+/// - No other threads are concurrently reading from the same buffer
+/// - `buffer_size` matches the size of the data buffer for `direction`
+/// - `slot` is less than the number of client slots in the header
+pub unsafe fn read_bytes_from_shared_memory<T: Transport + ?Sized>(
+    transport: &T,
+    buffer_size: usize,
+    direction: Direction,
+    slot: usize,
+    timeout_ms: Option<u32>,
+) -> ReceiveBytes {
+    unsafe { recv_bytes_from_shared_memory(transport, buffer_size, direction, slot, timeout_ms) }.into()
+}
+
+/// Reads data from shared memory as a String
+///
+/// Returns `Ok(None)` on timeout, [`Error::PeerClosed`] if the peer sent a close
+/// signal and [`Error::InvalidUtf8`] with the raw bytes if the message is not UTF-8.
+/// A `timeout_ms` of None waits indefinitely.
+///
+/// # Safety
+///
+/// Same requirements as [`read_from_shared_memory`].
+pub unsafe fn recv_from_shared_memory<T: Transport + ?Sized>(
+    transport: &T,
+    buffer_size: usize,
+    direction: Direction,
+    slot: usize,
+    timeout_ms: Option<u32>,
+) -> Result<Option<String>> {
+    unsafe { recv_from_shared_memory_until(transport, buffer_size, direction, slot, deadline(timeout_ms)) }
+}
+
+/// Reads raw bytes from shared memory
+///
+/// Returns `Ok(None)` on timeout and [`Error::PeerClosed`] if the peer sent a
+/// close signal. Fragmented messages are reassembled into a single message.
+/// A `timeout_ms` of None waits indefinitely.
+///
+/// # Safety
+///
+/// Same requirements as [`read_bytes_from_shared_memory`].
+pub unsafe fn recv_bytes_from_shared_memory<T: Transport + ?Sized>(
+    transport: &T,
+    buffer_size: usize,
+    direction: Direction,
+    slot: usize,
+    timeout_ms: Option<u32>,
+) -> Result<Option<Vec<u8>>> {
+    unsafe { recv_bytes_from_shared_memory_until(transport, buffer_size, direction, slot, deadline(timeout_ms)) }
+}
+
+/// Reads data from shared memory as a String, waiting until `deadline`
+///
+/// A deadline of None waits indefinitely, and a deadline that has already
+/// passed only checks for a message that has arrived. Otherwise behaves like
+/// [`recv_from_shared_memory`].
+///
+/// # Safety
+///
+/// Same requirements as [`read_from_shared_memory`].
+pub unsafe fn recv_from_shared_memory_until<T: Transport + ?Sized>(
+    transport: &T,
+    buffer_size: usize,
+    direction: Direction,
+    slot: usize,
+    deadline: Option<Instant>,
+) -> Result<Option<String>> {
+    match unsafe { recv_bytes_from_shared_memory_until(transport, buffer_size, direction, slot, deadline) }? {
+        Some(bytes) => String::from_utf8(bytes)
+            .map(Some)
+            .map_err(|e| Error::InvalidUtf8(e.into_bytes())),
+        None => Ok(None),
+    }
+}
+
+/// Reads raw bytes from shared memory, waiting until `deadline`
+///
+/// A deadline of None waits indefinitely, and a deadline that has already
+/// passed only checks for a message that has arrived. Once a fragmented message
+/// has started, each remaining fragment may arrive up to 1 s after the deadline.
+///
+/// # Safety
+///
+/// Same requirements as [`read_bytes_from_shared_memory`].
+pub unsafe fn recv_bytes_from_shared_memory_until<T: Transport + ?Sized>(
+    transport: &T,
+    buffer_size: usize,
+    direction: Direction,
+    slot: usize,
+    deadline: Option<Instant>,
+) -> Result<Option<Vec<u8>>> {
     unsafe {
-        // Wait for event
-        if let Some(timeout) = timeout_ms {
-            match WaitForSingleObject(event_handle, timeout) {
-                WAIT_OBJECT_0 => {}
-                WAIT_TIMEOUT => return ReceiveMessage::Timeout,
-                _ => return ReceiveMessage::MessageError("Event wait failed".to_string()),
-            }
+        if is_ring(transport.as_ptr() as *const SharedDataHeader) {
+            return recv_bytes_from_ring(transport, buffer_size, direction, slot, deadline);
         }
 
-        let base_ptr = header as *const u8;
-
-        let (flag, data_buffer_offset, data_len) = if is_server_reading {
-            // Server reading: data from client
-            (
-                &(*header).flag_client,
-                SharedDataHeader::offset_c2s(buffer_size),
-                (*header).data_len_client_to_server as usize,
-            )
-        } else {
-            // Client reading: data from server
-            (
-                &(*header).flag_server,
-                SharedDataHeader::offset_s2c(),
-                (*header).data_len_server_to_client as usize,
-            )
-        };
+        let base_ptr = transport.as_ptr();
+        let header = base_ptr as *mut SharedDataHeader;
+        let (flag, data_len, data_buffer_offset) = lane(header, direction, slot);
+        let scrub = scrubs(header);
 
-        let data_buffer = std::slice::from_raw_parts(base_ptr.add(data_buffer_offset), buffer_size);
+        let data_buffer = std::slice::from_raw_parts_mut(base_ptr.add(data_buffer_offset), buffer_size);
 
-        // State - 0: waiting, 1: data sent, 2: data received, 3: exit
-        match flag.load(Ordering::Acquire) {
-            1 => {
-                // Read only the valid data length
-                let valid_len = std::cmp::min(data_len, buffer_size);
-                let valid_data = &data_buffer[..valid_len];
-                let message = match String::from_utf8(valid_data.to_vec()) {
-                    Ok(s) => s,
-                    Err(_) => return ReceiveMessage::MessageError("UTF-8 conversion failed".to_string()),
-                };
+        // Fragments of a message larger than the buffer received so far
+        let mut message: Option<Vec<u8>> = None;
+
+        loop {
+            // State - 0: waiting, 1: data sent, 2: data received, 3: close requested,
+            // 4: fragment sent, 5: close acknowledged, 6: data being read in place
+            match flag.load(Ordering::Acquire) {
+                1 => {
+                    // Mark data as received (2)
+                    let len = *data_len as usize;
+                    let bytes = data_buffer[..len.min(buffer_size)].to_vec();
+                    if scrub {
+                        data_buffer[..bytes.len()].fill(0);
+                    }
+                    flag.store(2, Ordering::Release);
+
+                    if len > buffer_size {
+                        return Err(Error::Truncated { received: buffer_size });
+                    }
+                    return Ok(Some(bytes));
+                }
+                4 if buffer_size < FRAGMENT_HEADER => {
+                    // Drop the fragment, its header cannot even be read
+                    flag.store(2, Ordering::Release);
+                    return Err(Error::Corrupt(format!(
+                        "fragment in a buffer of {} bytes, smaller than its {} byte header",
+                        buffer_size, FRAGMENT_HEADER
+                    )));
+                }
+                4 => {
+                    let valid_len = (*data_len as usize).clamp(FRAGMENT_HEADER, buffer_size);
+                    let total_len = u32::from_ne_bytes(data_buffer[..4].try_into().unwrap()) as usize;
+                    let offset = u32::from_ne_bytes(data_buffer[4..FRAGMENT_HEADER].try_into().unwrap());
+
+                    if offset == 0 {
+                        message = Some(Vec::new());
+                    }
+                    let Some(bytes) = message.as_mut().filter(|bytes| bytes.len() == offset as usize) else {
+                        // The start of this message was missed, drop the fragment
+                        message = None;
+                        if scrub {
+                            data_buffer[..valid_len].fill(0);
+                        }
+                        flag.store(2, Ordering::Release);
+                        continue;
+                    };
+                    bytes.extend_from_slice(&data_buffer[FRAGMENT_HEADER..valid_len]);
+                    if scrub {
+                        data_buffer[..valid_len].fill(0);
+                    }
+
+                    // Mark fragment as received (2)
+                    flag.store(2, Ordering::Release);
+                    if bytes.len() >= total_len {
+                        return Ok(Some(std::mem::take(bytes)));
+                    }
+                }
+                3 | 5 => {
+                    acknowledge_close(flag);
+                    return Err(Error::PeerClosed);
+                }
+                0 | 2 | 6 => {}
+                state => return Err(Error::UnknownState(state)),
+            }
 
-                // Mark data as received (2)
-                flag.store(2, Ordering::Release);
-                ReceiveMessage::Message(message)
+            // A signal may be left over from a message that was already read, or meant for
+            // another slot, so keep waiting until the deadline
+            if !wait_for_peer(transport, direction, slot, wait_until(&message, deadline))? {
+                return match message {
+                    Some(bytes) => Err(Error::Truncated { received: bytes.len() }),
+                    None => Ok(None),
+                };
             }
-            3 => ReceiveMessage::Exit,
-            2 => ReceiveMessage::Timeout,
-            0 => ReceiveMessage::Timeout,
-            _ => ReceiveMessage::MessageError("Unknown state".to_string()),
         }
     }
 }
 
-/// Reads raw bytes from shared memory
+/// Reads the next queued message from a ring buffer channel
+///
+/// Queued messages are returned before an exit signal.
 ///
 /// # Safety
 ///
-/// The caller must ensure:
-/// - `header` is a valid, properly aligned pointer to initialized SharedDataHeader
-/// - No other threads are concurrently reading from the same buffer
-/// - `event_handle` is a valid Windows event handle
-/// - `buffer_size` matches the actual buffer size allocated after the header
-pub unsafe fn read_bytes_from_shared_memory(
-    header: *mut SharedDataHeader,
+/// Same requirements as [`read_bytes_from_shared_memory`].
+unsafe fn recv_bytes_from_ring<T: Transport + ?Sized>(
+    transport: &T,
     buffer_size: usize,
-    is_server_reading: bool,
-    timeout_ms: Option<u32>,
-    event_handle: HANDLE,
-) -> ReceiveBytes {
+    direction: Direction,
+    slot: usize,
+    deadline: Option<Instant>,
+) -> Result<Option<Vec<u8>>> {
     unsafe {
-        // Wait for event
-        if let Some(timeout) = timeout_ms {
-            match WaitForSingleObject(event_handle, timeout) {
-                WAIT_OBJECT_0 => {}
-                WAIT_TIMEOUT => return ReceiveBytes::Timeout,
-                _ => return ReceiveBytes::Error("Event wait failed".to_string()),
-            }
-        }
+        let header = transport.as_ptr() as *mut SharedDataHeader;
+        let (flag, _, _) = lane(header, direction, slot);
+        let ring = ring(header, buffer_size, direction, slot);
 
-        let base_ptr = header as *const u8;
-
-        let (flag, data_buffer_offset, data_len) = if is_server_reading {
-            (
-                &(*header).flag_client,
-                SharedDataHeader::offset_c2s(buffer_size),
-                (*header).data_len_client_to_server as usize,
-            )
-        } else {
-            (
-                &(*header).flag_server,
-                SharedDataHeader::offset_s2c(),
-                (*header).data_len_server_to_client as usize,
-            )
-        };
+        // Records of a fragmented message received so far
+        let mut message: Option<Vec<u8>> = None;
 
-        let data_buffer = std::slice::from_raw_parts(base_ptr.add(data_buffer_offset), buffer_size);
+        loop {
+            while let Some(record) = ring.pop()? {
+                if record.first {
+                    let mut data = record.data;
+                    if !record.last {
+                        // The first of several records starts with the total length
+                        if data.len() < TOTAL_LEN_PREFIX {
+                            return Err(Error::Corrupt(format!(
+                                "first fragment of {} bytes has no total length",
+                                data.len()
+                            )));
+                        }
+                        data.drain(..TOTAL_LEN_PREFIX);
+                    }
+                    message = Some(data);
+                } else if let Some(bytes) = message.as_mut() {
+                    bytes.extend_from_slice(&record.data);
+                } else {
+                    // The start of this message was missed, drop the record
+                    continue;
+                }
 
-        match flag.load(Ordering::Acquire) {
-            1 => {
-                let valid_len = std::cmp::min(data_len, buffer_size);
-                let bytes = data_buffer[..valid_len].to_vec();
-                flag.store(2, Ordering::Release);
-                ReceiveBytes::Bytes(bytes)
+                if record.last {
+                    return Ok(message.take());
+                }
+            }
+            if message.is_none() && matches!(flag.load(Ordering::Acquire), 3 | 5) {
+                acknowledge_close(flag);
+                return Err(Error::PeerClosed);
+            }
+
+            // A signal may be left over from a message that was already popped, so keep
+            // waiting until the deadline
+            if !wait_for_peer(transport, direction, slot, wait_until(&message, deadline))? {
+                return match message {
+                    Some(bytes) => Err(Error::Truncated { received: bytes.len() }),
+                    None => Ok(None),
+                };
             }
-            3 => ReceiveBytes::Exit,
-            2 => ReceiveBytes::Timeout,
-            0 => ReceiveBytes::Timeout,
-            _ => ReceiveBytes::Error("Unknown state".to_string()),
         }
     }
 }
@@ -1,57 +1,332 @@
-use std::sync::atomic::AtomicU32;
+use crate::{Direction, Error, Result};
+use std::sync::atomic::{AtomicU32, AtomicU64};
 
 /// Default buffer size for data transfer (16 KB)
 pub const DEFAULT_BUFFER_SIZE: usize = 16 * 1024;
 
+/// Mapping name used when none is given
+pub const DEFAULT_MAPPING_NAME: &str = "Local\\MySharedMemory";
+
+/// Time a close on drop waits for the peer to acknowledge (1 s)
+pub const DEFAULT_CLOSE_TIMEOUT_MS: u32 = 1000;
+
+/// Magic number at the start of every header ("WSHM")
+pub const HEADER_MAGIC: u32 = u32::from_le_bytes(*b"WSHM");
+
+/// Version of the shared memory protocol written to the header
+pub const PROTOCOL_VERSION: u32 = 7;
+
+/// Feature bit: senders may split messages into fragments
+pub const FEATURE_FRAGMENTATION: u32 = 1 << 0;
+
+/// Feature bit: readers zero each message once they have consumed it
+pub const FEATURE_SCRUB_AFTER_READ: u32 = 1 << 1;
+
+/// Feature bits understood by this version of the crate
+pub const SUPPORTED_FEATURES: u32 = FEATURE_FRAGMENTATION | FEATURE_SCRUB_AFTER_READ;
+
+/// How messages are stored in the data buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelMode {
+    /// Single-slot mailbox per direction, each send replaces the previous message
+    Mailbox,
+    /// Ring buffer per direction that queues many length-prefixed messages
+    Ring,
+}
+
+impl ChannelMode {
+    /// Returns the value stored in the header for this mode.
+    pub fn as_u32(self) -> u32 {
+        match self {
+            ChannelMode::Mailbox => 0,
+            ChannelMode::Ring => 1,
+        }
+    }
+
+    /// Parses a mode value read from the header.
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(ChannelMode::Mailbox),
+            1 => Some(ChannelMode::Ring),
+            _ => None,
+        }
+    }
+}
+
+/// What a server does when its shared memory name is already in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CreationPolicy {
+    /// Fail with [`Error::AlreadyExists`] if the name exists at all
+    CreateNew,
+    /// Reuse a mapping left behind by a server that is gone, but fail with
+    /// [`Error::AlreadyExists`] while another server is running
+    #[default]
+    OpenOrCreate,
+    /// Reinitialize the mapping even if another server is still running on it.
+    ///
+    /// Clients of the old server see it as gone. On Linux the old server still
+    /// removes the name when it is dropped, so new clients cannot connect after that.
+    TakeOver,
+}
+
+/// Sizes of the data buffers in each direction of a client slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferSizes {
+    /// Size of the buffer the server writes and the client reads, in bytes
+    pub server_to_client: usize,
+    /// Size of the buffer the client writes and the server reads, in bytes
+    pub client_to_server: usize,
+}
+
+impl BufferSizes {
+    /// Creates buffer sizes for each direction.
+    pub fn new(server_to_client: usize, client_to_server: usize) -> Self {
+        Self {
+            server_to_client,
+            client_to_server,
+        }
+    }
+
+    /// Uses the same buffer size in both directions.
+    pub fn symmetric(buffer_size: usize) -> Self {
+        Self::new(buffer_size, buffer_size)
+    }
+
+    /// Returns the size of the buffer written in `direction`.
+    pub fn get(&self, direction: Direction) -> usize {
+        match direction {
+            Direction::ServerToClient => self.server_to_client,
+            Direction::ClientToServer => self.client_to_server,
+        }
+    }
+
+    /// Returns the combined size of both buffers of one client slot.
+    pub fn per_slot(&self) -> usize {
+        self.server_to_client + self.client_to_server
+    }
+}
+
 /// Header structure for shared memory.
 ///
 /// This structure contains metadata and is placed at the beginning of shared memory.
 /// It is designed to work across 32-bit and 64-bit processes.
+///
+/// The header is followed by `max_clients` [`ClientSlot`]s and then, for each
+/// slot, a server-to-client and a client-to-server data buffer.
 #[repr(C, align(8))]
 pub struct SharedDataHeader {
-    /// Buffer size for each direction
-    pub buffer_size: u32,
-    /// Server state flag - 0: waiting, 1: data sent, 2: data received, 3: exit
+    /// Always [`HEADER_MAGIC`]
+    pub magic: u32,
+    /// Protocol version of the creator, see [`PROTOCOL_VERSION`]
+    pub version: u32,
+    /// Size of this header in bytes
+    pub header_size: u32,
+    /// Feature bits used by the creator, see [`SUPPORTED_FEATURES`]
+    pub features: u32,
+    /// Size of the server-to-client buffer of each client slot
+    pub buffer_size_s2c: u32,
+    /// Size of the client-to-server buffer of each client slot
+    pub buffer_size_c2s: u32,
+    /// Channel mode - 0: mailbox, 1: ring buffer
+    pub mode: u32,
+    /// Number of client slots
+    pub max_clients: u32,
+    /// Process ID of the server, 0 if unknown
+    pub server_pid: AtomicU32,
+    /// Time on the system's monotonic millisecond clock until which the server
+    /// counts as alive, 0 if it does not send heartbeats
+    pub server_heartbeat: AtomicU64,
+    /// Changes every time a server initializes the shared memory. 0 while the server
+    /// is still setting up and once it is dropped, so it doubles as the readiness flag
+    pub generation: AtomicU64,
+}
+
+/// Per-client state stored after the [`SharedDataHeader`].
+#[repr(C, align(8))]
+pub struct ClientSlot {
+    /// Slot state - 0: free, 1: claimed by a client
+    pub state: AtomicU32,
+    /// Server state flag - 0: waiting, 1: data sent, 2: data received, 3: close requested,
+    /// 4: fragment sent, 5: close acknowledged, 6: data being read in place
     pub flag_server: AtomicU32,
-    /// Client state flag - 0: waiting, 1: data sent, 2: data received, 3: exit
+    /// Client state flag - 0: waiting, 1: data sent, 2: data received, 3: close requested,
+    /// 4: fragment sent, 5: close acknowledged, 6: data being read in place
     pub flag_client: AtomicU32,
     /// Length of data from server to client
     pub data_len_server_to_client: u32,
     /// Length of data from client to server
     pub data_len_client_to_server: u32,
-    /// Padding for alignment
-    _padding: u32,
+    /// Ring buffer write offset, server to client
+    pub ring_head_s2c: AtomicU32,
+    /// Ring buffer read offset, server to client
+    pub ring_tail_s2c: AtomicU32,
+    /// Ring buffer write offset, client to server
+    pub ring_head_c2s: AtomicU32,
+    /// Ring buffer read offset, client to server
+    pub ring_tail_c2s: AtomicU32,
+    /// Process ID of the client holding the slot, 0 if unknown
+    pub client_pid: AtomicU32,
+    /// Time on the system's monotonic millisecond clock until which the client
+    /// counts as alive, 0 if it does not send heartbeats
+    pub client_heartbeat: AtomicU64,
 }
 
-impl SharedDataHeader {
-    /// Creates a new header with the specified buffer size.
-    pub fn new(buffer_size: usize) -> Self {
+impl ClientSlot {
+    /// Creates a free slot with empty buffers.
+    pub fn new() -> Self {
         Self {
-            buffer_size: buffer_size as u32,
+            state: AtomicU32::new(0),
             flag_server: AtomicU32::new(0),
             flag_client: AtomicU32::new(0),
             data_len_server_to_client: 0,
             data_len_client_to_server: 0,
-            _padding: 0,
+            ring_head_s2c: AtomicU32::new(0),
+            ring_tail_s2c: AtomicU32::new(0),
+            ring_head_c2s: AtomicU32::new(0),
+            ring_tail_c2s: AtomicU32::new(0),
+            client_pid: AtomicU32::new(0),
+            client_heartbeat: AtomicU64::new(0),
         }
     }
+}
 
-    /// Returns the total size of shared memory needed for the given buffer size.
+impl Default for ClientSlot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SharedDataHeader {
+    /// Creates a new mailbox header with the specified buffer size.
+    pub fn new(buffer_size: usize) -> Self {
+        Self::with_mode(buffer_size, ChannelMode::Mailbox)
+    }
+
+    /// Creates a new single-client header with the specified buffer size and channel mode.
+    pub fn with_mode(buffer_size: usize, mode: ChannelMode) -> Self {
+        Self::with_clients(buffer_size, mode, 1)
+    }
+
+    /// Creates a new header with the specified buffer size, channel mode and number of client slots.
+    pub fn with_clients(buffer_size: usize, mode: ChannelMode, max_clients: usize) -> Self {
+        Self::with_buffers(BufferSizes::symmetric(buffer_size), mode, max_clients)
+    }
+
+    /// Creates a new header with separate buffer sizes per direction.
+    pub fn with_buffers(buffers: BufferSizes, mode: ChannelMode, max_clients: usize) -> Self {
+        Self {
+            magic: HEADER_MAGIC,
+            version: PROTOCOL_VERSION,
+            header_size: std::mem::size_of::<SharedDataHeader>() as u32,
+            features: FEATURE_FRAGMENTATION,
+            buffer_size_s2c: buffers.server_to_client as u32,
+            buffer_size_c2s: buffers.client_to_server as u32,
+            mode: mode.as_u32(),
+            max_clients: max_clients as u32,
+            server_pid: AtomicU32::new(0),
+            server_heartbeat: AtomicU64::new(0),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Checks that the header was written by a compatible version of this crate.
+    ///
+    /// Returns [`Error::HeaderMismatch`] describing the first problem found.
+    pub fn validate(&self) -> Result<()> {
+        if self.magic != HEADER_MAGIC {
+            return Err(Error::HeaderMismatch(format!(
+                "bad magic number 0x{:08x}, not a shared memory channel",
+                self.magic
+            )));
+        }
+        if self.version != PROTOCOL_VERSION {
+            return Err(Error::HeaderMismatch(format!(
+                "protocol version {} is not supported (expected {})",
+                self.version, PROTOCOL_VERSION
+            )));
+        }
+        if self.header_size as usize != std::mem::size_of::<SharedDataHeader>() {
+            return Err(Error::HeaderMismatch(format!(
+                "header size {} does not match the expected {}",
+                self.header_size,
+                std::mem::size_of::<SharedDataHeader>()
+            )));
+        }
+        if self.features & !SUPPORTED_FEATURES != 0 {
+            return Err(Error::HeaderMismatch(format!(
+                "unsupported feature bits 0x{:x}",
+                self.features & !SUPPORTED_FEATURES
+            )));
+        }
+        if self.max_clients == 0 {
+            return Err(Error::HeaderMismatch("no client slots".to_string()));
+        }
+        // No mapping can be larger than isize::MAX bytes
+        let size = Self::checked_total_size_with_buffers(self.buffer_sizes(), self.max_clients as usize);
+        if size.is_none_or(|size| size > isize::MAX as usize) {
+            return Err(Error::HeaderMismatch(format!(
+                "buffers of {} and {} bytes for {} client slots do not fit in memory",
+                self.buffer_size_s2c, self.buffer_size_c2s, self.max_clients
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the buffer sizes recorded in the header.
+    pub fn buffer_sizes(&self) -> BufferSizes {
+        BufferSizes::new(self.buffer_size_s2c as usize, self.buffer_size_c2s as usize)
+    }
+
+    /// Returns the total size of shared memory needed for one client with the given buffer size.
     pub fn total_size(buffer_size: usize) -> usize {
-        std::mem::size_of::<SharedDataHeader>() + buffer_size * 2
+        Self::total_size_with_clients(buffer_size, 1)
+    }
+
+    /// Returns the total size of shared memory needed for `max_clients` client slots.
+    pub fn total_size_with_clients(buffer_size: usize, max_clients: usize) -> usize {
+        Self::total_size_with_buffers(BufferSizes::symmetric(buffer_size), max_clients)
+    }
+
+    /// Returns the total size of shared memory needed for `max_clients` client
+    /// slots with separate buffer sizes per direction.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size does not fit in a `usize`, see
+    /// [`checked_total_size_with_buffers`](Self::checked_total_size_with_buffers).
+    pub fn total_size_with_buffers(buffers: BufferSizes, max_clients: usize) -> usize {
+        Self::checked_total_size_with_buffers(buffers, max_clients).expect("shared memory size overflows usize")
+    }
+
+    /// Like [`total_size_with_buffers`](Self::total_size_with_buffers), but returns None
+    /// if the size does not fit in a `usize`.
+    pub fn checked_total_size_with_buffers(buffers: BufferSizes, max_clients: usize) -> Option<usize> {
+        let slots = max_clients.checked_mul(std::mem::size_of::<ClientSlot>())?;
+        let per_slot = buffers.server_to_client.checked_add(buffers.client_to_server)?;
+        let buffers = per_slot.checked_mul(max_clients)?;
+        std::mem::size_of::<SharedDataHeader>().checked_add(slots)?.checked_add(buffers)
+    }
+
+    /// Returns the offset to the [`ClientSlot`] of `slot`.
+    pub fn slot_offset(slot: usize) -> usize {
+        std::mem::size_of::<SharedDataHeader>() + slot * std::mem::size_of::<ClientSlot>()
     }
 
-    /// Returns the offset to the server-to-client data buffer.
-    pub fn offset_s2c() -> usize {
-        std::mem::size_of::<SharedDataHeader>()
+    /// Returns the offset to the server-to-client data buffer of `slot`.
+    pub fn offset_s2c(buffers: BufferSizes, max_clients: usize, slot: usize) -> usize {
+        Self::slot_offset(max_clients) + buffers.per_slot() * slot
     }
 
-    /// Returns the offset to the client-to-server data buffer.
-    pub fn offset_c2s(buffer_size: usize) -> usize {
-        std::mem::size_of::<SharedDataHeader>() + buffer_size
+    /// Returns the offset to the client-to-server data buffer of `slot`.
+    pub fn offset_c2s(buffers: BufferSizes, max_clients: usize, slot: usize) -> usize {
+        Self::offset_s2c(buffers, max_clients, slot) + buffers.server_to_client
     }
 }
 
+/// Identifies a client slot of a [`Server`](crate::Server).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientId(pub usize);
+
 /// Result of receiving a message from shared memory.
 #[derive(Debug)]
 pub enum ReceiveMessage {
@@ -63,6 +338,8 @@ pub enum ReceiveMessage {
     MessageError(String),
     /// The receive operation timed out
     Timeout,
+    /// The sender's process exited or its heartbeat stopped
+    PeerDied,
 }
 
 /// Result of receiving raw bytes from shared memory.
@@ -76,4 +353,30 @@ pub enum ReceiveBytes {
     Error(String),
     /// The receive operation timed out
     Timeout,
+    /// The sender's process exited or its heartbeat stopped
+    PeerDied,
+}
+
+impl From<Result<Option<String>>> for ReceiveMessage {
+    fn from(result: Result<Option<String>>) -> Self {
+        match result {
+            Ok(Some(message)) => ReceiveMessage::Message(message),
+            Ok(None) => ReceiveMessage::Timeout,
+            Err(Error::PeerClosed) => ReceiveMessage::Exit,
+            Err(Error::PeerDied) => ReceiveMessage::PeerDied,
+            Err(e) => ReceiveMessage::MessageError(e.to_string()),
+        }
+    }
+}
+
+impl From<Result<Option<Vec<u8>>>> for ReceiveBytes {
+    fn from(result: Result<Option<Vec<u8>>>) -> Self {
+        match result {
+            Ok(Some(bytes)) => ReceiveBytes::Bytes(bytes),
+            Ok(None) => ReceiveBytes::Timeout,
+            Err(Error::PeerClosed) => ReceiveBytes::Exit,
+            Err(Error::PeerDied) => ReceiveBytes::PeerDied,
+            Err(e) => ReceiveBytes::Error(e.to_string()),
+        }
+    }
 }
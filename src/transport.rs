@@ -0,0 +1,435 @@
+use crate::liveness;
+use crate::{CreationPolicy, Error, Event, Mapping, Result, Security, SharedDataHeader};
+use std::cell::UnsafeCell;
+use std::io;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Direction of a data buffer and of the signal that announces it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Data written by the server and read by the client
+    ServerToClient,
+    /// Data written by the client and read by the server
+    ClientToServer,
+}
+
+/// A shared memory region plus the signals used to wake the peer.
+///
+/// The region starts with a [`SharedDataHeader`] followed by the data buffers.
+/// [`Server`](crate::Server) and [`Client`](crate::Client) only talk to the
+/// outside world through this trait, so they can run over any implementation.
+pub trait Transport {
+    /// Returns the base address of the shared region.
+    fn as_ptr(&self) -> *mut u8;
+
+    /// Returns the size of the shared region in bytes.
+    fn size(&self) -> usize;
+
+    /// Signals the peer that new data is available in `direction` of client slot `slot`.
+    ///
+    /// A client-to-server signal wakes both a [`wait`](Self::wait) for `slot`
+    /// and a [`wait_any`](Self::wait_any).
+    fn signal(&self, direction: Direction, slot: usize) -> Result<()>;
+
+    /// Waits for a signal in `direction` of client slot `slot`.
+    ///
+    /// Returns `Ok(false)` if the timeout elapsed. `None` waits indefinitely.
+    fn wait(&self, direction: Direction, slot: usize, timeout_ms: Option<u32>) -> Result<bool>;
+
+    /// Waits for a client-to-server signal from any slot.
+    ///
+    /// The default suits transports whose client-to-server signal is shared by all slots.
+    fn wait_any(&self, timeout_ms: Option<u32>) -> Result<bool> {
+        self.wait(Direction::ClientToServer, 0, timeout_ms)
+    }
+}
+
+/// Names of the events used by a [`ShmTransport`].
+///
+/// By default the names are derived from the mapping name, so independent
+/// channels never share events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventNames {
+    /// Event signaled when the server has sent data
+    pub server_to_client: String,
+    /// Event signaled when any client has sent data
+    pub client_to_server: String,
+}
+
+impl EventNames {
+    /// Derives the event names from a mapping name.
+    ///
+    /// `Local\MySharedMemory` uses `Local\MySharedMemory_S2C` and `Local\MySharedMemory_C2S`.
+    pub fn for_mapping(mapping_name: &str) -> Self {
+        Self {
+            server_to_client: format!("{}_S2C", mapping_name),
+            client_to_server: format!("{}_C2S", mapping_name),
+        }
+    }
+
+    /// Returns the name of the server-to-client event of client slot `slot`.
+    ///
+    /// Slot 0 uses `server_to_client` as is, other slots append `_{slot}`.
+    pub fn server_to_client_slot(&self, slot: usize) -> String {
+        match slot {
+            0 => self.server_to_client.clone(),
+            _ => format!("{}_{}", self.server_to_client, slot),
+        }
+    }
+
+    /// Returns the name of the client-to-server event of client slot `slot`.
+    ///
+    /// Every slot appends `_{slot}`, since `client_to_server` itself wakes a
+    /// server waiting for any client.
+    pub fn client_to_server_slot(&self, slot: usize) -> String {
+        format!("{}_{}", self.client_to_server, slot)
+    }
+}
+
+/// How [`ShmTransport`] creates its named objects.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CreateOptions {
+    pub(crate) policy: CreationPolicy,
+    pub(crate) security: Security,
+}
+
+/// Named OS shared memory and events.
+///
+/// Uses file mappings and events on Windows, and POSIX shared memory with
+/// futexes on Linux.
+pub struct ShmTransport {
+    size: usize,
+    // One event per client slot
+    event_s2c: Vec<Event>,
+    event_c2s: Vec<Event>,
+    // Signaled along with every client-to-server event
+    event_any: Event,
+    mapping: Mapping,
+}
+
+impl ShmTransport {
+    /// Creates the named shared memory of `size` bytes and its events.
+    ///
+    /// Event names are derived from the mapping name with [`EventNames::for_mapping`].
+    pub fn create(mapping_name: &str, size: usize) -> Result<Self> {
+        Self::create_with_events(mapping_name, size, &EventNames::for_mapping(mapping_name))
+    }
+
+    /// Creates the named shared memory of `size` bytes and the given events.
+    pub fn create_with_events(mapping_name: &str, size: usize, events: &EventNames) -> Result<Self> {
+        Self::create_with_clients(mapping_name, size, events, 1)
+    }
+
+    /// Creates the named shared memory of `size` bytes and the events for `max_clients` client slots.
+    ///
+    /// Fails with [`Error::AlreadyExists`] while another server is running on the name,
+    /// see [`CreationPolicy::OpenOrCreate`].
+    pub fn create_with_clients(
+        mapping_name: &str,
+        size: usize,
+        events: &EventNames,
+        max_clients: usize,
+    ) -> Result<Self> {
+        Self::create_with_policy(mapping_name, size, events, max_clients, CreationPolicy::OpenOrCreate)
+    }
+
+    /// Creates the named shared memory of `size` bytes and the events for `max_clients` client slots,
+    /// handling an existing mapping of the same name according to `policy`.
+    pub fn create_with_policy(
+        mapping_name: &str,
+        size: usize,
+        events: &EventNames,
+        max_clients: usize,
+        policy: CreationPolicy,
+    ) -> Result<Self> {
+        let options = CreateOptions {
+            policy,
+            ..CreateOptions::default()
+        };
+        Self::create_with_options(mapping_name, size, events, max_clients, &options)
+    }
+
+    pub(crate) fn create_with_options(
+        mapping_name: &str,
+        size: usize,
+        events: &EventNames,
+        max_clients: usize,
+        options: &CreateOptions,
+    ) -> Result<Self> {
+        let security = &options.security;
+        let mapping = match options.policy {
+            CreationPolicy::CreateNew => Mapping::create(mapping_name, size, true, security)?,
+            CreationPolicy::OpenOrCreate => match Mapping::create(mapping_name, size, true, security) {
+                Err(Error::AlreadyExists) if Self::server_running(mapping_name)? => {
+                    return Err(Error::AlreadyExists);
+                }
+                // Left behind by a server that is gone
+                Err(Error::AlreadyExists) => Mapping::create(mapping_name, size, false, security)?,
+                result => result?,
+            },
+            CreationPolicy::TakeOver => Mapping::create(mapping_name, size, false, security)?,
+        };
+        let event_s2c = (0..max_clients.max(1))
+            .map(|slot| Event::create_secured(&events.server_to_client_slot(slot), security))
+            .collect::<Result<_>>()?;
+        let event_c2s = (0..max_clients.max(1))
+            .map(|slot| Event::create_secured(&events.client_to_server_slot(slot), security))
+            .collect::<Result<_>>()?;
+        let event_any = Event::create_secured(&events.client_to_server, security)?;
+
+        Ok(Self {
+            size,
+            event_s2c,
+            event_c2s,
+            event_any,
+            mapping,
+        })
+    }
+
+    /// Opens the named shared memory created by a server.
+    ///
+    /// The size of the region is read from the header written by the server.
+    /// Event names are derived from the mapping name with [`EventNames::for_mapping`].
+    pub fn open(mapping_name: &str) -> Result<Self> {
+        Self::open_with_events(mapping_name, &EventNames::for_mapping(mapping_name))
+    }
+
+    /// Opens the named shared memory created by a server, using the given events.
+    pub fn open_with_events(mapping_name: &str, events: &EventNames) -> Result<Self> {
+        // First, map only the header to read buffer size
+        let header_size = std::mem::size_of::<SharedDataHeader>();
+        let mut mapping = Mapping::open(mapping_name, header_size)?;
+
+        // Validate the header, then read buffer size from it
+        let header = mapping.as_ptr() as *const SharedDataHeader;
+        unsafe { (*header).validate()? };
+        let buffers = unsafe { (*header).buffer_sizes() };
+        let max_clients = unsafe { (*header).max_clients as usize };
+
+        // Now map the full shared memory
+        let size = SharedDataHeader::total_size_with_buffers(buffers, max_clients);
+        mapping.remap(size)?;
+
+        // Open event objects
+        let event_s2c = (0..max_clients)
+            .map(|slot| Event::open(&events.server_to_client_slot(slot)))
+            .collect::<Result<_>>()?;
+        let event_c2s = (0..max_clients)
+            .map(|slot| Event::open(&events.client_to_server_slot(slot)))
+            .collect::<Result<_>>()?;
+        let event_any = Event::open(&events.client_to_server)?;
+
+        Ok(Self {
+            size,
+            event_s2c,
+            event_c2s,
+            event_any,
+            mapping,
+        })
+    }
+
+    /// Returns true once a server has finished setting up the named shared memory.
+    ///
+    /// Returns `Ok(false)` if the mapping does not exist yet or its header is
+    /// still being initialized, and an error for anything else.
+    pub(crate) fn server_ready(mapping_name: &str) -> Result<bool> {
+        // The server stores its generation last
+        Self::inspect_header(mapping_name, |header| header.generation.load(Ordering::Acquire) != 0)
+    }
+
+    /// Returns true if a live server is using the named shared memory.
+    fn server_running(mapping_name: &str) -> Result<bool> {
+        Self::inspect_header(mapping_name, |header| {
+            header.generation.load(Ordering::Acquire) != 0
+                && liveness::peer_alive(
+                    header.server_pid.load(Ordering::Acquire),
+                    header.server_heartbeat.load(Ordering::Acquire),
+                )
+        })
+    }
+
+    /// Maps the header of the named shared memory and passes it to `check`.
+    ///
+    /// Returns `Ok(false)` if the mapping does not exist or has no room for a header yet.
+    fn inspect_header(mapping_name: &str, check: impl FnOnce(&SharedDataHeader) -> bool) -> Result<bool> {
+        let mapping = match Mapping::open(mapping_name, std::mem::size_of::<SharedDataHeader>()) {
+            Ok(mapping) => mapping,
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            // On Linux the object exists before the server has sized it
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::InvalidData => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        Ok(check(unsafe { &*(mapping.as_ptr() as *const SharedDataHeader) }))
+    }
+
+    fn event(&self, direction: Direction, slot: usize) -> Result<&Event> {
+        match direction {
+            Direction::ServerToClient => self.event_s2c.get(slot).ok_or_else(|| no_such_slot(slot)),
+            Direction::ClientToServer => self.event_c2s.get(slot).ok_or_else(|| no_such_slot(slot)),
+        }
+    }
+}
+
+fn no_such_slot(slot: usize) -> crate::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("no client slot {}", slot)).into()
+}
+
+impl Transport for ShmTransport {
+    fn as_ptr(&self) -> *mut u8 {
+        self.mapping.as_ptr()
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn signal(&self, direction: Direction, slot: usize) -> Result<()> {
+        self.event(direction, slot)?.set()?;
+        if direction == Direction::ClientToServer {
+            self.event_any.set()?;
+        }
+        Ok(())
+    }
+
+    fn wait(&self, direction: Direction, slot: usize, timeout_ms: Option<u32>) -> Result<bool> {
+        self.event(direction, slot)?.wait(timeout_ms)
+    }
+
+    fn wait_any(&self, timeout_ms: Option<u32>) -> Result<bool> {
+        self.event_any.wait(timeout_ms)
+    }
+}
+
+/// Auto-reset event for threads of the same process.
+struct HeapEvent {
+    signaled: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl HeapEvent {
+    fn new() -> Self {
+        Self {
+            signaled: Mutex::new(false),
+            condvar: Condvar::new(),
+        }
+    }
+
+    fn set(&self) {
+        *self.signaled.lock().unwrap() = true;
+        self.condvar.notify_one();
+    }
+
+    fn wait(&self, timeout_ms: Option<u32>) -> bool {
+        let deadline = timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms as u64));
+        let mut signaled = self.signaled.lock().unwrap();
+
+        while !*signaled {
+            signaled = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return false;
+                    }
+                    self.condvar.wait_timeout(signaled, remaining).unwrap().0
+                }
+                None => self.condvar.wait(signaled).unwrap(),
+            };
+        }
+
+        *signaled = false;
+        true
+    }
+}
+
+struct HeapRegion {
+    // u64 words keep the header 8-byte aligned
+    memory: Box<[UnsafeCell<u64>]>,
+    size: usize,
+    // One event per client slot and direction, created on first use
+    event_s2c: Mutex<Vec<Arc<HeapEvent>>>,
+    event_c2s: Mutex<Vec<Arc<HeapEvent>>>,
+    // Signaled along with every client-to-server event
+    event_any: HeapEvent,
+}
+
+// The memory is only accessed through raw pointers, synchronized by the
+// atomic flags in the header exactly like OS shared memory.
+unsafe impl Send for HeapRegion {}
+unsafe impl Sync for HeapRegion {}
+
+/// In-process transport backed by a heap allocation.
+///
+/// Clones share the same region, so a [`Server`](crate::Server) and a
+/// [`Client`](crate::Client) can be connected without any OS objects.
+///
+/// # Example
+///
+/// ```
+/// use windows_shared_memory::{Client, HeapTransport, ReceiveMessage, Server, SharedDataHeader};
+///
+/// let transport = HeapTransport::new(SharedDataHeader::total_size(1024));
+/// let server = Server::with_transport(transport.clone(), 1024).unwrap();
+/// let client = Client::with_transport(transport).unwrap();
+///
+/// server.send(b"hello").unwrap();
+/// assert!(matches!(client.receive(Some(100)), ReceiveMessage::Message(m) if m == "hello"));
+/// ```
+#[derive(Clone)]
+pub struct HeapTransport {
+    region: Arc<HeapRegion>,
+}
+
+impl HeapTransport {
+    /// Allocates a zeroed region of `size` bytes.
+    pub fn new(size: usize) -> Self {
+        let words = size.div_ceil(std::mem::size_of::<u64>());
+        Self {
+            region: Arc::new(HeapRegion {
+                memory: (0..words).map(|_| UnsafeCell::new(0)).collect(),
+                size,
+                event_s2c: Mutex::new(Vec::new()),
+                event_c2s: Mutex::new(Vec::new()),
+                event_any: HeapEvent::new(),
+            }),
+        }
+    }
+
+    fn event(&self, direction: Direction, slot: usize) -> Arc<HeapEvent> {
+        let mut events = match direction {
+            Direction::ServerToClient => self.region.event_s2c.lock().unwrap(),
+            Direction::ClientToServer => self.region.event_c2s.lock().unwrap(),
+        };
+        if events.len() <= slot {
+            events.resize_with(slot + 1, || Arc::new(HeapEvent::new()));
+        }
+        events[slot].clone()
+    }
+}
+
+impl Transport for HeapTransport {
+    fn as_ptr(&self) -> *mut u8 {
+        UnsafeCell::raw_get(self.region.memory.as_ptr()) as *mut u8
+    }
+
+    fn size(&self) -> usize {
+        self.region.size
+    }
+
+    fn signal(&self, direction: Direction, slot: usize) -> Result<()> {
+        self.event(direction, slot).set();
+        if direction == Direction::ClientToServer {
+            self.region.event_any.set();
+        }
+        Ok(())
+    }
+
+    fn wait(&self, direction: Direction, slot: usize, timeout_ms: Option<u32>) -> Result<bool> {
+        Ok(self.event(direction, slot).wait(timeout_ms))
+    }
+
+    fn wait_any(&self, timeout_ms: Option<u32>) -> Result<bool> {
+        Ok(self.region.event_any.wait(timeout_ms))
+    }
+}
@@ -1,4 +1,5 @@
 use windows::Win32::Foundation::{HANDLE, WAIT_OBJECT_0, WAIT_TIMEOUT};
+use windows::Win32::Security::SECURITY_ATTRIBUTES;
 use windows::Win32::System::Threading::*;
 use windows::core::{Result, HSTRING};
 
@@ -21,8 +22,16 @@ pub fn open_event(event_name: &str) -> Result<HANDLE> {
 
 /// Creates an event object
 pub fn create_event(event_name: &str) -> Result<HANDLE> {
+    create_event_with_attributes(event_name, None)
+}
+
+/// Creates an event object with the given security attributes
+pub(crate) fn create_event_with_attributes(
+    event_name: &str,
+    attributes: Option<*const SECURITY_ATTRIBUTES>,
+) -> Result<HANDLE> {
     let event_name_pcwstr = str_to_pcwstr(event_name);
-    let h_event = unsafe { CreateEventW(None, false, false, &event_name_pcwstr) }?;
+    let h_event = unsafe { CreateEventW(attributes, false, false, &event_name_pcwstr) }?;
 
     if h_event.is_invalid() {
         return Err(windows::core::Error::from_thread());
@@ -0,0 +1,226 @@
+use crate::{create_event_with_attributes, open_event, str_to_pcwstr, wait_for_event, Error, Result};
+use windows::Win32::{
+    Foundation::{
+        CloseHandle, GetLastError, LocalFree, E_ACCESSDENIED, ERROR_ALREADY_EXISTS, HANDLE, HLOCAL,
+        INVALID_HANDLE_VALUE, STILL_ACTIVE,
+    },
+    Security::{
+        Authorization::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1},
+        PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES,
+    },
+    System::{
+        Memory::*,
+        SystemInformation::GetTickCount64,
+        Threading::{GetExitCodeProcess, OpenProcess, SetEvent, PROCESS_QUERY_LIMITED_INFORMATION},
+    },
+};
+
+/// Returns false if the process with ID `pid` has exited.
+pub(crate) fn process_alive(pid: u32) -> bool {
+    let process = match unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) } {
+        Ok(process) => process,
+        // Access denied means the process exists but may not be queried
+        Err(e) => return e.code() == E_ACCESSDENIED,
+    };
+    let mut exit_code = 0u32;
+    let queried = unsafe { GetExitCodeProcess(process, &mut exit_code) };
+    let _ = unsafe { CloseHandle(process) };
+    queried.is_err() || exit_code == STILL_ACTIVE.0 as u32
+}
+
+/// Returns the milliseconds since system start, which all processes share.
+///
+/// The clock is monotonic, so heartbeats survive changes of the wall-clock time.
+pub(crate) fn monotonic_ms() -> u64 {
+    unsafe { GetTickCount64() }
+}
+
+/// Access rights of newly created mappings and events.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Security {
+    /// Security descriptor in SDDL form, the creator's default descriptor if None
+    pub(crate) descriptor: Option<String>,
+}
+
+impl Security {
+    fn attributes(&self) -> Result<Option<SecurityAttributes>> {
+        self.descriptor.as_deref().map(SecurityAttributes::from_sddl).transpose()
+    }
+}
+
+/// Security attributes holding a descriptor converted from SDDL.
+struct SecurityAttributes(SECURITY_ATTRIBUTES);
+
+impl SecurityAttributes {
+    fn from_sddl(sddl: &str) -> Result<Self> {
+        let mut descriptor = PSECURITY_DESCRIPTOR::default();
+        unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                &str_to_pcwstr(sddl),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                None,
+            )?
+        };
+        Ok(Self(SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: descriptor.0,
+            bInheritHandle: false.into(),
+        }))
+    }
+
+    fn as_ptr(&self) -> *const SECURITY_ATTRIBUTES {
+        &self.0
+    }
+}
+
+impl Drop for SecurityAttributes {
+    fn drop(&mut self) {
+        // The descriptor was allocated by ConvertStringSecurityDescriptorToSecurityDescriptorW
+        unsafe { LocalFree(Some(HLOCAL(self.0.lpSecurityDescriptor))) };
+    }
+}
+
+/// A named file mapping and its mapped view.
+pub(crate) struct Mapping {
+    h_map_file: HANDLE,
+    view: MEMORY_MAPPED_VIEW_ADDRESS,
+}
+
+// Handles and views are not tied to the thread that created them
+unsafe impl Send for Mapping {}
+// Shared references only read the view address; the memory behind it is accessed
+// atomically or under the protocol's flags
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    /// Creates (or opens, if it already exists) a named mapping and maps `size` bytes.
+    pub(crate) fn create(name: &str, size: usize, exclusive: bool, security: &Security) -> Result<Self> {
+        let name_pcwstr = str_to_pcwstr(name);
+        let attributes = security.attributes()?;
+        let h_map_file = unsafe {
+            CreateFileMappingW(
+                INVALID_HANDLE_VALUE,
+                attributes.as_ref().map(SecurityAttributes::as_ptr),
+                PAGE_READWRITE,
+                0,
+                size as u32,
+                &name_pcwstr,
+            )?
+        };
+        // An existing mapping is opened instead of failing
+        if exclusive && unsafe { GetLastError() } == ERROR_ALREADY_EXISTS {
+            unsafe { CloseHandle(h_map_file)? };
+            return Err(Error::AlreadyExists);
+        }
+        Self::map(h_map_file, size)
+    }
+
+    /// Opens an existing named mapping and maps `size` bytes of it.
+    pub(crate) fn open(name: &str, size: usize) -> Result<Self> {
+        let name_pcwstr = str_to_pcwstr(name);
+        let h_map_file = unsafe { OpenFileMappingW(FILE_MAP_ALL_ACCESS.0, false, &name_pcwstr)? };
+        Self::map(h_map_file, size)
+    }
+
+    fn map(h_map_file: HANDLE, size: usize) -> Result<Self> {
+        let view = unsafe { MapViewOfFile(h_map_file, FILE_MAP_ALL_ACCESS, 0, 0, size) };
+
+        if view.Value.is_null() {
+            let err = windows::core::Error::from_thread();
+            unsafe { CloseHandle(h_map_file)? };
+            return Err(err.into());
+        }
+
+        Ok(Self { h_map_file, view })
+    }
+
+    /// Replaces the current view with a view of `size` bytes.
+    pub(crate) fn remap(&mut self, size: usize) -> Result<()> {
+        unsafe {
+            UnmapViewOfFile(self.view)?;
+            self.view = MapViewOfFile(self.h_map_file, FILE_MAP_ALL_ACCESS, 0, 0, size);
+        }
+
+        if self.view.Value.is_null() {
+            return Err(windows::core::Error::from_thread().into());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the base address of the mapped view.
+    pub(crate) fn as_ptr(&self) -> *mut u8 {
+        self.view.Value as *mut u8
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.view.Value.is_null()
+                && let Err(e) = UnmapViewOfFile(self.view)
+            {
+                eprintln!("Failed to unmap shared memory: {:?}", e);
+            }
+
+            if let Err(e) = CloseHandle(self.h_map_file) {
+                eprintln!("Failed to close file mapping handle: {:?}", e);
+            }
+        }
+    }
+}
+
+/// A named auto-reset event used to wake the peer process.
+pub struct Event {
+    handle: HANDLE,
+}
+
+// Event handles may be signaled and waited on from any thread, also at the same time
+unsafe impl Send for Event {}
+unsafe impl Sync for Event {}
+
+impl Event {
+    /// Creates a named event, or opens it if it already exists.
+    pub fn create(name: &str) -> Result<Self> {
+        Self::create_secured(name, &Security::default())
+    }
+
+    /// Creates a named event with the given access rights, or opens it if it already exists.
+    pub(crate) fn create_secured(name: &str, security: &Security) -> Result<Self> {
+        let attributes = security.attributes()?;
+        Ok(Self {
+            handle: create_event_with_attributes(name, attributes.as_ref().map(SecurityAttributes::as_ptr))?,
+        })
+    }
+
+    /// Opens an existing named event.
+    pub fn open(name: &str) -> Result<Self> {
+        Ok(Self {
+            handle: open_event(name)?,
+        })
+    }
+
+    /// Signals the event, releasing one waiter.
+    pub fn set(&self) -> Result<()> {
+        unsafe { SetEvent(self.handle)? };
+        Ok(())
+    }
+
+    /// Waits for the event to be signaled.
+    ///
+    /// Returns `Ok(false)` if the timeout elapsed. `None` waits indefinitely.
+    pub fn wait(&self, timeout_ms: Option<u32>) -> Result<bool> {
+        Ok(wait_for_event(self.handle, timeout_ms)?)
+    }
+}
+
+impl Drop for Event {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(e) = CloseHandle(self.handle) {
+                eprintln!("Failed to close event handle: {:?}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+#![cfg(feature = "async")]
+
+use futures::executor::block_on;
+use futures::{FutureExt, StreamExt};
+use std::thread;
+use std::time::{Duration, Instant};
+use windows_shared_memory::{
+    AsyncClient, AsyncServer, Client, Error, HeapTransport, Server, SharedDataHeader,
+};
+
+#[test]
+fn test_async_roundtrip() {
+    let server = AsyncServer::new(Server::new(Some("Local\\TestAsyncRoundtrip")).unwrap());
+    let client = AsyncClient::new(Client::new(Some("Local\\TestAsyncRoundtrip")).unwrap());
+
+    block_on(async {
+        server.send(b"ping").await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), b"ping");
+        client.send(b"pong").await.unwrap();
+        assert_eq!(server.recv().await.unwrap(), b"pong");
+    });
+}
+
+#[test]
+fn test_async_recv_wakes_on_send_from_another_thread() {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(1024));
+    let server = AsyncServer::new(Server::with_transport(transport.clone(), 1024).unwrap());
+    let client = Client::with_transport(transport).unwrap();
+
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        client.send(b"late").unwrap();
+        client
+    });
+
+    assert_eq!(block_on(server.recv()).unwrap(), b"late");
+    drop(handle.join().unwrap());
+}
+
+#[test]
+fn test_async_send_waits_for_reader() {
+    let server = Server::with_buffer_size(Some("Local\\TestAsyncSendWaits"), 1024).unwrap();
+    let client = AsyncClient::new(Client::new(Some("Local\\TestAsyncSendWaits")).unwrap());
+
+    // Mailbox mode: every send waits until the previous message was read
+    let reader = thread::spawn(move || {
+        let mut received = Vec::new();
+        while received.len() < 20 {
+            thread::sleep(Duration::from_millis(5));
+            if let Some(data) = server.recv_bytes(Some(1000)).unwrap() {
+                received.push(data);
+            }
+        }
+        received
+    });
+
+    block_on(async {
+        for i in 0..20u8 {
+            client.send(&[i]).await.unwrap();
+        }
+    });
+
+    let expected: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i]).collect();
+    assert_eq!(reader.join().unwrap(), expected);
+}
+
+#[test]
+fn test_async_stream_ends_on_close() {
+    // Ring mode keeps queued messages readable after the close signal
+    let server = Server::with_ring_buffer(Some("Local\\TestAsyncStream"), 1024).unwrap();
+    let client = AsyncClient::new(Client::new_ring(Some("Local\\TestAsyncStream")).unwrap());
+
+    for message in [&b"one"[..], b"two", b"three"] {
+        server.send(message).unwrap();
+    }
+    server.send_close().unwrap();
+
+    let messages: Vec<Vec<u8>> = block_on(client.map(|message| message.unwrap()).collect());
+    assert_eq!(messages, vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]);
+}
+
+#[test]
+fn test_async_recv_reports_close() {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(1024));
+    let server = Server::with_transport(transport.clone(), 1024).unwrap();
+    let client = AsyncClient::new(Client::with_transport(transport).unwrap());
+
+    server.send_close().unwrap();
+    assert!(matches!(block_on(client.recv()), Err(Error::PeerClosed)));
+}
+
+#[test]
+fn test_async_drop_does_not_wait_for_unread_fragments() {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(64));
+    let mut server = Server::with_transport(transport.clone(), 64).unwrap();
+    server.set_fragmentation(true);
+    let server = AsyncServer::new(server);
+    let _client = Client::with_transport(transport).unwrap();
+
+    // The client never reads, so only the first fragment fits
+    assert!(server.send(&[7; 1000]).now_or_never().is_none());
+    thread::sleep(Duration::from_millis(50));
+
+    let start = Instant::now();
+    drop(server);
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
@@ -0,0 +1,266 @@
+use windows_shared_memory::{
+    BufferSizes, ChannelMode, Client, ClientId, CreationPolicy, Error, Namespace, ReceiveMessage, Server,
+    SharedDataHeader, ShmTransport,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_builder_defaults_and_options() {
+    let server = Server::builder()
+        .name("Local\\TestBuilderOptions")
+        .buffer_size(2048)
+        .mode(ChannelMode::Ring)
+        .max_clients(3)
+        .build()
+        .unwrap();
+    assert_eq!(server.buffer_size(), 2048);
+    assert_eq!(server.mode(), ChannelMode::Ring);
+    assert_eq!(server.max_clients(), 3);
+
+    let client = Client::new_ring(Some("Local\\TestBuilderOptions")).unwrap();
+    server.send(b"built").unwrap();
+    assert!(matches!(client.receive(Some(1000)), ReceiveMessage::Message(m) if m == "built"));
+}
+
+#[test]
+fn test_create_new_refuses_existing_name() {
+    let name = "Local\\TestCreateNew";
+    let server = Server::builder().name(name).creation_policy(CreationPolicy::CreateNew).build().unwrap();
+
+    let second = Server::builder().name(name).creation_policy(CreationPolicy::CreateNew).build();
+    assert!(matches!(second, Err(Error::AlreadyExists)));
+
+    drop(server);
+    assert!(Server::builder().name(name).creation_policy(CreationPolicy::CreateNew).build().is_ok());
+}
+
+#[test]
+fn test_open_or_create_refuses_running_server() {
+    let name = "Local\\TestOpenOrCreateRunning";
+    let server = Server::new(Some(name)).unwrap();
+    let client = Client::new(Some(name)).unwrap();
+    server.send(b"still mine").unwrap();
+
+    assert!(matches!(Server::with_buffer_size(Some(name), 1024), Err(Error::AlreadyExists)));
+
+    // The running server's header and buffers were left alone
+    assert!(client.is_connected());
+    assert!(matches!(client.receive(Some(1000)), ReceiveMessage::Message(m) if m == "still mine"));
+}
+
+#[test]
+fn test_open_or_create_reuses_abandoned_mapping() {
+    let name = "Local\\TestOpenOrCreateAbandoned";
+    // A mapping without a live server, like one left behind by a crash
+    let _leftover = ShmTransport::create(name, SharedDataHeader::total_size(1024)).unwrap();
+
+    let server = Server::with_buffer_size(Some(name), 1024).unwrap();
+    let client = Client::new(Some(name)).unwrap();
+    client.send(b"hello").unwrap();
+    assert_eq!(server.recv_bytes(Some(1000)).unwrap(), Some(b"hello".to_vec()));
+}
+
+#[test]
+fn test_take_over_running_server() {
+    let name = "Local\\TestTakeOver";
+    let old = Server::new(Some(name)).unwrap();
+    let old_client = Client::new(Some(name)).unwrap();
+
+    let new = Server::builder().name(name).creation_policy(CreationPolicy::TakeOver).build().unwrap();
+    assert!(!old_client.is_connected());
+
+    let client = Client::new(Some(name)).unwrap();
+    drop(old_client);
+    drop(old);
+
+    // Dropping the old server does not disconnect the new one
+    assert!(client.is_connected());
+    new.send(b"taken over").unwrap();
+    assert_eq!(client.recv_bytes(Some(1000)).unwrap(), Some(b"taken over".to_vec()));
+}
+
+#[test]
+fn test_client_builder_options() {
+    let name = "Local\\TestClientBuilderOptions";
+    let server = Server::builder().name(name).fragmentation(true).heartbeat(50).build().unwrap();
+    assert!(server.fragmentation());
+
+    let client = Client::builder()
+        .name(name)
+        .fragmentation(true)
+        .close_on_drop(true)
+        .close_timeout(Some(100))
+        .heartbeat(50)
+        .build()
+        .unwrap();
+    assert!(client.fragmentation());
+    assert!(client.is_peer_alive());
+    assert!(server.is_peer_alive());
+
+    // Closes with the configured timeout; the server never acknowledges
+    drop(client);
+    assert!(matches!(server.receive(Some(1000)), ReceiveMessage::Exit));
+}
+
+#[test]
+fn test_client_builder_checks_mode() {
+    let name = "Local\\TestClientBuilderMode";
+    let _server = Server::builder().name(name).build().unwrap();
+
+    assert!(Client::builder().name(name).mode(ChannelMode::Ring).build().is_err());
+    assert!(Client::builder().name(name).mode(ChannelMode::Ring).connect_timeout(Some(50)).build().is_err());
+
+    // The failed attempts left the only slot free
+    let client = Client::builder().name(name).mode(ChannelMode::Mailbox).build().unwrap();
+    assert_eq!(client.id(), ClientId(0));
+}
+
+#[test]
+fn test_builder_default_timeouts() {
+    let name = "Local\\TestBuilderDefaultTimeouts";
+    let server = Server::builder().name(name).receive_timeout(Some(50)).send_timeout(Some(50)).build().unwrap();
+    let client = Client::builder()
+        .name(name)
+        .receive_timeout(Some(50))
+        .send_timeout(Some(50))
+        .build()
+        .unwrap();
+
+    // Calls without a timeout fall back to the configured ones instead of waiting forever
+    assert_eq!(server.recv_bytes(None).unwrap(), None);
+    assert_eq!(client.recv_bytes(None).unwrap(), None);
+    client.send(b"unread").unwrap();
+    assert!(matches!(client.send_timeout(b"blocked", None), Err(Error::Timeout)));
+    server.send(b"unread").unwrap();
+    assert!(matches!(server.send_timeout(b"blocked", None), Err(Error::Timeout)));
+
+    // An explicit timeout still wins
+    let start = Instant::now();
+    assert_eq!(server.recv_bytes(Some(0)).unwrap(), Some(b"unread".to_vec()));
+    assert_eq!(server.recv_bytes(Some(200)).unwrap(), None);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+#[test]
+fn test_client_builder_connect_timeout() {
+    let name = "Local\\TestClientBuilderConnect";
+    assert!(Client::builder().name(name).build().is_err());
+    assert!(matches!(
+        Client::builder().name(name).connect_timeout(Some(50)).build(),
+        Err(Error::Timeout)
+    ));
+
+    let server_thread = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        let server = Server::builder().name(name).build().unwrap();
+        assert_eq!(server.recv_bytes(Some(5000)).unwrap(), Some(b"waited".to_vec()));
+    });
+
+    let client = Client::builder().name(name).connect_timeout(Some(5000)).build().unwrap();
+    client.send(b"waited").unwrap();
+    server_thread.join().unwrap();
+}
+
+#[test]
+fn test_namespace_replaces_prefix() {
+    let server = Server::builder()
+        .name("Global\\TestBuilderNamespace")
+        .namespace(Namespace::Local)
+        .build()
+        .unwrap();
+    let client = Client::builder()
+        .name("TestBuilderNamespace")
+        .namespace(Namespace::Local)
+        .build()
+        .unwrap();
+    client.send(b"local").unwrap();
+    assert_eq!(server.recv_bytes(Some(1000)).unwrap(), Some(b"local".to_vec()));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let _default = Server::builder().name("Local\\TestPermissionsDefault").build().unwrap();
+    let mode = std::fs::metadata("/dev/shm/TestPermissionsDefault").unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let _shared = Server::builder()
+        .name("Local\\TestPermissionsShared")
+        .permissions(0o666)
+        .build()
+        .unwrap();
+    let mode = std::fs::metadata("/dev/shm/TestPermissionsShared").unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o666);
+}
+
+#[test]
+fn test_asymmetric_buffer_sizes() {
+    let name = "Local\\TestAsymmetricBuffers";
+    let server = Server::builder()
+        .name(name)
+        .server_to_client_buffer(64 * 1024)
+        .client_to_server_buffer(256)
+        .max_clients(2)
+        .build()
+        .unwrap();
+    let client = Client::new(Some(name)).unwrap();
+    let other = Client::new(Some(name)).unwrap();
+
+    let sizes = BufferSizes::new(64 * 1024, 256);
+    assert_eq!(server.buffer_sizes(), sizes);
+    assert_eq!(client.buffer_sizes(), sizes);
+    assert_eq!(server.max_message_len(), 64 * 1024);
+    assert_eq!(client.max_message_len(), 256);
+
+    let frame = vec![7u8; 60 * 1024];
+    server.send_to(other.id(), &frame).unwrap();
+    assert!(matches!(client.send(&[0u8; 257]), Err(Error::MessageTooLarge { capacity: 256, .. })));
+    client.send(b"command").unwrap();
+    other.send(b"other").unwrap();
+
+    // Each slot's buffers are laid out with their own sizes
+    assert_eq!(other.recv_bytes(Some(1000)).unwrap(), Some(frame));
+    assert_eq!(server.recv_from(client.id(), Some(1000)).unwrap(), Some(b"command".to_vec()));
+    assert_eq!(server.recv_from(other.id(), Some(1000)).unwrap(), Some(b"other".to_vec()));
+}
+
+#[test]
+fn test_asymmetric_ring_buffers() {
+    let name = "Local\\TestAsymmetricRing";
+    let server = Server::builder()
+        .name(name)
+        .mode(ChannelMode::Ring)
+        .server_to_client_buffer(128)
+        .client_to_server_buffer(4096)
+        .build()
+        .unwrap();
+    let client = Client::new_ring(Some(name)).unwrap();
+
+    let large = vec![1u8; 2048];
+    client.send(&large).unwrap();
+    client.send(b"queued").unwrap();
+    server.send(b"small").unwrap();
+
+    assert_eq!(server.recv_bytes(Some(1000)).unwrap(), Some(large));
+    assert_eq!(server.recv_bytes(Some(1000)).unwrap(), Some(b"queued".to_vec()));
+    assert_eq!(client.recv_bytes(Some(1000)).unwrap(), Some(b"small".to_vec()));
+}
+
+#[test]
+fn test_builder_rejects_unusable_buffer_sizes() {
+    let name = "Local\\TestUnusableBuffers";
+    // Too small for a fragment header and one byte
+    assert!(Server::builder().name(name).buffer_size(8).build().is_err());
+    assert!(Server::builder().name(name).mode(ChannelMode::Ring).buffer_size(9).build().is_err());
+    assert!(Server::builder().name(name).client_to_server_buffer(0).build().is_err());
+
+    // The header records sizes in 32 bits
+    #[cfg(target_pointer_width = "64")]
+    assert!(Server::builder().name(name).server_to_client_buffer(u32::MAX as usize + 1).build().is_err());
+
+    // Nothing was created by the failed attempts
+    assert!(Server::builder().name(name).buffer_size(9).creation_policy(CreationPolicy::CreateNew).build().is_ok());
+}
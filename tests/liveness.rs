@@ -0,0 +1,199 @@
+use std::env;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use windows_shared_memory::{Client, ClientId, Error, HeapTransport, ReceiveMessage, Server, SharedDataHeader};
+
+/// Set to `role:name` when this test binary runs as a peer process.
+const CHILD_ENV: &str = "WSHM_LIVENESS_CHILD";
+
+/// Entry point of the peer processes spawned by the tests below.
+#[test]
+fn child_entry() {
+    let Ok(spec) = env::var(CHILD_ENV) else {
+        return;
+    };
+    let (role, name) = spec.split_once(':').unwrap();
+    match role {
+        "server" => {
+            let _server = Server::new(Some(name)).unwrap();
+            println!("ready");
+            thread::sleep(Duration::from_secs(60));
+        }
+        "heartbeat-server" => {
+            let mut server = Server::new(Some(name)).unwrap();
+            server.start_heartbeat(50);
+            println!("ready");
+            thread::sleep(Duration::from_secs(60));
+        }
+        "client" => {
+            let _client = Client::new(Some(name)).unwrap();
+            println!("ready");
+            thread::sleep(Duration::from_secs(60));
+        }
+        _ => panic!("unknown role {}", role),
+    }
+}
+
+/// Runs `child_entry` in a new process and waits until it is set up.
+fn spawn_peer(role: &str, name: &str) -> Child {
+    let mut child = Command::new(env::current_exe().unwrap())
+        .args(["--exact", "child_entry", "--nocapture", "--test-threads=1"])
+        .env(CHILD_ENV, format!("{}:{}", role, name))
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    while stdout.read_line(&mut line).unwrap() > 0 {
+        if line.contains("ready") {
+            return child;
+        }
+        line.clear();
+    }
+    child.wait().unwrap();
+    panic!("peer process exited before it was ready");
+}
+
+fn kill_peer(mut child: Child) {
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn test_client_detects_server_crash() {
+    let name = "Local\\TestLivenessServerCrash";
+    let child = spawn_peer("server", name);
+    let client = Client::new(Some(name)).unwrap();
+    assert!(client.is_peer_alive());
+    assert!(matches!(client.receive(Some(100)), ReceiveMessage::Timeout));
+
+    kill_peer(child);
+    assert!(!client.is_peer_alive());
+
+    let start = Instant::now();
+    assert!(matches!(client.receive(Some(5000)), ReceiveMessage::PeerDied));
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert!(matches!(client.recv(None), Err(Error::PeerDied)));
+
+    // The crashed server could not remove its shared memory
+    drop(client);
+    drop(Server::new(Some(name)));
+}
+
+#[test]
+fn test_server_detects_client_crash() {
+    let name = "Local\\TestLivenessClientCrash";
+    let server = Server::new(Some(name)).unwrap();
+    assert!(!server.is_peer_alive());
+
+    let child = spawn_peer("client", name);
+    assert!(server.is_peer_alive());
+
+    kill_peer(child);
+    assert!(!server.is_peer_alive());
+    assert!(matches!(server.receive(Some(5000)), ReceiveMessage::PeerDied));
+}
+
+#[test]
+fn test_crashed_client_slot_is_reclaimed() {
+    let name = "Local\\TestLivenessReclaimSlot";
+    let server = Server::new(Some(name)).unwrap();
+
+    let child = spawn_peer("client", name);
+    server.send(b"never read").unwrap();
+    kill_peer(child);
+
+    // The only slot is still claimed by the dead client, but can be taken over
+    let client = Client::new(Some(name)).unwrap();
+    assert_eq!(client.id(), ClientId(0));
+    assert!(server.is_peer_alive());
+    assert_eq!(client.try_recv_bytes().unwrap(), None);
+
+    server.send(b"fresh").unwrap();
+    assert_eq!(client.recv_bytes(Some(1000)).unwrap(), Some(b"fresh".to_vec()));
+}
+
+#[test]
+fn test_server_releases_crashed_client_slot() {
+    let name = "Local\\TestLivenessReleaseSlot";
+    let server = Server::new(Some(name)).unwrap();
+
+    let child = spawn_peer("client", name);
+    assert_eq!(server.connected_clients(), vec![ClientId(0)]);
+    kill_peer(child);
+
+    assert!(matches!(server.recv(Some(5000)), Err(Error::PeerDied)));
+    assert!(server.connected_clients().is_empty());
+    let client = Client::new(Some(name)).unwrap();
+    assert_eq!(client.id(), ClientId(0));
+}
+
+#[test]
+fn test_receive_any_detects_client_crash() {
+    let name = "Local\\TestLivenessReceiveAny";
+    let server = Server::with_clients(Some(name), 1024, 2).unwrap();
+    let client = Client::new(Some(name)).unwrap();
+
+    let child = spawn_peer("client", name);
+    kill_peer(child);
+
+    let start = Instant::now();
+    assert!(matches!(server.receive_any(Some(5000)), Err(Error::PeerDied)));
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert_eq!(server.connected_clients(), vec![client.id()]);
+
+    // The crash is reported once, the live client is still served
+    client.send(b"still here").unwrap();
+    let (id, bytes) = server.receive_any(Some(1000)).unwrap().unwrap();
+    assert_eq!((id, bytes), (client.id(), b"still here".to_vec()));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_stale_heartbeat() {
+    let name = "Local\\TestLivenessHeartbeat";
+    let child = spawn_peer("heartbeat-server", name);
+    let client = Client::new(Some(name)).unwrap();
+    assert!(client.is_peer_alive());
+
+    // A stopped process still exists, only its heartbeat gives it away
+    let pid = child.id().to_string();
+    Command::new("kill").args(["-STOP", &pid]).status().unwrap();
+    thread::sleep(Duration::from_millis(300));
+    assert!(!client.is_peer_alive());
+    assert!(matches!(client.receive(Some(5000)), ReceiveMessage::PeerDied));
+
+    Command::new("kill").args(["-CONT", &pid]).status().unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert!(client.is_peer_alive());
+
+    kill_peer(child);
+    drop(client);
+    drop(Server::new(Some(name)));
+}
+
+#[test]
+fn test_heartbeat_start_and_stop() {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(1024));
+    let mut server = Server::with_transport(transport.clone(), 1024).unwrap();
+    let mut client = Client::with_transport(transport).unwrap();
+
+    server.start_heartbeat(20);
+    client.start_heartbeat(20);
+    thread::sleep(Duration::from_millis(100));
+    assert!(server.is_peer_alive());
+    assert!(client.is_peer_alive());
+
+    // Without a heartbeat only the process ID is checked
+    server.stop_heartbeat();
+    client.stop_heartbeat();
+    thread::sleep(Duration::from_millis(100));
+    assert!(server.is_peer_alive());
+    assert!(client.is_peer_alive());
+
+    drop(client);
+    assert!(!server.is_peer_alive());
+}
@@ -0,0 +1,129 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use windows_shared_memory::{Client, Error, ReconnectingClient, Server};
+
+#[test]
+fn test_client_is_connected() {
+    let server = Server::new(Some("Local\\TestIsConnected")).unwrap();
+    let client = Client::new(Some("Local\\TestIsConnected")).unwrap();
+    assert!(client.is_connected());
+    assert_ne!(client.generation(), 0);
+
+    drop(server);
+    assert!(!client.is_connected());
+}
+
+#[test]
+fn test_reconnect_after_server_restart() {
+    let name = "Local\\TestReconnectRestart";
+    let server = Server::new(Some(name)).unwrap();
+    let mut client = ReconnectingClient::new(Some(name)).unwrap();
+    let reconnects = Arc::new(AtomicUsize::new(0));
+    let counter = reconnects.clone();
+    client.set_on_reconnect(move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    let first_generation = client.client().unwrap().generation();
+
+    server.send(b"one").unwrap();
+    assert_eq!(client.recv_bytes(Some(1000)).unwrap().unwrap(), b"one");
+
+    drop(server);
+    let server = Server::new(Some(name)).unwrap();
+    server.send(b"two").unwrap();
+
+    assert_eq!(client.recv_bytes(Some(2000)).unwrap().unwrap(), b"two");
+    assert_eq!(reconnects.load(Ordering::SeqCst), 1);
+    assert_ne!(client.client().unwrap().generation(), first_generation);
+
+    client.send(b"back").unwrap();
+    assert_eq!(server.recv_bytes(Some(1000)).unwrap().unwrap(), b"back");
+}
+
+#[test]
+fn test_reconnect_waits_for_server() {
+    let name = "Local\\TestReconnectWait";
+    let server = Server::new(Some(name)).unwrap();
+    let mut client = ReconnectingClient::new(Some(name)).unwrap();
+    client.set_backoff(5, 50);
+    drop(server);
+
+    let restarted = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        let server = Server::new(Some(name)).unwrap();
+        server.recv_bytes(Some(2000)).unwrap()
+    });
+
+    client.send_timeout(b"hello", Some(2000)).unwrap();
+    assert!(client.is_connected());
+    assert_eq!(restarted.join().unwrap().unwrap(), b"hello");
+}
+
+#[test]
+fn test_reconnect_times_out() {
+    let name = "Local\\TestReconnectTimeout";
+    let server = Server::new(Some(name)).unwrap();
+    let mut client = ReconnectingClient::new(Some(name)).unwrap();
+    drop(server);
+
+    let start = Instant::now();
+    assert_eq!(client.recv_bytes(Some(200)).unwrap(), None);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(!client.is_connected());
+    assert!(client.reconnect(Some(50)).is_err());
+}
+
+#[test]
+fn test_reconnect_after_close() {
+    let name = "Local\\TestReconnectClose";
+    let server = Server::new(Some(name)).unwrap();
+    let mut client = ReconnectingClient::new(Some(name)).unwrap();
+
+    // A server that closed is not reconnected to, even while it is still running
+    server.send_close().unwrap();
+    assert_eq!(client.recv_bytes(Some(200)).unwrap(), None);
+    assert!(client.client().is_none());
+
+    drop(server);
+    let server = Server::new(Some(name)).unwrap();
+    server.send(b"new server").unwrap();
+    assert_eq!(client.recv(Some(2000)).unwrap().unwrap(), "new server");
+}
+
+#[test]
+fn test_send_reconnects_after_close() {
+    let name = "Local\\TestReconnectSendClose";
+    let server = Server::new(Some(name)).unwrap();
+    let mut client = ReconnectingClient::new(Some(name)).unwrap();
+
+    // The message goes to the next server, not to the one that closed
+    server.send_close().unwrap();
+    let sender = thread::spawn(move || {
+        client.send(b"to the new server").unwrap();
+        client
+    });
+    thread::sleep(Duration::from_millis(100));
+    drop(server);
+
+    let server = Server::new(Some(name)).unwrap();
+    assert_eq!(server.recv_bytes(Some(2000)).unwrap().unwrap(), b"to the new server");
+    sender.join().unwrap();
+}
+
+#[test]
+fn test_reconnect_returns_permanent_errors() {
+    let name = "Local\\TestReconnectServerFull";
+    let server = Server::new(Some(name)).unwrap();
+    let mut client = ReconnectingClient::new(Some(name)).unwrap();
+    drop(server);
+
+    // The only slot of the new server is taken, which waiting does not fix
+    let _server = Server::new(Some(name)).unwrap();
+    let _other = Client::new(Some(name)).unwrap();
+    let start = Instant::now();
+    assert!(matches!(client.reconnect(Some(2000)), Err(Error::ServerFull { max_clients: 1 })));
+    assert!(matches!(client.send(b"lost"), Err(Error::ServerFull { .. })));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
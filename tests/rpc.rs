@@ -0,0 +1,123 @@
+use std::sync::Arc;
+use std::thread;
+use windows_shared_memory::{
+    ChannelMode, Client, Error, HeapTransport, RpcClient, RpcServer, Server, SharedDataHeader,
+};
+
+fn heap_rpc(buffer_size: usize) -> (RpcServer<HeapTransport>, RpcClient<HeapTransport>) {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(buffer_size));
+    let server = Server::with_transport(transport.clone(), buffer_size).unwrap();
+    let client = Client::with_transport(transport).unwrap();
+    (RpcServer::new(server), RpcClient::new(client))
+}
+
+/// Serves `count` requests on another thread, echoing them in upper case.
+fn serve_upper(server: RpcServer<HeapTransport>, count: usize) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut served = 0;
+        while served < count {
+            let handled = server
+                .serve_once(
+                    |request| match std::str::from_utf8(request) {
+                        Ok(text) => Ok(text.to_uppercase().into_bytes()),
+                        Err(_) => Err("request is not UTF-8"),
+                    },
+                    Some(5000),
+                )
+                .unwrap();
+            assert!(handled, "no request within the timeout");
+            served += 1;
+        }
+    })
+}
+
+#[test]
+fn test_rpc_call_roundtrip() {
+    let (server, client) = heap_rpc(256);
+    let handle = serve_upper(server, 2);
+
+    assert_eq!(client.call(b"hello", Some(1000)).unwrap(), b"HELLO");
+    assert_eq!(client.call(b"again", Some(1000)).unwrap(), b"AGAIN");
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_rpc_error_reply() {
+    let (server, client) = heap_rpc(256);
+    let handle = serve_upper(server, 2);
+
+    match client.call(&[0xFF], Some(1000)) {
+        Err(Error::Remote(message)) => assert_eq!(message, "request is not UTF-8"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    // The error did not stop the server
+    assert_eq!(client.call(b"ok", Some(1000)).unwrap(), b"OK");
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_rpc_concurrent_calls() {
+    const THREADS: usize = 4;
+    const CALLS: usize = 25;
+    let (server, client) = heap_rpc(256);
+    let handle = serve_upper(server, THREADS * CALLS);
+
+    let client = Arc::new(client);
+    let callers: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let client = client.clone();
+            thread::spawn(move || {
+                for i in 0..CALLS {
+                    let request = format!("thread {} call {}", t, i);
+                    let reply = client.call(request.as_bytes(), Some(5000)).unwrap();
+                    assert_eq!(reply, request.to_uppercase().into_bytes());
+                }
+            })
+        })
+        .collect();
+
+    for caller in callers {
+        caller.join().unwrap();
+    }
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_rpc_call_timeout() {
+    let (_server, client) = heap_rpc(256);
+    assert!(matches!(client.call(b"nobody listening", Some(50)), Err(Error::Timeout)));
+}
+
+#[test]
+fn test_rpc_large_payload_over_shared_memory() {
+    let server = RpcServer::new(Server::with_clients(Some("Local\\TestRpcLarge"), 1024, 2).unwrap());
+    let request: Vec<u8> = (0..32 * 1024).map(|i| (i % 251) as u8).collect();
+    let expected: Vec<u8> = request.iter().rev().copied().collect();
+
+    let caller = thread::spawn(move || {
+        let client = RpcClient::new(Client::new(Some("Local\\TestRpcLarge")).unwrap());
+        client.call(&request, Some(5000)).unwrap()
+    });
+
+    let reverse = |request: &[u8]| Ok::<_, String>(request.iter().rev().copied().collect());
+    assert!(server.serve_once(reverse, Some(5000)).unwrap());
+    assert_eq!(caller.join().unwrap(), expected);
+}
+
+#[test]
+fn test_rpc_undelivered_reply_does_not_stop_server() {
+    let transport = HeapTransport::new(SharedDataHeader::total_size_with_clients(256, 2));
+    let server = Server::with_transport_clients(transport.clone(), 256, ChannelMode::Mailbox, 2).unwrap();
+    let mut server = RpcServer::new(server);
+    server.set_reply_timeout(Some(20));
+    let gone = RpcClient::new(Client::with_transport(transport.clone()).unwrap());
+    let client = RpcClient::new(Client::with_transport(transport).unwrap());
+
+    // Nobody reads the fragments of this reply once the call has timed out
+    assert!(matches!(gone.call(b"big", Some(10)), Err(Error::Timeout)));
+    assert!(server.serve_once(|_| Ok::<_, String>(vec![0; 4096]), Some(1000)).unwrap());
+
+    let caller = thread::spawn(move || client.call(b"small", Some(5000)).unwrap());
+    assert!(server.serve_once(|request| Ok::<_, String>(request.to_vec()), Some(1000)).unwrap());
+    assert_eq!(caller.join().unwrap(), b"small");
+}
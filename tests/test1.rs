@@ -1,6 +1,10 @@
-use windows_shared_memory::{Client, ReceiveBytes, ReceiveMessage, Server, DEFAULT_BUFFER_SIZE};
+use windows_shared_memory::{
+    ChannelMode, Client, ClientId, Error, EventNames, ReceiveBytes, ReceiveMessage, Server,
+    DEFAULT_BUFFER_SIZE,
+};
 
 #[test]
+#[allow(clippy::needless_borrow)]
 fn simple_test() {
     let server = Server::new(None).unwrap();
     let data = "hello i'am server".as_bytes();
@@ -155,17 +159,13 @@ fn test_buffer_size_boundary() {
         panic!("Failed to receive exact buffer size message");
     }
 
-    // Test oversized data (should be truncated)
+    // Oversized data is rejected instead of truncated
     let oversized_msg: String = "Y".repeat(2048);
-    client.send(oversized_msg.as_bytes()).unwrap();
-
-    if let ReceiveMessage::Message(received) = server.receive(Some(100)) {
-        // Should be truncated to buffer size
-        assert_eq!(received.len(), 1024);
-        assert!(received.chars().all(|c| c == 'Y'));
-    } else {
-        panic!("Failed to receive truncated message");
-    }
+    assert!(matches!(
+        client.send(oversized_msg.as_bytes()),
+        Err(Error::MessageTooLarge { len: 2048, capacity: 1024 })
+    ));
+    assert!(matches!(server.receive(Some(50)), ReceiveMessage::Timeout));
 }
 
 #[test]
@@ -231,3 +231,496 @@ fn test_very_small_buffer() {
         panic!("Failed with very small buffer");
     }
 }
+
+#[test]
+fn test_concurrent_named_channels() {
+    let handles: Vec<_> = (0..4)
+        .map(|channel| {
+            std::thread::spawn(move || {
+                let name = format!("Local\\TestConcurrentChannel{}", channel);
+                let server = Server::with_buffer_size(Some(&name), 1024).unwrap();
+                let client = Client::new(Some(&name)).unwrap();
+
+                for i in 0..50 {
+                    let msg = format!("channel {} message {}", channel, i);
+                    server.send(msg.as_bytes()).unwrap();
+                    match client.receive(Some(1000)) {
+                        ReceiveMessage::Message(received) => assert_eq!(received, msg),
+                        other => panic!("Channel {} expected message {}, got {:?}", channel, i, other),
+                    }
+
+                    let reply = format!("channel {} reply {}", channel, i);
+                    client.send(reply.as_bytes()).unwrap();
+                    match server.receive(Some(1000)) {
+                        ReceiveMessage::Message(received) => assert_eq!(received, reply),
+                        other => panic!("Channel {} expected reply {}, got {:?}", channel, i, other),
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn test_explicit_event_names() {
+    let events = EventNames {
+        server_to_client: "Local\\TestExplicitEventsReady".to_string(),
+        client_to_server: "Local\\TestExplicitEventsRequest".to_string(),
+    };
+    let server = Server::with_event_names(Some("Local\\TestExplicitEvents"), 1024, &events).unwrap();
+    let client = Client::with_event_names(Some("Local\\TestExplicitEvents"), &events).unwrap();
+
+    server.send(b"explicit").unwrap();
+    if let ReceiveMessage::Message(received) = client.receive(Some(100)) {
+        assert_eq!(received, "explicit");
+    } else {
+        panic!("Failed to receive with explicit event names");
+    }
+
+    // The derived names were never created
+    assert!(Client::new(Some("Local\\TestExplicitEvents")).is_err());
+}
+
+#[test]
+fn test_ring_buffer_queues_messages() {
+    let server = Server::with_ring_buffer(Some("Local\\TestRingQueue"), 1024).unwrap();
+    let client = Client::new_ring(Some("Local\\TestRingQueue")).unwrap();
+    assert_eq!(client.mode(), ChannelMode::Ring);
+    assert_eq!(client.buffer_size(), 1024);
+
+    // Several sends before the client reads anything
+    for i in 0..10 {
+        server.send(format!("Queued {}", i).as_bytes()).unwrap();
+    }
+    for i in 0..10 {
+        match client.receive(Some(100)) {
+            ReceiveMessage::Message(received) => assert_eq!(received, format!("Queued {}", i)),
+            other => panic!("Expected queued message {}, got {:?}", i, other),
+        }
+    }
+    assert!(matches!(client.receive(Some(10)), ReceiveMessage::Timeout));
+
+    client.send(b"one").unwrap();
+    client.send(b"two").unwrap();
+    assert!(matches!(server.receive_bytes(Some(100)), ReceiveBytes::Bytes(b) if b == b"one"));
+    assert!(matches!(server.receive_bytes(Some(100)), ReceiveBytes::Bytes(b) if b == b"two"));
+}
+
+#[test]
+fn test_ring_buffer_full_and_close() {
+    let server = Server::with_ring_buffer(Some("Local\\TestRingFull"), 64).unwrap();
+    let client = Client::new(Some("Local\\TestRingFull")).unwrap();
+    assert_eq!(client.mode(), ChannelMode::Ring);
+
+    // 4 + 16 bytes per message, 63 usable bytes
+    let msg = [0x5A; 16];
+    for _ in 0..3 {
+        server.send(&msg).unwrap();
+    }
+    assert!(server.send(&msg).is_err());
+    assert!(server.send(&[0; 64]).is_err());
+
+    // Queued messages are delivered before the exit signal
+    server.send_close().unwrap();
+    for _ in 0..3 {
+        assert!(matches!(client.receive_bytes(Some(100)), ReceiveBytes::Bytes(b) if b == msg));
+    }
+    assert!(matches!(client.receive_bytes(Some(100)), ReceiveBytes::Exit));
+}
+
+#[test]
+fn test_new_ring_rejects_mailbox_server() {
+    let _server = Server::with_buffer_size(Some("Local\\TestRingMismatch"), 1024).unwrap();
+    assert!(Client::new_ring(Some("Local\\TestRingMismatch")).is_err());
+}
+
+#[test]
+fn test_try_send_would_block() {
+    let server = Server::with_buffer_size(Some("Local\\TestTrySend"), 1024).unwrap();
+    let client = Client::new(Some("Local\\TestTrySend")).unwrap();
+
+    server.try_send(b"first").unwrap();
+    assert!(matches!(server.try_send(b"second"), Err(Error::WouldBlock)));
+
+    // The unread message was not overwritten
+    assert!(matches!(client.receive(Some(100)), ReceiveMessage::Message(m) if m == "first"));
+    server.try_send(b"second").unwrap();
+    assert!(matches!(client.receive(Some(100)), ReceiveMessage::Message(m) if m == "second"));
+}
+
+#[test]
+fn test_send_timeout_waits_for_consumption() {
+    let server = Server::with_buffer_size(Some("Local\\TestSendTimeout"), 1024).unwrap();
+    let client = Client::new(Some("Local\\TestSendTimeout")).unwrap();
+
+    client.send_timeout(b"first", Some(100)).unwrap();
+    let start = std::time::Instant::now();
+    assert!(matches!(client.send_timeout(b"second", Some(50)), Err(Error::Timeout)));
+    assert!(start.elapsed() >= std::time::Duration::from_millis(50));
+
+    assert!(matches!(server.receive(Some(100)), ReceiveMessage::Message(m) if m == "first"));
+    client.send_timeout(b"second", Some(100)).unwrap();
+    assert!(matches!(server.receive(Some(100)), ReceiveMessage::Message(m) if m == "second"));
+}
+
+#[test]
+fn test_send_timeout_ring_buffer() {
+    let server = Server::with_ring_buffer(Some("Local\\TestSendTimeoutRing"), 64).unwrap();
+    let client = Client::new(Some("Local\\TestSendTimeoutRing")).unwrap();
+
+    let msg = [7u8; 16];
+    for _ in 0..3 {
+        server.try_send(&msg).unwrap();
+    }
+    assert!(matches!(server.try_send(&msg), Err(Error::WouldBlock)));
+    assert!(matches!(server.send_timeout(&msg, Some(20)), Err(Error::Timeout)));
+
+    assert!(matches!(client.receive_bytes(Some(100)), ReceiveBytes::Bytes(b) if b == msg));
+    server.send_timeout(&msg, Some(100)).unwrap();
+}
+
+fn fragmented_roundtrip(name: &'static str, server: Server) {
+    let blob: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+    let expected = blob.clone();
+
+    // The client sends from another thread while the server reassembles
+    let sender = std::thread::spawn(move || {
+        let mut client = Client::new(Some(name)).unwrap();
+        client.set_fragmentation(true);
+        client.send(&blob).unwrap();
+        client.send_timeout(b"after", Some(1000)).unwrap();
+    });
+
+    assert!(matches!(server.receive_bytes(Some(1000)), ReceiveBytes::Bytes(b) if b == expected));
+    assert!(matches!(server.receive(Some(1000)), ReceiveMessage::Message(m) if m == "after"));
+    sender.join().unwrap();
+}
+
+#[test]
+fn test_fragmentation_mailbox() {
+    let server = Server::with_buffer_size(Some("Local\\TestFragMailbox"), 1024).unwrap();
+    fragmented_roundtrip("Local\\TestFragMailbox", server);
+}
+
+#[test]
+fn test_fragmentation_ring_buffer() {
+    let server = Server::with_ring_buffer(Some("Local\\TestFragRing"), 1024).unwrap();
+    fragmented_roundtrip("Local\\TestFragRing", server);
+}
+
+#[test]
+fn test_fragmentation_settings() {
+    let mut server = Server::with_buffer_size(Some("Local\\TestFragSettings"), 64).unwrap();
+    let _client = Client::new(Some("Local\\TestFragSettings")).unwrap();
+
+    assert_eq!(server.max_message_len(), 64);
+    assert!(!server.fragmentation());
+    server.set_fragmentation(true);
+    assert!(server.fragmentation());
+
+    // try_send never fragments
+    assert!(matches!(
+        server.try_send(&[0; 65]),
+        Err(Error::MessageTooLarge { len: 65, capacity: 64 })
+    ));
+
+    // The timeout covers the whole message when the client is not reading
+    assert!(matches!(server.send_timeout(&[0; 200], Some(50)), Err(Error::Timeout)));
+}
+
+#[test]
+fn test_multiple_clients_claim_slots() {
+    let server = Server::with_clients(Some("Local\\TestClientSlots"), 256, 3).unwrap();
+    assert_eq!(server.max_clients(), 3);
+
+    let first = Client::new(Some("Local\\TestClientSlots")).unwrap();
+    let second = Client::new(Some("Local\\TestClientSlots")).unwrap();
+    let third = Client::new(Some("Local\\TestClientSlots")).unwrap();
+    assert_eq!([first.id(), second.id(), third.id()], [ClientId(0), ClientId(1), ClientId(2)]);
+    assert!(matches!(
+        Client::new(Some("Local\\TestClientSlots")),
+        Err(Error::ServerFull { max_clients: 3 })
+    ));
+
+    // A dropped client frees its slot for the next one
+    drop(second);
+    assert_eq!(server.connected_clients(), vec![ClientId(0), ClientId(2)]);
+    let replacement = Client::new(Some("Local\\TestClientSlots")).unwrap();
+    assert_eq!(replacement.id(), ClientId(1));
+}
+
+#[test]
+fn test_single_slot_server_rejects_second_client() {
+    let _server = Server::new(Some("Local\\TestSingleSlot")).unwrap();
+    let _client = Client::new(Some("Local\\TestSingleSlot")).unwrap();
+    assert!(matches!(Client::new(Some("Local\\TestSingleSlot")), Err(Error::ServerFull { .. })));
+}
+
+#[test]
+fn test_per_client_send_and_receive() {
+    let server = Server::with_clients(Some("Local\\TestPerClient"), 256, 2).unwrap();
+    let first = Client::new(Some("Local\\TestPerClient")).unwrap();
+    let second = Client::new(Some("Local\\TestPerClient")).unwrap();
+
+    first.send(b"from first").unwrap();
+    second.send(b"from second").unwrap();
+
+    // Each client has its own buffers, so neither message was overwritten
+    assert_eq!(server.recv_from(second.id(), Some(100)).unwrap(), Some(b"from second".to_vec()));
+    assert_eq!(server.recv_from(first.id(), Some(100)).unwrap(), Some(b"from first".to_vec()));
+
+    server.send_to(second.id(), b"to second").unwrap();
+    assert!(matches!(first.recv(Some(50)), Ok(None)));
+    assert_eq!(second.recv(Some(100)).unwrap().as_deref(), Some("to second"));
+
+    // Close reaches every client
+    server.send_close().unwrap();
+    assert!(matches!(first.recv(Some(100)), Err(Error::PeerClosed)));
+    assert!(matches!(second.recv(Some(100)), Err(Error::PeerClosed)));
+}
+
+#[test]
+fn test_receive_any_from_concurrent_clients() {
+    const CLIENTS: usize = 3;
+    const MESSAGES: usize = 20;
+    let server = Server::with_clients(Some("Local\\TestReceiveAny"), 256, CLIENTS).unwrap();
+
+    let handles: Vec<_> = (0..CLIENTS)
+        .map(|_| {
+            std::thread::spawn(|| {
+                let client = Client::new(Some("Local\\TestReceiveAny")).unwrap();
+                let id = client.id();
+                for i in 0..MESSAGES {
+                    let msg = format!("{} {}", id.0, i);
+                    client.send_timeout(msg.as_bytes(), Some(5000)).unwrap();
+                }
+                // Wait for the server to finish before releasing the slot
+                client.recv(Some(5000)).unwrap();
+            })
+        })
+        .collect();
+
+    let mut next = [0; CLIENTS];
+    for _ in 0..CLIENTS * MESSAGES {
+        let (client, bytes) = server.receive_any(Some(5000)).unwrap().expect("timed out");
+        // Messages from one client arrive in order
+        assert_eq!(String::from_utf8(bytes).unwrap(), format!("{} {}", client.0, next[client.0]));
+        next[client.0] += 1;
+    }
+    assert_eq!(next, [MESSAGES; CLIENTS]);
+
+    for client in server.connected_clients() {
+        server.send_to(client, b"done").unwrap();
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn test_receive_none_waits_for_message() {
+    for (name, ring) in [("Local\\TestWaitForeverMailbox", false), ("Local\\TestWaitForeverRing", true)] {
+        let server = if ring {
+            Server::with_ring_buffer(Some(name), 1024).unwrap()
+        } else {
+            Server::new(Some(name)).unwrap()
+        };
+        let client = Client::new(Some(name)).unwrap();
+
+        let sender = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            client.send(b"late").unwrap();
+            client
+        });
+
+        match server.receive(None) {
+            ReceiveMessage::Message(msg) => assert_eq!(msg, "late"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        drop(sender.join().unwrap());
+    }
+}
+
+#[test]
+fn test_try_receive_never_waits() {
+    let server = Server::new(Some("Local\\TestTryReceive")).unwrap();
+    let client = Client::new(Some("Local\\TestTryReceive")).unwrap();
+
+    let start = std::time::Instant::now();
+    assert!(matches!(client.try_receive(), ReceiveMessage::Timeout));
+    assert!(matches!(client.try_recv_bytes(), Ok(None)));
+    assert!(start.elapsed() < std::time::Duration::from_millis(50));
+
+    server.send(b"ready").unwrap();
+    match client.try_receive() {
+        ReceiveMessage::Message(msg) => assert_eq!(msg, "ready"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(matches!(client.try_receive_bytes(), ReceiveBytes::Timeout));
+
+    server.send_close().unwrap();
+    assert!(matches!(client.try_recv(), Err(Error::PeerClosed)));
+}
+
+#[test]
+fn test_receive_until_deadline() {
+    use std::time::{Duration, Instant};
+
+    let server = Server::with_ring_buffer(Some("Local\\TestReceiveUntil"), 1024).unwrap();
+    let client = Client::new_ring(Some("Local\\TestReceiveUntil")).unwrap();
+
+    // A deadline in the past behaves like try_receive
+    server.send(b"queued").unwrap();
+    let past = Instant::now() - Duration::from_millis(10);
+    assert_eq!(client.recv_bytes_until(past).unwrap().unwrap(), b"queued");
+    assert!(matches!(client.receive_until(past), ReceiveMessage::Timeout));
+
+    // Nothing arrives: gives up at the deadline
+    let start = Instant::now();
+    let deadline = start + Duration::from_millis(100);
+    assert!(matches!(client.recv_until(deadline), Ok(None)));
+    assert!(Instant::now() >= deadline);
+    assert!(start.elapsed() < Duration::from_millis(1000));
+
+    // A message sent before the deadline is returned
+    let sender = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        server.send(b"in time").unwrap();
+        server
+    });
+    match client.receive_bytes_until(Instant::now() + Duration::from_secs(5)) {
+        ReceiveBytes::Bytes(bytes) => assert_eq!(bytes, b"in time"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    drop(sender.join().unwrap());
+}
+
+#[test]
+fn test_receive_any_none_waits_for_message() {
+    let server = Server::with_clients(Some("Local\\TestReceiveAnyForever"), 1024, 2).unwrap();
+    let first = Client::new(Some("Local\\TestReceiveAnyForever")).unwrap();
+    let second = Client::new(Some("Local\\TestReceiveAnyForever")).unwrap();
+
+    let sender = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        second.send(b"from second").unwrap();
+        second
+    });
+
+    let (client, bytes) = server.receive_any(None).unwrap().unwrap();
+    assert_eq!(client, ClientId(1));
+    assert_eq!(bytes, b"from second");
+    drop(sender.join().unwrap());
+    drop(first);
+}
+
+#[test]
+fn test_client_send_close() {
+    let server = Server::new(Some("Local\\TestClientClose")).unwrap();
+    let client = Client::new(Some("Local\\TestClientClose")).unwrap();
+
+    client.send_close().unwrap();
+    assert!(matches!(server.receive(Some(1000)), ReceiveMessage::Exit));
+    // The close stays visible to later receives
+    assert!(matches!(server.recv_bytes(Some(10)), Err(Error::PeerClosed)));
+}
+
+#[test]
+fn test_close_handshake() {
+    let server = Server::with_ring_buffer(Some("Local\\TestCloseHandshake"), 1024).unwrap();
+    let client = Client::new_ring(Some("Local\\TestCloseHandshake")).unwrap();
+
+    // Client closes, server acknowledges by receiving
+    let closer = std::thread::spawn(move || {
+        client.send(b"last words").unwrap();
+        client.close(Some(5000)).unwrap();
+        client
+    });
+    assert_eq!(server.recv_bytes(Some(5000)).unwrap().unwrap(), b"last words");
+    assert!(matches!(server.receive(Some(5000)), ReceiveMessage::Exit));
+    let client = closer.join().unwrap();
+
+    // Server closes, client acknowledges by receiving
+    let listener = std::thread::spawn(move || {
+        let result = client.receive(Some(5000));
+        (client, result)
+    });
+    server.close(Some(5000)).unwrap();
+    let (_client, result) = listener.join().unwrap();
+    assert!(matches!(result, ReceiveMessage::Exit));
+}
+
+#[test]
+fn test_close_times_out_without_listener() {
+    let server = Server::new(Some("Local\\TestCloseTimeout")).unwrap();
+    let client = Client::new(Some("Local\\TestCloseTimeout")).unwrap();
+
+    assert!(matches!(client.close(Some(50)), Err(Error::Timeout)));
+    assert!(matches!(server.close(Some(50)), Err(Error::Timeout)));
+
+    // Clients that are gone count as acknowledged
+    drop(client);
+    server.close(Some(50)).unwrap();
+}
+
+#[test]
+fn test_close_on_drop() {
+    let server = Server::new(Some("Local\\TestCloseOnDrop")).unwrap();
+    let mut client = Client::new(Some("Local\\TestCloseOnDrop")).unwrap();
+    client.set_close_on_drop(true);
+
+    let dropper = std::thread::spawn(move || drop(client));
+    assert!(matches!(server.receive(Some(5000)), ReceiveMessage::Exit));
+    dropper.join().unwrap();
+
+    // The next client of the slot starts without the old close
+    let _client = Client::new(Some("Local\\TestCloseOnDrop")).unwrap();
+    assert!(matches!(server.recv(Some(10)), Ok(None)));
+}
+
+#[test]
+fn test_receive_any_reports_client_close() {
+    let server = Server::with_clients(Some("Local\\TestReceiveAnyClose"), 1024, 2).unwrap();
+    let first = Client::new(Some("Local\\TestReceiveAnyClose")).unwrap();
+    let second = Client::new(Some("Local\\TestReceiveAnyClose")).unwrap();
+
+    second.send_close().unwrap();
+    match server.receive_any(Some(1000)) {
+        Err(Error::ClientClosed(client)) => assert_eq!(client, second.id()),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // Reported once, other clients are still served
+    first.send(b"still here").unwrap();
+    assert_eq!(server.receive_any(Some(1000)).unwrap(), Some((first.id(), b"still here".to_vec())));
+    assert!(matches!(server.receive_any(Some(10)), Ok(None)));
+}
+
+#[test]
+fn test_connect_waits_for_server() {
+    let starter = std::thread::spawn(|| {
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        let server = Server::new(Some("Local\\TestConnectWait")).unwrap();
+        server.recv_bytes(Some(2000)).unwrap()
+    });
+
+    // Not found yet is not an error
+    let client = Client::connect(Some("Local\\TestConnectWait"), Some(2000)).unwrap();
+    client.send(b"connected").unwrap();
+    assert_eq!(starter.join().unwrap(), Some(b"connected".to_vec()));
+}
+
+#[test]
+fn test_connect_times_out() {
+    let start = std::time::Instant::now();
+    assert!(matches!(Client::connect(Some("Local\\TestConnectMissing"), Some(100)), Err(Error::Timeout)));
+    assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+
+    // An existing server connects right away
+    let _server = Server::new(Some("Local\\TestConnectMissing")).unwrap();
+    assert!(Client::connect(Some("Local\\TestConnectMissing"), Some(0)).is_ok());
+}
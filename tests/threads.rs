@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread;
+use windows_shared_memory::{Client, Server, SyncClient, SyncServer};
+
+fn assert_send<T: Send>() {}
+fn assert_sync<T: Sync>() {}
+
+#[test]
+fn test_endpoints_are_send_and_wrappers_sync() {
+    assert_send::<Server>();
+    assert_send::<Client>();
+    assert_sync::<SyncServer>();
+    assert_sync::<SyncClient>();
+    assert_send::<SyncServer>();
+    assert_send::<SyncClient>();
+}
+
+#[test]
+fn test_move_client_into_thread() {
+    let server = Server::new(Some("Local\\TestMoveClient")).unwrap();
+    let client = Client::new(Some("Local\\TestMoveClient")).unwrap();
+
+    let handle = thread::spawn(move || {
+        client.send_timeout(b"from worker", Some(1000)).unwrap();
+        client
+    });
+
+    assert_eq!(server.recv_bytes(Some(1000)).unwrap().unwrap(), b"from worker");
+    drop(handle.join().unwrap());
+}
+
+/// Sends `PER_THREAD` messages from each of `THREADS` threads through one shared client.
+fn multi_producer(server: Server, client: Client) {
+    const THREADS: u8 = 4;
+    const PER_THREAD: u8 = 50;
+    let client = Arc::new(SyncClient::new(client));
+
+    let producers: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let client = client.clone();
+            thread::spawn(move || {
+                for i in 0..PER_THREAD {
+                    client.send_timeout(&[t, i], Some(5000)).unwrap();
+                }
+            })
+        })
+        .collect();
+
+    // Every message arrives once, and each producer's messages stay in order
+    let mut next = [0u8; THREADS as usize];
+    for _ in 0..THREADS as usize * PER_THREAD as usize {
+        let message = server.recv_bytes(Some(5000)).unwrap().expect("message lost");
+        let (t, i) = (message[0] as usize, message[1]);
+        assert_eq!(i, next[t], "producer {} out of order", t);
+        next[t] += 1;
+    }
+    assert_eq!(next, [PER_THREAD; THREADS as usize]);
+
+    for producer in producers {
+        producer.join().unwrap();
+    }
+}
+
+#[test]
+fn test_multi_producer_mailbox() {
+    let server = Server::new(Some("Local\\TestMultiProducerMailbox")).unwrap();
+    let client = Client::new(Some("Local\\TestMultiProducerMailbox")).unwrap();
+    multi_producer(server, client);
+}
+
+#[test]
+fn test_multi_producer_ring_buffer() {
+    let server = Server::with_ring_buffer(Some("Local\\TestMultiProducerRing"), 256).unwrap();
+    let client = Client::new_ring(Some("Local\\TestMultiProducerRing")).unwrap();
+    multi_producer(server, client);
+}
+
+#[test]
+fn test_concurrent_receivers() {
+    const MESSAGES: u32 = 100;
+    let server = Arc::new(SyncServer::new(
+        Server::with_ring_buffer(Some("Local\\TestConcurrentReceivers"), 1024).unwrap(),
+    ));
+    let client = Client::new_ring(Some("Local\\TestConcurrentReceivers")).unwrap();
+
+    let receivers: Vec<_> = (0..3)
+        .map(|_| {
+            let server = server.clone();
+            thread::spawn(move || {
+                let mut received = Vec::new();
+                while let Some(message) = server.recv_bytes(Some(200)).unwrap() {
+                    received.push(u32::from_le_bytes(message.try_into().unwrap()));
+                }
+                received
+            })
+        })
+        .collect();
+
+    for i in 0..MESSAGES {
+        client.send_timeout(&i.to_le_bytes(), Some(5000)).unwrap();
+    }
+
+    let mut seen = HashSet::new();
+    for receiver in receivers {
+        for message in receiver.join().unwrap() {
+            assert!(seen.insert(message), "message {} received twice", message);
+        }
+    }
+    assert_eq!(seen.len(), MESSAGES as usize);
+}
@@ -0,0 +1,297 @@
+use std::sync::atomic::Ordering;
+use std::thread;
+use windows_shared_memory::{
+    BufferSizes, ChannelMode, Client, ClientSlot, Direction, Error, EventNames, HeapTransport, ReceiveBytes,
+    ReceiveMessage, Server, SharedDataHeader, ShmTransport, Transport, FEATURE_SCRUB_AFTER_READ,
+    PROTOCOL_VERSION,
+};
+
+fn heap_pair(buffer_size: usize) -> (Server<HeapTransport>, Client<HeapTransport>) {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(buffer_size));
+    let server = Server::with_transport(transport.clone(), buffer_size).unwrap();
+    let client = Client::with_transport(transport).unwrap();
+    (server, client)
+}
+
+/// Returns a copy of the server-to-client data buffer of a single-slot transport.
+fn s2c_buffer(transport: &HeapTransport, buffer_size: usize) -> Vec<u8> {
+    let offset = SharedDataHeader::offset_s2c(BufferSizes::symmetric(buffer_size), 1, 0);
+    unsafe { std::slice::from_raw_parts(transport.as_ptr().add(offset), buffer_size) }.to_vec()
+}
+
+/// Creates a server that asks readers to scrub, and a client of it.
+fn scrubbing_pair(
+    transport: &HeapTransport,
+    buffer_size: usize,
+    mode: ChannelMode,
+) -> (Server<HeapTransport>, Client<HeapTransport>) {
+    let server = Server::with_transport_mode(transport.clone(), buffer_size, mode).unwrap();
+    unsafe { (*(transport.as_ptr() as *mut SharedDataHeader)).features |= FEATURE_SCRUB_AFTER_READ };
+    let client = Client::with_transport(transport.clone()).unwrap();
+    (server, client)
+}
+
+#[test]
+fn test_heap_transport_roundtrip() {
+    let (server, client) = heap_pair(1024);
+    assert_eq!(client.buffer_size(), 1024);
+
+    server.send(b"Hello from server").unwrap();
+    if let ReceiveMessage::Message(received) = client.receive(Some(100)) {
+        assert_eq!(received, "Hello from server");
+    } else {
+        panic!("Client failed to receive from server");
+    }
+
+    client.send(&[0xAA, 0xBB, 0xCC]).unwrap();
+    if let ReceiveBytes::Bytes(received) = server.receive_bytes(Some(100)) {
+        assert_eq!(received, vec![0xAA, 0xBB, 0xCC]);
+    } else {
+        panic!("Server failed to receive from client");
+    }
+}
+
+#[test]
+fn test_heap_transport_across_threads() {
+    let (server, client) = heap_pair(256);
+
+    let handle = thread::spawn(move || {
+        for i in 0..10 {
+            match client.receive(Some(1000)) {
+                ReceiveMessage::Message(msg) => client.send(format!("echo {}", msg).as_bytes()).unwrap(),
+                other => panic!("Client expected message {}, got {:?}", i, other),
+            }
+        }
+    });
+
+    for i in 0..10 {
+        server.send(format!("{}", i).as_bytes()).unwrap();
+        match server.receive(Some(1000)) {
+            ReceiveMessage::Message(reply) => assert_eq!(reply, format!("echo {}", i)),
+            other => panic!("Server expected reply {}, got {:?}", i, other),
+        }
+    }
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_heap_transport_close_signal() {
+    let (server, client) = heap_pair(64);
+    server.send_close().unwrap();
+    assert!(matches!(client.receive(Some(100)), ReceiveMessage::Exit));
+}
+
+#[test]
+fn test_heap_transport_wait_timeout() {
+    let transport = HeapTransport::new(64);
+    assert!(!transport.wait(Direction::ClientToServer, 0, Some(10)).unwrap());
+
+    transport.signal(Direction::ClientToServer, 0).unwrap();
+    assert!(!transport.wait(Direction::ServerToClient, 0, Some(10)).unwrap());
+    assert!(transport.wait(Direction::ClientToServer, 0, Some(10)).unwrap());
+    // Auto-reset: the signal is consumed by the first wait
+    assert!(!transport.wait(Direction::ClientToServer, 0, Some(10)).unwrap());
+}
+
+#[test]
+fn test_client_signals_are_per_slot() {
+    let heap = HeapTransport::new(64);
+    let name = "Local\\TestSignalsPerSlot";
+    let shm = ShmTransport::create_with_clients(name, 64, &EventNames::for_mapping(name), 2).unwrap();
+    let transports: [&dyn Transport; 2] = [&heap, &shm];
+    for transport in transports {
+        transport.signal(Direction::ClientToServer, 0).unwrap();
+        // A wait for another slot does not consume the signal
+        assert!(!transport.wait(Direction::ClientToServer, 1, Some(10)).unwrap());
+        assert!(transport.wait(Direction::ClientToServer, 0, Some(10)).unwrap());
+        // Every client signal also wakes a wait for any slot
+        assert!(transport.wait_any(Some(10)).unwrap());
+        assert!(!transport.wait_any(Some(10)).unwrap());
+    }
+}
+
+#[test]
+fn test_transport_too_small() {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(1024) - 1);
+    assert!(Server::with_transport(transport.clone(), 1024).is_err());
+
+    let transport = HeapTransport::new(4);
+    assert!(matches!(Client::with_transport(transport), Err(Error::HeaderMismatch(_))));
+}
+
+#[test]
+fn test_recv_typed_results() {
+    let (server, client) = heap_pair(64);
+
+    assert!(matches!(client.recv(Some(10)), Ok(None)));
+
+    server.send(b"hello").unwrap();
+    assert_eq!(client.recv(Some(100)).unwrap().as_deref(), Some("hello"));
+
+    server.send(&[0xFF, 0xFE]).unwrap();
+    assert!(matches!(client.recv(Some(100)), Err(Error::InvalidUtf8(b)) if b == [0xFF, 0xFE]));
+
+    client.send(&[1, 2, 3]).unwrap();
+    assert_eq!(server.recv_bytes(Some(100)).unwrap(), Some(vec![1, 2, 3]));
+
+    server.send_close().unwrap();
+    assert!(matches!(client.recv_bytes(Some(100)), Err(Error::PeerClosed)));
+    server.send_close().unwrap();
+    assert!(matches!(client.receive_bytes(Some(100)), ReceiveBytes::Exit));
+}
+
+#[test]
+fn test_recv_unknown_state() {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(64));
+    let server = Server::with_transport(transport.clone(), 64).unwrap();
+
+    let slot = unsafe { transport.as_ptr().add(SharedDataHeader::slot_offset(0)) as *const ClientSlot };
+    unsafe { (*slot).flag_client.store(42, Ordering::Release) };
+    transport.signal(Direction::ClientToServer, 0).unwrap();
+
+    assert!(matches!(server.recv(Some(100)), Err(Error::UnknownState(42))));
+    transport.signal(Direction::ClientToServer, 0).unwrap();
+    assert!(matches!(server.receive(Some(100)), ReceiveMessage::MessageError(e) if e == "Unknown state 42"));
+}
+
+#[test]
+fn test_fragment_in_too_small_buffer_is_corrupt() {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(64));
+    let _server = Server::with_transport(transport.clone(), 64).unwrap();
+    // A header claiming a buffer too small for the fragment header
+    unsafe { (*(transport.as_ptr() as *mut SharedDataHeader)).buffer_size_s2c = 4 };
+    let client = Client::with_transport(transport.clone()).unwrap();
+
+    let slot = unsafe { transport.as_ptr().add(SharedDataHeader::slot_offset(0)) as *const ClientSlot };
+    unsafe { (*slot).flag_server.store(4, Ordering::Release) };
+    assert!(matches!(client.recv_bytes(Some(100)), Err(Error::Corrupt(_))));
+    unsafe { (*slot).flag_server.store(4, Ordering::Release) };
+    assert!(matches!(client.receive_into_slice(&mut [0u8; 16], Some(100)), Err(Error::Corrupt(_))));
+}
+
+#[test]
+fn test_client_rejects_incompatible_header() {
+    let corruptions: [fn(&mut SharedDataHeader); 5] = [
+        |h| h.magic = 0x1234_5678,
+        |h| h.version = PROTOCOL_VERSION + 1,
+        |h| h.header_size += 8,
+        |h| h.features |= 1 << 31,
+        // The layout size overflows instead of failing the length check
+        |h| {
+            h.buffer_size_s2c = u32::MAX;
+            h.buffer_size_c2s = u32::MAX;
+            h.max_clients = u32::MAX;
+        },
+    ];
+
+    for corrupt in corruptions {
+        let transport = HeapTransport::new(SharedDataHeader::total_size(64));
+        let _server = Server::with_transport(transport.clone(), 64).unwrap();
+        unsafe { corrupt(&mut *(transport.as_ptr() as *mut SharedDataHeader)) };
+
+        assert!(matches!(Client::with_transport(transport), Err(Error::HeaderMismatch(_))));
+    }
+}
+
+#[test]
+fn test_client_rejects_foreign_mapping() {
+    // A mapping that was not created by a server has no valid header
+    let _foreign = ShmTransport::create("Local\\TestForeignMapping", 4096).unwrap();
+
+    match Client::new(Some("Local\\TestForeignMapping")) {
+        Err(Error::HeaderMismatch(reason)) => assert!(reason.contains("magic"), "{}", reason),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("connected to a foreign mapping"),
+    }
+}
+
+#[test]
+fn test_connect_fails_fast_on_incompatible_server() {
+    let transport = ShmTransport::create("Local\\TestConnectIncompatible", SharedDataHeader::total_size(64)).unwrap();
+    let _server = Server::with_transport(transport, 64).unwrap();
+    let view = ShmTransport::open("Local\\TestConnectIncompatible").unwrap();
+    unsafe { (*(view.as_ptr() as *mut SharedDataHeader)).version = PROTOCOL_VERSION + 1 };
+
+    // A server that is up but incompatible is a real error, not "not ready yet"
+    let start = std::time::Instant::now();
+    let result = Client::connect(Some("Local\\TestConnectIncompatible"), Some(5000));
+    assert!(matches!(result, Err(Error::HeaderMismatch(_))));
+    assert!(start.elapsed() < std::time::Duration::from_secs(1));
+}
+
+#[test]
+fn test_send_writes_only_message_bytes() {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(64));
+    let server = Server::with_transport(transport.clone(), 64).unwrap();
+    let client = Client::with_transport(transport.clone()).unwrap();
+
+    let offset = SharedDataHeader::offset_s2c(BufferSizes::symmetric(64), 1, 0);
+    unsafe { std::ptr::write_bytes(transport.as_ptr().add(offset), 0xAA, 64) };
+    server.send(b"hi").unwrap();
+    assert_eq!(client.recv_bytes(Some(100)).unwrap(), Some(b"hi".to_vec()));
+
+    let buffer = s2c_buffer(&transport, 64);
+    assert_eq!(&buffer[..2], b"hi");
+    assert!(buffer[2..].iter().all(|&byte| byte == 0xAA));
+}
+
+#[test]
+fn test_scrub_after_read_mailbox() {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(64));
+    let (mut server, mut client) = scrubbing_pair(&transport, 64, ChannelMode::Mailbox);
+
+    server.send(b"secret").unwrap();
+    assert_eq!(client.recv_bytes(Some(100)).unwrap(), Some(b"secret".to_vec()));
+    assert!(s2c_buffer(&transport, 64).iter().all(|&byte| byte == 0));
+
+    server.send(b"borrowed").unwrap();
+    let message = client.receive_borrowed(Some(100)).unwrap().unwrap();
+    assert_eq!(&*message, b"borrowed");
+    drop(message);
+    assert!(s2c_buffer(&transport, 64).iter().all(|&byte| byte == 0));
+
+    // Fragments are scrubbed one at a time
+    server.set_fragmentation(true);
+    let large = vec![7u8; 200];
+    let sender = std::thread::spawn(move || server.send(&large));
+    assert_eq!(client.recv_bytes(Some(1000)).unwrap(), Some(vec![7u8; 200]));
+    sender.join().unwrap().unwrap();
+    assert!(s2c_buffer(&transport, 64).iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn test_scrub_after_read_ring() {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(64));
+    let (server, mut client) = scrubbing_pair(&transport, 64, ChannelMode::Ring);
+
+    // Enough messages to wrap around the end of the ring
+    for round in 0..4u8 {
+        server.send(&[round + 1; 20]).unwrap();
+        server.send(&[round + 1; 12]).unwrap();
+        assert_eq!(client.recv_bytes(Some(100)).unwrap(), Some(vec![round + 1; 20]));
+        let message = client.receive_borrowed(Some(100)).unwrap().unwrap();
+        assert_eq!(&*message, &[round + 1; 12]);
+        drop(message);
+        assert!(s2c_buffer(&transport, 64).iter().all(|&byte| byte == 0));
+    }
+}
+
+#[test]
+fn test_corrupt_ring_is_reported() {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(64));
+    let server = Server::with_transport_mode(transport.clone(), 64, ChannelMode::Ring).unwrap();
+    let client = Client::with_transport(transport.clone()).unwrap();
+
+    let slot = unsafe { &*(transport.as_ptr().add(SharedDataHeader::slot_offset(0)) as *const ClientSlot) };
+    slot.ring_head_s2c.store(1000, Ordering::Release);
+    assert!(matches!(client.try_recv_bytes(), Err(Error::Corrupt(_))));
+    assert!(matches!(server.send(b"x"), Err(Error::Corrupt(_))));
+
+    // A length prefix longer than the queued bytes
+    slot.ring_head_s2c.store(0, Ordering::Release);
+    server.send(b"abc").unwrap();
+    let offset = SharedDataHeader::offset_s2c(BufferSizes::symmetric(64), 1, 0);
+    unsafe { (transport.as_ptr().add(offset) as *mut u32).write_unaligned(60) };
+    assert!(matches!(client.try_recv_bytes(), Err(Error::Corrupt(_))));
+}
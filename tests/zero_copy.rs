@@ -0,0 +1,363 @@
+use std::io::IoSlice;
+use windows_shared_memory::{
+    BufferSizes, ChannelMode, Client, ClientId, ClientSlot, Error, HeapTransport, Server, SharedDataHeader, Transport,
+};
+use std::sync::atomic::Ordering;
+
+fn heap_pair(buffer_size: usize, mode: ChannelMode) -> (Server<HeapTransport>, Client<HeapTransport>) {
+    let transport = HeapTransport::new(SharedDataHeader::total_size(buffer_size));
+    let server = Server::with_transport_mode(transport.clone(), buffer_size, mode).unwrap();
+    let client = Client::with_transport(transport).unwrap();
+    (server, client)
+}
+
+#[test]
+fn test_reserve_commit() {
+    let (mut server, mut client) = heap_pair(64, ChannelMode::Mailbox);
+
+    let mut frame = server.reserve(5).unwrap();
+    assert_eq!(frame.len(), 5);
+    frame.copy_from_slice(b"hello");
+    frame.commit().unwrap();
+    assert_eq!(client.recv_bytes(Some(100)).unwrap(), Some(b"hello".to_vec()));
+
+    let mut request = client.reserve(3).unwrap();
+    request.copy_from_slice(&[1, 2, 3]);
+    request.commit().unwrap();
+    assert_eq!(server.recv_bytes(Some(100)).unwrap(), Some(vec![1, 2, 3]));
+}
+
+#[test]
+fn test_reserve_dropped_without_commit_sends_nothing() {
+    let (mut server, client) = heap_pair(64, ChannelMode::Mailbox);
+
+    let mut frame = server.reserve(7).unwrap();
+    frame.copy_from_slice(b"aborted");
+    drop(frame);
+    assert_eq!(client.try_recv_bytes().unwrap(), None);
+
+    let mut frame = server.reserve(2).unwrap();
+    frame.copy_from_slice(b"ok");
+    frame.commit().unwrap();
+    assert_eq!(client.recv_bytes(Some(100)).unwrap(), Some(b"ok".to_vec()));
+}
+
+#[test]
+fn test_reserve_checks_size_and_unread_message() {
+    let (mut server, client) = heap_pair(64, ChannelMode::Mailbox);
+
+    assert!(matches!(server.reserve(65), Err(Error::MessageTooLarge { len: 65, capacity: 64 })));
+
+    server.send(b"unread").unwrap();
+    assert!(matches!(server.reserve(1), Err(Error::WouldBlock)));
+    assert_eq!(client.recv_bytes(Some(100)).unwrap(), Some(b"unread".to_vec()));
+    assert!(server.reserve(1).is_ok());
+}
+
+#[test]
+fn test_reserve_ring() {
+    let (mut server, client) = heap_pair(32, ChannelMode::Ring);
+
+    // Records of 4 + 10 bytes, the third one wraps around the end of the ring
+    for round in 0..10u8 {
+        for i in 0..2 {
+            let mut frame = server.reserve(10).unwrap();
+            frame.fill(round * 2 + i);
+            frame.commit().unwrap();
+        }
+        assert!(matches!(server.reserve(10), Err(Error::WouldBlock)));
+        assert_eq!(client.recv_bytes(Some(100)).unwrap(), Some(vec![round * 2; 10]));
+        assert_eq!(client.recv_bytes(Some(100)).unwrap(), Some(vec![round * 2 + 1; 10]));
+    }
+
+    drop(server.reserve(4).unwrap());
+    assert_eq!(client.try_recv_bytes().unwrap(), None);
+}
+
+#[test]
+fn test_receive_borrowed_holds_buffer_until_dropped() {
+    let (mut server, client) = heap_pair(64, ChannelMode::Mailbox);
+
+    client.send(b"frame").unwrap();
+    let frame = server.receive_borrowed(Some(100)).unwrap().unwrap();
+    assert_eq!(&*frame, b"frame");
+    // Still unread from the client's side
+    assert!(matches!(client.try_send(b"next"), Err(Error::WouldBlock)));
+    drop(frame);
+
+    client.try_send(b"next").unwrap();
+    let frame = server.receive_borrowed(Some(100)).unwrap().unwrap();
+    assert_eq!(&*frame, b"next");
+    drop(frame);
+    assert!(server.receive_borrowed(Some(10)).unwrap().is_none());
+}
+
+#[test]
+fn test_concurrent_send_cannot_change_borrowed_message() {
+    let (mut server, client) = heap_pair(64, ChannelMode::Mailbox);
+
+    client.send(b"frame").unwrap();
+    let frame = server.receive_borrowed(Some(100)).unwrap().unwrap();
+    let client = std::thread::scope(|scope| {
+        let sender = scope.spawn(move || {
+            for _ in 0..1000 {
+                assert!(matches!(client.send(b"XXXXX"), Err(Error::WouldBlock)));
+                assert!(matches!(client.try_send(b"XXXXX"), Err(Error::WouldBlock)));
+            }
+            assert!(matches!(client.send_timeout(b"XXXXX", Some(20)), Err(Error::Timeout)));
+            client
+        });
+        for _ in 0..1000 {
+            assert_eq!(&*frame, b"frame");
+        }
+        sender.join().unwrap()
+    });
+    assert_eq!(&*frame, b"frame");
+    drop(frame);
+
+    client.send(b"next").unwrap();
+    assert_eq!(server.recv_bytes(Some(100)).unwrap(), Some(b"next".to_vec()));
+}
+
+#[test]
+fn test_borrowed_message_survives_client_reconnect() {
+    for mode in [ChannelMode::Mailbox, ChannelMode::Ring] {
+        let transport = HeapTransport::new(SharedDataHeader::total_size(64));
+        let mut server = Server::with_transport_mode(transport.clone(), 64, mode).unwrap();
+        let client = Client::with_transport(transport.clone()).unwrap();
+
+        client.send(b"frame").unwrap();
+        let frame = server.receive_borrowed(Some(100)).unwrap().unwrap();
+        drop(client);
+        let client = Client::with_transport(transport).unwrap();
+        assert_eq!(client.id(), ClientId(0));
+
+        // Taking over the slot did not free the borrowed buffer for the new client
+        match mode {
+            ChannelMode::Mailbox => assert!(matches!(client.try_send(b"XXXXX"), Err(Error::WouldBlock))),
+            ChannelMode::Ring => client.try_send(b"XXXXX").unwrap(),
+        }
+        assert_eq!(&*frame, b"frame");
+        drop(frame);
+
+        if mode == ChannelMode::Mailbox {
+            client.send(b"XXXXX").unwrap();
+        }
+        assert_eq!(server.recv_bytes(Some(100)).unwrap(), Some(b"XXXXX".to_vec()));
+    }
+}
+
+#[test]
+fn test_receive_borrowed_close_and_fragments() {
+    let (mut server, mut client) = heap_pair(64, ChannelMode::Mailbox);
+
+    let message: Vec<u8> = (0..200).map(|i| i as u8).collect();
+    let sender = std::thread::spawn({
+        let message = message.clone();
+        move || {
+            server.set_fragmentation(true);
+            server.send(&message).unwrap();
+            server
+        }
+    });
+
+    // Fragmented messages are copied out
+    assert_eq!(&*client.receive_borrowed(Some(1000)).unwrap().unwrap(), &message[..]);
+    let server = sender.join().unwrap();
+    server.send_close().unwrap();
+    assert!(matches!(client.receive_borrowed(Some(1000)), Err(Error::PeerClosed)));
+}
+
+#[test]
+fn test_receive_borrowed_ring() {
+    let (server, mut client) = heap_pair(32, ChannelMode::Ring);
+
+    server.send(&[1; 10]).unwrap();
+    server.send(&[2; 10]).unwrap();
+    let first = client.receive_borrowed(Some(100)).unwrap().unwrap();
+    assert_eq!(&*first, &[1; 10]);
+    // The record's space is only released on drop
+    assert!(matches!(server.try_send(&[3; 10]), Err(Error::WouldBlock)));
+    drop(first);
+    server.try_send(&[3; 10]).unwrap();
+
+    // Later records wrap around the end of the ring and are copied out
+    for round in 2..20u8 {
+        assert_eq!(&*client.receive_borrowed(Some(100)).unwrap().unwrap(), &[round; 10]);
+        server.send(&[round + 2; 10]).unwrap();
+    }
+}
+
+#[test]
+fn test_receive_into_reuses_buffer() {
+    let (server, client) = heap_pair(64, ChannelMode::Mailbox);
+
+    let mut buffer = Vec::with_capacity(64);
+    let capacity = buffer.capacity();
+    server.send(b"first message").unwrap();
+    assert_eq!(client.receive_into(&mut buffer, Some(100)).unwrap(), Some(13));
+    assert_eq!(buffer, b"first message");
+
+    server.send(b"second").unwrap();
+    assert_eq!(client.receive_into(&mut buffer, Some(100)).unwrap(), Some(6));
+    assert_eq!(buffer, b"second");
+    assert_eq!(buffer.capacity(), capacity);
+
+    assert_eq!(client.receive_into(&mut buffer, Some(10)).unwrap(), None);
+    client.send(b"reply").unwrap();
+    assert_eq!(server.receive_into(&mut buffer, Some(100)).unwrap(), Some(5));
+    assert_eq!(buffer, b"reply");
+}
+
+#[test]
+fn test_receive_into_slice() {
+    let (server, client) = heap_pair(64, ChannelMode::Ring);
+
+    let mut buffer = [0u8; 8];
+    client.send(b"fits").unwrap();
+    client.send(b"does not fit").unwrap();
+    client.send(b"next").unwrap();
+
+    assert_eq!(server.receive_into_slice(&mut buffer, Some(100)).unwrap(), Some(4));
+    assert_eq!(&buffer[..4], b"fits");
+    assert!(matches!(
+        server.receive_into_slice(&mut buffer, Some(100)),
+        Err(Error::MessageTooLarge { len: 12, capacity: 8 })
+    ));
+    // The oversized message is left unread for a retry with a larger buffer
+    let mut larger = [0u8; 16];
+    assert_eq!(server.receive_into_slice(&mut larger, Some(100)).unwrap(), Some(12));
+    assert_eq!(&larger[..12], b"does not fit");
+    assert_eq!(server.receive_into_slice(&mut buffer, Some(100)).unwrap(), Some(4));
+    assert_eq!(&buffer[..4], b"next");
+    assert_eq!(server.receive_into_slice(&mut buffer, Some(10)).unwrap(), None);
+
+    server.send_close().unwrap();
+    assert!(matches!(client.receive_into_slice(&mut buffer, Some(100)), Err(Error::PeerClosed)));
+}
+
+#[test]
+fn test_receive_into_slice_leaves_large_messages_unread() {
+    for mode in [ChannelMode::Mailbox, ChannelMode::Ring] {
+        let (mut server, client) = heap_pair(64, mode);
+        server.set_fragmentation(true);
+
+        let message: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        let sender = std::thread::spawn({
+            let message = message.clone();
+            move || {
+                server.send(b"does not fit").unwrap();
+                server.send(&message).unwrap();
+                server
+            }
+        });
+
+        let mut small = [0u8; 8];
+        let mut large = [0u8; 256];
+        assert!(matches!(
+            client.receive_into_slice(&mut small, Some(1000)),
+            Err(Error::MessageTooLarge { len: 12, capacity: 8 })
+        ));
+        assert_eq!(client.receive_into_slice(&mut large, Some(1000)).unwrap(), Some(12));
+        // The length of a fragmented message is known before any fragment is consumed
+        assert!(matches!(
+            client.receive_into_slice(&mut small, Some(1000)),
+            Err(Error::MessageTooLarge { len: 200, capacity: 8 })
+        ));
+        assert_eq!(client.receive_into_slice(&mut large, Some(1000)).unwrap(), Some(200));
+        assert_eq!(&large[..200], &message[..]);
+        sender.join().unwrap();
+    }
+}
+
+#[test]
+fn test_send_vectored() {
+    let (server, client) = heap_pair(64, ChannelMode::Mailbox);
+
+    let header = 7u32.to_le_bytes();
+    server.send_vectored(&[IoSlice::new(&header), IoSlice::new(b""), IoSlice::new(b"payload")]).unwrap();
+    let mut expected = header.to_vec();
+    expected.extend_from_slice(b"payload");
+    assert_eq!(client.recv_bytes(Some(100)).unwrap(), Some(expected));
+
+    client.send_vectored(&[IoSlice::new(b"ab"), IoSlice::new(b"cd")]).unwrap();
+    assert_eq!(server.recv_bytes(Some(100)).unwrap(), Some(b"abcd".to_vec()));
+
+    // The total length is checked like send
+    let half = [0u8; 40];
+    assert!(matches!(
+        server.send_vectored(&[IoSlice::new(&half), IoSlice::new(&half)]),
+        Err(Error::MessageTooLarge { len: 80, capacity: 64 })
+    ));
+    assert_eq!(client.try_recv_bytes().unwrap(), None);
+}
+
+#[test]
+fn test_send_vectored_ring() {
+    let (server, client) = heap_pair(64, ChannelMode::Ring);
+
+    // Enough messages to wrap the pieces around the end of the ring
+    for round in 0..8u8 {
+        let (header, payload) = ([round; 5], [round + 100; 9]);
+        let parts = [IoSlice::new(&header), IoSlice::new(&payload)];
+        server.send_vectored(&parts).unwrap();
+        server.send_vectored(&parts).unwrap();
+
+        let mut expected = vec![round; 5];
+        expected.extend_from_slice(&[round + 100; 9]);
+        assert_eq!(client.recv_bytes(Some(100)).unwrap(), Some(expected.clone()));
+        assert_eq!(client.recv_bytes(Some(100)).unwrap(), Some(expected));
+    }
+}
+
+#[test]
+fn test_send_vectored_fragmented() {
+    let (mut server, client) = heap_pair(64, ChannelMode::Mailbox);
+    server.set_fragmentation(true);
+
+    let header = [1u8; 16];
+    let payload = vec![2u8; 300];
+    let sender =
+        std::thread::spawn(move || server.send_vectored(&[IoSlice::new(&header), IoSlice::new(&payload)]));
+
+    let mut expected = vec![1u8; 16];
+    expected.extend_from_slice(&[2u8; 300]);
+    assert_eq!(client.recv_bytes(Some(1000)).unwrap(), Some(expected));
+    sender.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_receive_into_slice_drops_stray_fragments() {
+    let buffer_size = 32;
+    let transport = HeapTransport::new(SharedDataHeader::total_size(buffer_size));
+    let server = Server::with_transport(transport.clone(), buffer_size).unwrap();
+    let mut client = Client::with_transport(transport.clone()).unwrap();
+    client.set_fragmentation(true);
+
+    // The second fragment of a message whose receive was cut short earlier
+    let offset = SharedDataHeader::offset_c2s(BufferSizes::symmetric(buffer_size), 1, 0);
+    unsafe {
+        let data = std::slice::from_raw_parts_mut(transport.as_ptr().add(offset), buffer_size);
+        data[..4].copy_from_slice(&100u32.to_ne_bytes());
+        data[4..8].copy_from_slice(&24u32.to_ne_bytes());
+        let slot = &mut *(transport.as_ptr().add(SharedDataHeader::slot_offset(0)) as *mut ClientSlot);
+        slot.data_len_client_to_server = 20;
+        slot.flag_client.store(4, Ordering::Release);
+    }
+
+    let message = vec![7u8; 60];
+    let sender = std::thread::spawn({
+        let message = message.clone();
+        move || client.send(&message).unwrap()
+    });
+
+    let mut small = [0u8; 10];
+    assert!(matches!(
+        server.receive_into_slice(&mut small, Some(1000)),
+        Err(Error::MessageTooLarge { len: 60, capacity: 10 })
+    ));
+    let mut buffer = Vec::new();
+    assert_eq!(server.receive_into(&mut buffer, Some(1000)).unwrap(), Some(60));
+    assert_eq!(buffer, message);
+    sender.join().unwrap();
+}